@@ -0,0 +1,263 @@
+//! Actions that can be bound to power key presses
+//!
+//! An action is either handled by the daemon itself (toggling power-saving mode,
+//! emitting a key on the virtual device) or delegated to the system (poweroff,
+//! reboot, suspend, or an arbitrary shell command).
+
+use std::fmt;
+use std::process::Command;
+use std::str::FromStr;
+
+use log::{debug, info};
+
+use crate::error::Error;
+use crate::input::{key_name, keycode_from_name};
+
+/// Something the daemon can do in response to a key press
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    /// Do nothing (just log the press)
+    None,
+    /// Toggle between normal and power-saving mode
+    ToggleSaving,
+    /// Enter power-saving mode (no-op if already saving)
+    EnterSaving,
+    /// Leave power-saving mode (no-op if already normal)
+    ExitSaving,
+    /// Flip the WiFi rfkill block
+    ToggleWifi,
+    /// Flip the Bluetooth rfkill block
+    ToggleBt,
+    /// Clean poweroff via systemd
+    Poweroff,
+    /// Clean reboot via systemd
+    Reboot,
+    /// Suspend via systemd
+    Suspend,
+    /// Suspend by the daemon itself through `/sys/power/state`, with the power key
+    /// as wakeup source
+    Sleep,
+    /// Run an arbitrary command through `sh -c`
+    Command(String),
+    /// Press and release a key on the uinput virtual device
+    EmitKey(u16),
+    /// Switch to a named power profile (`normal` leaves saving mode)
+    Profile(String),
+    /// Switch to the next profile in `PROFILE_CYCLE`
+    CycleProfile,
+}
+
+impl FromStr for Action {
+    type Err = Error;
+
+    /// Parse an action name. Accepted values: `none`, `toggle`, `enter_saving`,
+    /// `exit_saving`, `toggle_wifi`, `toggle_bt`, `poweroff` (alias `shutdown`),
+    /// `reboot`, `suspend`, `sleep`, `cycle_profile`, `cmd:<command line>`, `key:<KEY_NAME>` and
+    /// `profile:<name>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(cmd) = s.strip_prefix("cmd:") {
+            let cmd = cmd.trim();
+            if cmd.is_empty() {
+                return Err(Error::InvalidValue("empty command action".to_string()));
+            }
+            return Ok(Action::Command(cmd.to_string()));
+        }
+        if let Some(key) = s.strip_prefix("key:") {
+            return keycode_from_name(key)
+                .map(Action::EmitKey)
+                .ok_or_else(|| Error::InvalidValue(format!("unknown key '{}'", key.trim())));
+        }
+        if let Some(name) = s.strip_prefix("profile:") {
+            let name = name.trim().to_ascii_lowercase();
+            if name.is_empty() {
+                return Err(Error::InvalidValue("empty profile name".to_string()));
+            }
+            return Ok(Action::Profile(name));
+        }
+        match s.to_ascii_lowercase().as_str() {
+            "" | "none" => Ok(Action::None),
+            "toggle" => Ok(Action::ToggleSaving),
+            "enter_saving" => Ok(Action::EnterSaving),
+            "exit_saving" => Ok(Action::ExitSaving),
+            "toggle_wifi" => Ok(Action::ToggleWifi),
+            "toggle_bt" => Ok(Action::ToggleBt),
+            "poweroff" | "shutdown" => Ok(Action::Poweroff),
+            "reboot" => Ok(Action::Reboot),
+            "suspend" => Ok(Action::Suspend),
+            "sleep" => Ok(Action::Sleep),
+            "cycle_profile" => Ok(Action::CycleProfile),
+            other => Err(Error::InvalidValue(format!("unknown action '{}'", other))),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::None => write!(f, "none"),
+            Action::ToggleSaving => write!(f, "toggle"),
+            Action::EnterSaving => write!(f, "enter_saving"),
+            Action::ExitSaving => write!(f, "exit_saving"),
+            Action::ToggleWifi => write!(f, "toggle_wifi"),
+            Action::ToggleBt => write!(f, "toggle_bt"),
+            Action::Poweroff => write!(f, "poweroff"),
+            Action::Reboot => write!(f, "reboot"),
+            Action::Suspend => write!(f, "suspend"),
+            Action::Sleep => write!(f, "sleep"),
+            Action::Command(cmd) => write!(f, "cmd:{}", cmd),
+            Action::EmitKey(code) => match key_name(*code) {
+                Some(name) => write!(f, "key:{}", name),
+                None => write!(f, "key:{}", code),
+            },
+            Action::Profile(name) => write!(f, "profile:{}", name),
+            Action::CycleProfile => write!(f, "cycle_profile"),
+        }
+    }
+}
+
+impl Action {
+    /// Program and arguments used to carry out a system action.
+    ///
+    /// Returns `None` for actions the daemon handles internally (mode and RF changes,
+    /// key emission, sleep).
+    pub fn command_line(&self) -> Option<(&str, Vec<&str>)> {
+        match self {
+            Action::None
+            | Action::ToggleSaving
+            | Action::EnterSaving
+            | Action::ExitSaving
+            | Action::ToggleWifi
+            | Action::ToggleBt
+            | Action::EmitKey(_)
+            | Action::Sleep
+            | Action::Profile(_)
+            | Action::CycleProfile => None,
+            Action::Poweroff => Some(("systemctl", vec!["poweroff"])),
+            Action::Reboot => Some(("systemctl", vec!["reboot"])),
+            Action::Suspend => Some(("systemctl", vec!["suspend"])),
+            Action::Command(cmd) => Some(("sh", vec!["-c", cmd.as_str()])),
+        }
+    }
+
+    /// Run a system action, waiting for the command to finish.
+    ///
+    /// Internal actions are a no-op here; the caller is expected to handle them.
+    pub fn execute(&self, dry_run: bool) -> Result<(), Error> {
+        let Some((program, args)) = self.command_line() else {
+            return Ok(());
+        };
+        if dry_run {
+            debug!("DRY-RUN: would run {} {}", program, args.join(" "));
+            return Ok(());
+        }
+        info!("Running action '{}'", self);
+        let status = Command::new(program)
+            .args(&args)
+            .status()
+            .map_err(|e| Error::Io(format!("failed to run {}: {}", program, e)))?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Error::Io(format!(
+                "action '{}' exited with {}",
+                self, status
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_parse_known_actions() {
+        assert_eq!("none".parse::<Action>().unwrap(), Action::None);
+        assert_eq!("toggle".parse::<Action>().unwrap(), Action::ToggleSaving);
+        assert_eq!("Poweroff".parse::<Action>().unwrap(), Action::Poweroff);
+        assert_eq!("shutdown".parse::<Action>().unwrap(), Action::Poweroff);
+        assert_eq!("reboot".parse::<Action>().unwrap(), Action::Reboot);
+        assert_eq!(" suspend ".parse::<Action>().unwrap(), Action::Suspend);
+        assert_eq!("toggle_wifi".parse::<Action>().unwrap(), Action::ToggleWifi);
+        for a in [
+            Action::EnterSaving,
+            Action::ExitSaving,
+            Action::ToggleWifi,
+            Action::ToggleBt,
+            Action::Sleep,
+        ] {
+            assert_eq!(a.to_string().parse::<Action>().unwrap(), a);
+            assert_eq!(a.command_line(), None);
+        }
+    }
+
+    #[test]
+    fn test_parse_command_action_keeps_case() {
+        let a = "cmd: loginctl lock-session Foo".parse::<Action>().unwrap();
+        assert_eq!(a, Action::Command("loginctl lock-session Foo".to_string()));
+        assert_eq!(a.to_string(), "cmd:loginctl lock-session Foo");
+    }
+
+    #[test]
+    fn test_parse_emit_key() {
+        let a = "key:KEY_POWER".parse::<Action>().unwrap();
+        assert_eq!(a, Action::EmitKey(116));
+        assert_eq!(a.to_string(), "key:KEY_POWER");
+        assert_eq!(a.command_line(), None);
+        assert_eq!("key:sleep".parse::<Action>().unwrap(), Action::EmitKey(142));
+        assert!("key:KEY_NOPE".parse::<Action>().is_err());
+    }
+
+    #[test]
+    fn test_parse_profile_actions() {
+        let a = "profile: Deep".parse::<Action>().unwrap();
+        assert_eq!(a, Action::Profile("deep".to_string()));
+        assert_eq!(a.to_string(), "profile:deep");
+        assert_eq!(a.command_line(), None);
+        assert_eq!(
+            "cycle_profile".parse::<Action>().unwrap(),
+            Action::CycleProfile
+        );
+        assert!("profile:".parse::<Action>().is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_and_empty_command() {
+        assert!("hibernate-now".parse::<Action>().is_err());
+        assert!("cmd:".parse::<Action>().is_err());
+    }
+
+    #[test]
+    fn test_command_line_mapping() {
+        assert_eq!(Action::None.command_line(), None);
+        assert_eq!(Action::ToggleSaving.command_line(), None);
+        assert_eq!(
+            Action::Poweroff.command_line(),
+            Some(("systemctl", vec!["poweroff"]))
+        );
+        assert_eq!(
+            Action::Command("echo hi".to_string()).command_line(),
+            Some(("sh", vec!["-c", "echo hi"]))
+        );
+    }
+
+    #[test]
+    fn test_execute_command_and_dry_run() {
+        let dir = TempDir::new("action");
+        let tmp = dir.join("touched");
+        let action = Action::Command(format!("touch {}", tmp.display()));
+        // dry-run must not run the command
+        action.execute(true).unwrap();
+        assert!(!tmp.exists());
+        action.execute(false).unwrap();
+        assert!(tmp.exists());
+
+        assert!(
+            Action::Command("exit 3".to_string())
+                .execute(false)
+                .is_err()
+        );
+    }
+}
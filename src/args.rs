@@ -1,14 +1,45 @@
 use std::path::PathBuf;
 
+use crate::action::Action;
+use crate::hardware::power_key::PowerKeySelector;
+use crate::input::keycode_from_name;
+
+/// Options parsed from the command line. `None` means "not given", so the config
+/// file value applies.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CliArgs {
+    pub dry_run: bool,
+    pub verbosity: u8,
+    pub toggle_wifi: Option<bool>,
+    pub toggle_bt: Option<bool>,
+    pub config_path: Option<PathBuf>,
+    pub long_press_action: Option<Action>,
+    pub power_key_device: Option<PowerKeySelector>,
+    pub power_key_code: Option<u16>,
+    /// Power profile to switch to at startup
+    pub profile: Option<String>,
+}
+
+/// Take the value of `--flag VALUE` or `--flag=VALUE`
+fn flag_value<I: Iterator<Item = String>>(flag: &str, arg: &str, iter: &mut I) -> Option<String> {
+    if arg == flag {
+        iter.next()
+    } else {
+        arg.find('=').map(|eq| arg[eq + 1..].to_string())
+    }
+}
+
 /// Parse CLI args for a minimal set: --dry-run, --toggle-wifi, --config <path>
-fn parse_cli_args_from<I: IntoIterator<Item = String>>(
-    args: I,
-) -> (bool, u8, Option<bool>, Option<bool>, Option<PathBuf>) {
+fn parse_cli_args_from<I: IntoIterator<Item = String>>(args: I) -> CliArgs {
     let mut dry_run = false;
     let mut verbosity: u8 = 0;
     let mut config_path: Option<PathBuf> = None;
     let mut toggle_wifi: Option<bool> = None;
     let mut toggle_bt: Option<bool> = None;
+    let mut long_press_action: Option<Action> = None;
+    let mut power_key_device: Option<PowerKeySelector> = None;
+    let mut power_key_code: Option<u16> = None;
+    let mut profile: Option<String> = None;
     let mut iter = args.into_iter();
     while let Some(a) = iter.next() {
         match a.as_str() {
@@ -50,10 +81,52 @@ fn parse_cli_args_from<I: IntoIterator<Item = String>>(
                     }
                 }
             }
+            s if s.starts_with("--long-press-action") => {
+                if let Some(v) = flag_value("--long-press-action", s, &mut iter) {
+                    match v.parse::<Action>() {
+                        Ok(a) => long_press_action = Some(a),
+                        Err(e) => eprintln!("Ignoring --long-press-action: {}", e),
+                    }
+                }
+            }
+            s if s.starts_with("--power-key-device") => {
+                if let Some(v) = flag_value("--power-key-device", s, &mut iter) {
+                    match v.parse::<PowerKeySelector>() {
+                        Ok(sel) => power_key_device = Some(sel),
+                        Err(e) => eprintln!("Ignoring --power-key-device: {}", e),
+                    }
+                }
+            }
+            s if s.starts_with("--power-key-code") => {
+                if let Some(v) = flag_value("--power-key-code", s, &mut iter) {
+                    match keycode_from_name(&v) {
+                        Some(code) => power_key_code = Some(code),
+                        None => eprintln!("Ignoring --power-key-code: unknown key '{}'", v),
+                    }
+                }
+            }
+            s if s.starts_with("--profile") => {
+                if let Some(v) = flag_value("--profile", s, &mut iter) {
+                    let name = v.trim().to_ascii_lowercase();
+                    if !name.is_empty() {
+                        profile = Some(name);
+                    }
+                }
+            }
             _ => {}
         }
     }
-    (dry_run, verbosity, toggle_wifi, toggle_bt, config_path)
+    CliArgs {
+        dry_run,
+        verbosity,
+        toggle_wifi,
+        toggle_bt,
+        config_path,
+        long_press_action,
+        power_key_device,
+        power_key_code,
+        profile,
+    }
 }
 
 fn print_help() {
@@ -64,6 +137,14 @@ fn print_help() {
     println!("  --config[=PATH]      Load configuration from PATH");
     println!("  --toggle-wifi[=VAL]  Toggle WiFi; VAL can be true/false/1/0/yes/no");
     println!("  --toggle-bt[=VAL]    Toggle Bluetooth; VAL can be true/false/1/0/yes/no");
+    println!("  --long-press-action=ACTION");
+    println!("                       Action on long press: none, toggle, poweroff, reboot,");
+    println!("                       suspend, sleep, cycle_profile, cmd:<command>, key:<KEY>");
+    println!("                       or profile:<name>");
+    println!("  --power-key-device=SEL");
+    println!("                       Power key device: PATH, name:STR, phys:STR or keycode:KEY");
+    println!("  --power-key-code=KEY Key that triggers gestures (e.g. KEY_POWER, KEY_SLEEP)");
+    println!("  --profile=NAME       Power profile to start in (see PROFILE_* in the config)");
     println!("  --dry-run            Don't actually perform changes; just log actions");
     println!();
     println!("  -v, -vv, -vvv        Increase verbosity (max 3)");
@@ -71,7 +152,7 @@ fn print_help() {
     println!("  -h, --help           Print this help message and exit");
 }
 
-pub fn parse_cli_args() -> (bool, u8, Option<bool>, Option<bool>, Option<PathBuf>) {
+pub fn parse_cli_args() -> CliArgs {
     // If the user asked for help, print and exit; keep parse_cli_args_from unchanged so unit
     // tests that call it directly are unaffected.
     for a in std::env::args() {
@@ -89,17 +170,11 @@ mod tests {
     use crate::Config;
 
     use super::*;
+    use crate::test_util::TempDir;
 
     #[test]
     fn test_parse_cli_args_from_flags() {
-        let tmp = std::env::temp_dir().join(format!(
-            "uconsole_cli_cfg_{}",
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis()
-        ));
-        let _ = std::fs::create_dir_all(&tmp);
+        let tmp = TempDir::new("cli_cfg");
         let cfg_path = tmp.join("cli_cfg");
         std::fs::write(&cfg_path, "SAVING_CPU_FREQ=55,66\nHOLD_TRIGGER_SEC=1.4\n").unwrap();
 
@@ -109,14 +184,13 @@ mod tests {
             String::from("--config"),
             cfg_path.to_string_lossy().to_string(),
         ];
-        let (dry_run, verbosity, _toggle_wifi, _toggle_bt, cli_config_path) =
-            parse_cli_args_from(args);
-        assert!(dry_run);
-        assert_eq!(verbosity, 0);
-        assert_eq!(cli_config_path, Some(cfg_path.clone()));
+        let cli = parse_cli_args_from(args);
+        assert!(cli.dry_run);
+        assert_eq!(cli.verbosity, 0);
+        assert_eq!(cli.config_path, Some(cfg_path.clone()));
 
         // ensure the Config::load uses this file when provided
-        let loaded = Config::load(cli_config_path.clone());
+        let loaded = Config::load(cli.config_path.clone());
         assert_eq!(loaded.saving_cpu_freq.unwrap(), "55,66");
         assert_eq!(loaded.hold_trigger_sec.unwrap(), 1.4_f32);
         // no-op; this used to check default examples
@@ -124,14 +198,7 @@ mod tests {
 
     #[test]
     fn test_parse_cli_args_from_flags_eq_form() {
-        let tmp = std::env::temp_dir().join(format!(
-            "uconsole_cli_cfg_{}",
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis()
-        ));
-        let _ = std::fs::create_dir_all(&tmp);
+        let tmp = TempDir::new("cli_cfg");
         let cfg_path = tmp.join("cli_cfg2");
         std::fs::write(&cfg_path, "SAVING_CPU_FREQ=22,33\nHOLD_TRIGGER_SEC=2.1\n").unwrap();
 
@@ -140,12 +207,11 @@ mod tests {
             String::from("--dry-run"),
             format!("--config={}", cfg_path.to_string_lossy()),
         ];
-        let (dry_run, verbosity, _toggle_wifi, _toggle_bt, cli_config_path) =
-            parse_cli_args_from(args);
-        assert!(dry_run);
-        assert_eq!(verbosity, 0);
-        assert_eq!(cli_config_path, Some(cfg_path.clone()));
-        let loaded = Config::load(cli_config_path.clone());
+        let cli = parse_cli_args_from(args);
+        assert!(cli.dry_run);
+        assert_eq!(cli.verbosity, 0);
+        assert_eq!(cli.config_path, Some(cfg_path.clone()));
+        let loaded = Config::load(cli.config_path.clone());
         assert_eq!(loaded.saving_cpu_freq.unwrap(), "22,33");
         assert_eq!(loaded.hold_trigger_sec.unwrap(), 2.1_f32);
     }
@@ -153,14 +219,22 @@ mod tests {
     #[test]
     fn test_toggle_wifi_cli_precedence_over_config() {
         use crate::Config;
+        use crate::hardware::rf::{RfSetting, RfType};
         let cfg = Config {
-            toggle_wifi: true,
+            rf: [(
+                RfType::Wlan,
+                RfSetting {
+                    toggle: true,
+                    ..Default::default()
+                },
+            )]
+            .into(),
             ..Default::default()
         };
         let toggle_wifi_flag = Some(false);
         let final_toggle_wifi = match toggle_wifi_flag {
             Some(v) => v,
-            None => cfg.toggle_wifi,
+            None => cfg.rf_setting(RfType::Wlan).toggle,
         };
         assert!(!final_toggle_wifi);
     }
@@ -168,25 +242,22 @@ mod tests {
     #[test]
     fn test_parse_cli_args_verbosity_v() {
         let args = vec![String::from("prog"), String::from("-v")];
-        let (_dry_run, verbosity, _toggle_wifi, _toggle_bt, _cli_config_path) =
-            parse_cli_args_from(args);
-        assert_eq!(verbosity, 1);
+        let cli = parse_cli_args_from(args);
+        assert_eq!(cli.verbosity, 1);
     }
 
     #[test]
     fn test_parse_cli_args_verbosity_vv() {
         let args = vec![String::from("prog"), String::from("-vv")];
-        let (_dry_run, verbosity, _toggle_wifi, _toggle_bt, _cli_config_path) =
-            parse_cli_args_from(args);
-        assert_eq!(verbosity, 2);
+        let cli = parse_cli_args_from(args);
+        assert_eq!(cli.verbosity, 2);
     }
 
     #[test]
     fn test_parse_cli_args_verbosity_vvv() {
         let args = vec![String::from("prog"), String::from("-vvv")];
-        let (_dry_run, verbosity, _toggle_wifi, _toggle_bt, _cli_config_path) =
-            parse_cli_args_from(args);
-        assert_eq!(verbosity, 3);
+        let cli = parse_cli_args_from(args);
+        assert_eq!(cli.verbosity, 3);
     }
 
     /// `--toggle-wifi` with `=VALUE` must normalize false-y values to false.
@@ -194,9 +265,9 @@ mod tests {
     fn test_toggle_wifi_eq_false_variants() {
         for val in ["false", "0", "no"] {
             let args = vec![String::from("prog"), format!("--toggle-wifi={val}")];
-            let (_dry, _verb, toggle_wifi, _toggle_bt, _cfg) = parse_cli_args_from(args);
+            let cli = parse_cli_args_from(args);
             assert_eq!(
-                toggle_wifi,
+                cli.toggle_wifi,
                 Some(false),
                 "value '{val}' should parse to false"
             );
@@ -208,8 +279,12 @@ mod tests {
     fn test_toggle_bt_eq_true_variants() {
         for val in ["true", "1", "yes"] {
             let args = vec![String::from("prog"), format!("--toggle-bt={val}")];
-            let (_dry, _verb, _toggle_wifi, toggle_bt, _cfg) = parse_cli_args_from(args);
-            assert_eq!(toggle_bt, Some(true), "value '{val}' should parse to true");
+            let cli = parse_cli_args_from(args);
+            assert_eq!(
+                cli.toggle_bt,
+                Some(true),
+                "value '{val}' should parse to true"
+            );
         }
     }
 
@@ -217,9 +292,52 @@ mod tests {
     #[test]
     fn test_toggle_flags_absent_yield_none() {
         let args = vec![String::from("prog")];
-        let (_dry, _verb, toggle_wifi, toggle_bt, _cfg) = parse_cli_args_from(args);
-        assert_eq!(toggle_wifi, None);
-        assert_eq!(toggle_bt, None);
+        let cli = parse_cli_args_from(args);
+        assert_eq!(cli.toggle_wifi, None);
+        assert_eq!(cli.toggle_bt, None);
+        assert_eq!(cli.long_press_action, None);
+    }
+
+    #[test]
+    fn test_power_key_device_and_code() {
+        let args = vec![
+            String::from("prog"),
+            String::from("--power-key-device"),
+            String::from("phys:gpio-keys/input0"),
+            String::from("--power-key-code=KEY_SLEEP"),
+        ];
+        let cli = parse_cli_args_from(args);
+        assert_eq!(
+            cli.power_key_device,
+            Some(PowerKeySelector::Phys("gpio-keys/input0".to_string()))
+        );
+        assert_eq!(cli.power_key_code, Some(142));
+        assert_eq!(cli.profile, None);
+
+        let args = vec![String::from("prog"), String::from("--profile=Deep")];
+        assert_eq!(parse_cli_args_from(args).profile.as_deref(), Some("deep"));
+    }
+
+    /// `--long-press-action` accepts both the separate and the `=VALUE` form.
+    #[test]
+    fn test_long_press_action_forms() {
+        let args = vec![
+            String::from("prog"),
+            String::from("--long-press-action"),
+            String::from("poweroff"),
+        ];
+        assert_eq!(
+            parse_cli_args_from(args).long_press_action,
+            Some(Action::Poweroff)
+        );
+        let args = vec![
+            String::from("prog"),
+            String::from("--long-press-action=cmd:echo bye"),
+        ];
+        assert_eq!(
+            parse_cli_args_from(args).long_press_action,
+            Some(Action::Command("echo bye".to_string()))
+        );
     }
 
     /// The final dry-run decision is `cli_dry_run || cfg.dry_run`: either source
@@ -0,0 +1,209 @@
+//! Hotkey chords: combinations of keys, possibly spread over several devices, that
+//! trigger an action once all of them are held down.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::action::Action;
+use crate::error::Error;
+use crate::input::{EV_KEY, KeyState, key_name, keycode_from_name};
+
+/// A set of keys that must all be held at once
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chord {
+    /// Key codes, sorted and without duplicates
+    pub keys: Vec<u16>,
+}
+
+impl Chord {
+    pub fn contains(&self, code: u16) -> bool {
+        self.keys.contains(&code)
+    }
+
+    fn is_held(&self, state: &KeyState) -> bool {
+        self.keys.iter().all(|k| state.is_pressed(*k))
+    }
+}
+
+impl FromStr for Chord {
+    type Err = Error;
+
+    /// Parse `KEY_LEFTCTRL+KEY_LEFTALT+KEY_POWER` (names as accepted by
+    /// [`keycode_from_name`]); at least two distinct keys are required.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut keys = Vec::new();
+        for part in s.split('+') {
+            let code = keycode_from_name(part)
+                .ok_or_else(|| Error::InvalidValue(format!("unknown key '{}'", part.trim())))?;
+            keys.push(code);
+        }
+        keys.sort_unstable();
+        keys.dedup();
+        if keys.len() < 2 {
+            return Err(Error::InvalidValue(format!(
+                "chord '{}' needs at least two keys",
+                s
+            )));
+        }
+        Ok(Chord { keys })
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<String> = self
+            .keys
+            .iter()
+            .map(|k| key_name(*k).map(str::to_string).unwrap_or(k.to_string()))
+            .collect();
+        write!(f, "{}", names.join("+"))
+    }
+}
+
+/// Chord to action table plus the state needed to fire each chord once per press
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChordBindings {
+    chords: Vec<(Chord, Action)>,
+    /// Parallel to `chords`: the chord fired and not all of its keys were released yet
+    active: Vec<bool>,
+}
+
+impl ChordBindings {
+    pub fn bind(&mut self, chord: Chord, action: Action) {
+        if let Some(i) = self.chords.iter().position(|(c, _)| *c == chord) {
+            self.chords[i].1 = action;
+        } else {
+            self.chords.push((chord, action));
+            self.active.push(false);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chords.is_empty()
+    }
+
+    pub fn actions(&self) -> impl Iterator<Item = &Action> {
+        self.chords.iter().map(|(_, a)| a)
+    }
+
+    /// Parse a `CHORD_<NAME>` config value of the form `<keys>:<action>`
+    pub fn parse_value(val: &str) -> Result<(Chord, Action), Error> {
+        let (keys, action) = val.split_once(':').ok_or_else(|| {
+            Error::InvalidValue(format!("expected <keys>:<action>, got '{}'", val))
+        })?;
+        Ok((keys.parse()?, action.parse()?))
+    }
+
+    /// Feed a key event after `state` has been updated with it. Returns the chord and
+    /// action if this key-down completed a chord; the largest matching chord wins.
+    pub fn on_event(
+        &mut self,
+        state: &KeyState,
+        etype: u16,
+        code: u16,
+        value: i32,
+    ) -> Option<(Chord, Action)> {
+        if etype != EV_KEY {
+            return None;
+        }
+        match value {
+            1 => {
+                let best = self
+                    .chords
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, (c, _))| !self.active[*i] && c.contains(code) && c.is_held(state))
+                    .max_by_key(|(_, (c, _))| c.keys.len())
+                    .map(|(i, _)| i)?;
+                self.active[best] = true;
+                Some(self.chords[best].clone())
+            }
+            0 => {
+                for (i, (c, _)) in self.chords.iter().enumerate() {
+                    if c.contains(code) {
+                        self.active[i] = false;
+                    }
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CTRL: u16 = 29;
+    const ALT: u16 = 56;
+    const POWER: u16 = 116;
+    const FN: u16 = 464;
+    const F1: u16 = 59;
+
+    fn feed(
+        b: &mut ChordBindings,
+        ks: &mut KeyState,
+        code: u16,
+        value: i32,
+    ) -> Option<(Chord, Action)> {
+        ks.update(0, EV_KEY, code, value);
+        b.on_event(ks, EV_KEY, code, value)
+    }
+
+    #[test]
+    fn test_parse_chord() {
+        let c: Chord = "KEY_LEFTCTRL+KEY_LEFTALT+KEY_POWER".parse().unwrap();
+        assert_eq!(c.keys, vec![CTRL, ALT, POWER]);
+        assert_eq!(c.to_string(), "KEY_LEFTCTRL+KEY_LEFTALT+KEY_POWER");
+        assert!("KEY_POWER".parse::<Chord>().is_err());
+        assert!("KEY_POWER+KEY_POWER".parse::<Chord>().is_err());
+        assert!("KEY_POWER+KEY_BOGUS".parse::<Chord>().is_err());
+    }
+
+    #[test]
+    fn test_parse_value() {
+        let (c, a) = ChordBindings::parse_value("fn+f1:toggle_wifi").unwrap();
+        assert_eq!(c.keys, vec![F1, FN]);
+        assert_eq!(a, Action::ToggleWifi);
+        let (_, a) = ChordBindings::parse_value("KEY_FN+KEY_F2:cmd:echo a:b").unwrap();
+        assert_eq!(a, Action::Command("echo a:b".to_string()));
+        assert!(ChordBindings::parse_value("KEY_FN+KEY_F2").is_err());
+    }
+
+    #[test]
+    fn test_chord_fires_once_in_any_order() {
+        let mut b = ChordBindings::default();
+        b.bind("KEY_FN+KEY_F1".parse().unwrap(), Action::ToggleWifi);
+        let mut ks = KeyState::default();
+
+        assert_eq!(feed(&mut b, &mut ks, F1, 1), None);
+        let (c, a) = feed(&mut b, &mut ks, FN, 1).unwrap();
+        assert_eq!(c.keys, vec![F1, FN]);
+        assert_eq!(a, Action::ToggleWifi);
+        // auto-repeat and re-pressing while still active don't fire again
+        assert_eq!(feed(&mut b, &mut ks, FN, 2), None);
+        assert_eq!(feed(&mut b, &mut ks, F1, 0), None);
+        assert_eq!(
+            feed(&mut b, &mut ks, F1, 1).map(|(_, a)| a),
+            Some(Action::ToggleWifi)
+        );
+    }
+
+    #[test]
+    fn test_largest_chord_wins() {
+        let mut b = ChordBindings::default();
+        b.bind("KEY_LEFTALT+KEY_POWER".parse().unwrap(), Action::ToggleBt);
+        b.bind(
+            "KEY_LEFTCTRL+KEY_LEFTALT+KEY_POWER".parse().unwrap(),
+            Action::Poweroff,
+        );
+        let mut ks = KeyState::default();
+        assert_eq!(feed(&mut b, &mut ks, CTRL, 1), None);
+        assert_eq!(feed(&mut b, &mut ks, ALT, 1), None);
+        assert_eq!(
+            feed(&mut b, &mut ks, POWER, 1).map(|(_, a)| a),
+            Some(Action::Poweroff)
+        );
+    }
+}
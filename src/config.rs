@@ -4,24 +4,223 @@
 //! comments with #). Loads environment variables first and then overlays the
 //! values from a config file if present. This is intentionally lightweight.
 
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
+use std::time::Duration;
 
-use crate::hardware::rf;
+use crate::action::Action;
+use crate::chord::ChordBindings;
+use crate::dim::{DimConfig, FadeCurve};
+use crate::error::Error;
+use crate::feedback::Feedback;
+use crate::gesture::Bindings;
+use crate::hardware::cpu::parse_tunables;
+use crate::hardware::cpu_freq::FreqSpec;
+use crate::hardware::cpu_hotplug::OfflineCores;
+use crate::hardware::power_key::PowerKeySelector;
+use crate::hardware::rf::{RfSelector, RfSetting, RfType};
+use crate::hardware::rfkill::RFKILL_DEV_PATH;
+use crate::idle::IdleConfig;
+use crate::input::keycode_from_name;
+use crate::low_battery::LowBatteryConfig;
+use crate::profile::Profiles;
+use crate::suspend::{SleepState, SuspendConfig};
+use crate::thermal::{ThermalConfig, parse_celsius};
+use crate::wake::{WakeConfig, WakeKeys};
 use log::Level;
 
+/// Saving mode settings given per power source with an `_AC` / `_BATTERY` suffix,
+/// e.g. `SAVING_CPU_FREQ_AC=none` or `TOGGLE_WIFI_AC=false`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SourceOverrides {
+    pub policy_path: Option<PathBuf>,
+    /// `Some(None)` turns the CPU clamp off on this source
+    pub saving_cpu_freq: Option<Option<String>>,
+    /// `Some(None)` keeps the running governor on this source
+    pub saving_cpu_governor: Option<Option<String>>,
+    pub saving_governor_tunables: Option<Vec<(String, String)>>,
+    /// `SAVING_CPU_FREQ_POLICY<N>` ranges, replacing the general ones per policy
+    pub saving_cpu_freq_policies: BTreeMap<u32, Option<String>>,
+    /// `Some(None)` keeps every core online on this source
+    pub saving_cpu_offline: Option<Option<OfflineCores>>,
+    /// `TOGGLE_<TYPE>` per radio type
+    pub rf_toggle: BTreeMap<RfType, bool>,
+    /// `<TYPE>_RFKILL` per radio type
+    pub rf_select: BTreeMap<RfType, RfSelector>,
+}
+
+impl SourceOverrides {
+    pub fn is_empty(&self) -> bool {
+        *self == SourceOverrides::default()
+    }
+
+    /// Apply a key with its suffix already stripped; false if it can't be overridden
+    fn apply_value(&mut self, key: &str, val: &str, warnings: &mut Vec<String>) -> bool {
+        match key {
+            "POLICY_PATH" => self.policy_path = Some(PathBuf::from(val)),
+            "SAVING_CPU_FREQ" => {
+                self.saving_cpu_freq = Some(match val.to_ascii_lowercase().as_str() {
+                    "" | "none" => None,
+                    _ => Some(val.to_string()),
+                })
+            }
+            "SAVING_CPU_GOVERNOR" => {
+                self.saving_cpu_governor = Some(match val.to_ascii_lowercase().as_str() {
+                    "" | "none" => None,
+                    _ => Some(val.to_string()),
+                })
+            }
+            "SAVING_CPU_GOVERNOR_TUNABLES" => match parse_tunables(val) {
+                Ok(t) => self.saving_governor_tunables = Some(t),
+                Err(e) => warnings.push(format!("Ignoring {}: {}", key, e)),
+            },
+            "SAVING_CPU_OFFLINE" | "SAVING_CPU_ONLINE" => match parse_cpu_offline(key, val) {
+                Ok(cores) => self.saving_cpu_offline = Some(cores),
+                Err(e) => warnings.push(format!("Ignoring {}: {}", key, e)),
+            },
+            k if k.starts_with("SAVING_CPU_FREQ_POLICY") => match policy_range(k, val) {
+                Ok((n, range)) => {
+                    self.saving_cpu_freq_policies.insert(n, range);
+                }
+                Err(e) => warnings.push(format!("Ignoring {}: {}", k, e)),
+            },
+            k => match rf_key(k) {
+                Some((kind, true)) => {
+                    self.rf_toggle.insert(kind, parse_bool(val));
+                }
+                Some((kind, false)) => match val.parse::<RfSelector>() {
+                    Ok(sel) => {
+                        self.rf_select.insert(kind, sel);
+                    }
+                    Err(e) => warnings.push(format!("Ignoring {}: {}", k, e)),
+                },
+                None => return false,
+            },
+        }
+        true
+    }
+}
+
+/// Key without its `_AC` / `_BATTERY` suffix, and whether it's the AC one
+fn source_suffix(key: &str) -> Option<(&str, bool)> {
+    match key.strip_suffix("_AC") {
+        Some(base) => Some((base, true)),
+        None => Some((key.strip_suffix("_BATTERY")?, false)),
+    }
+}
+
+/// `SAVING_CPU_FREQ_POLICY<N>`: the policy number and its range (`None` leaves it alone)
+fn policy_range(key: &str, val: &str) -> Result<(u32, Option<String>), String> {
+    let n = key["SAVING_CPU_FREQ_POLICY".len()..]
+        .parse::<u32>()
+        .map_err(|_| "expected a policy number".to_string())?;
+    let range = match val.to_ascii_lowercase().as_str() {
+        "" | "none" => None,
+        r => Some(r.to_string()),
+    };
+    Ok((n, range))
+}
+
+/// `SAVING_CPU_OFFLINE` (a core list or `none`) or `SAVING_CPU_ONLINE` (cores to keep)
+fn parse_cpu_offline(key: &str, val: &str) -> Result<Option<OfflineCores>, Error> {
+    if key == "SAVING_CPU_ONLINE" {
+        return OfflineCores::parse_online(val).map(Some);
+    }
+    OfflineCores::parse_offline(val)
+}
+
+/// `TOGGLE_<TYPE>` and `<TYPE>_RFKILL` keys, e.g. `TOGGLE_WWAN` or `BT_RFKILL`: the
+/// radio type and whether it's the toggle
+fn rf_key(key: &str) -> Option<(RfType, bool)> {
+    if let Some(t) = key.strip_prefix("TOGGLE_") {
+        return Some((t.parse().ok()?, true));
+    }
+    Some((key.strip_suffix("_RFKILL")?.parse().ok()?, false))
+}
+
+/// Keys also read from the process environment; everything else only comes
+/// from the config file
+pub const ENV_KEYS: &[&str] = &[
+    "DRY_RUN",
+    "POLICY_PATH",
+    "SAVING_CPU_FREQ",
+    "HOLD_TRIGGER_SEC",
+    "TOGGLE_WIFI",
+    "WIFI_RFKILL",
+    "TOGGLE_BT",
+    "BT_RFKILL",
+    "LOG_LEVEL",
+    "LONG_PRESS_ACTION",
+];
+
 #[derive(Clone, Debug, Default)]
 pub struct Config {
     pub dry_run: bool,
     pub policy_path: Option<PathBuf>,
     pub saving_cpu_freq: Option<String>,
+    /// Saving ranges of single cpufreq policies (`SAVING_CPU_FREQ_POLICY<N>`),
+    /// taking precedence over `saving_cpu_freq`; `None` leaves that policy alone
+    pub saving_cpu_freq_policies: BTreeMap<u32, Option<String>>,
+    /// Cores to take offline in saving mode
+    pub saving_cpu_offline: Option<OfflineCores>,
+    /// `scaling_governor` in saving mode
+    pub saving_cpu_governor: Option<String>,
+    /// Tunables of the saving governor
+    pub saving_governor_tunables: Vec<(String, String)>,
+    /// `max_freq` of devfreq devices (GPU etc.) in saving mode
+    pub saving_devfreq_max: Option<FreqSpec>,
+    /// devfreq `governor` in saving mode
+    pub saving_devfreq_governor: Option<String>,
+    /// devfreq devices to clamp by name (empty = all)
+    pub devfreq_devices: Vec<String>,
     pub hold_trigger_sec: Option<f32>,
-    pub toggle_wifi: bool,
-    pub wifi_rfkill_path: Option<PathBuf>,
-    pub toggle_bt: bool,
-    pub bt_rfkill_path: Option<PathBuf>,
+    /// Which radios saving mode blocks, by type (`TOGGLE_<TYPE>` / `<TYPE>_RFKILL`)
+    pub rf: BTreeMap<RfType, RfSetting>,
+    /// rfkill character device to block radios through and watch for changes;
+    /// `None` writes sysfs `state` files
+    pub rfkill_dev: Option<PathBuf>,
     pub log_level: Option<Level>,
+    pub long_press_action: Option<Action>,
+    pub tap_window_sec: Option<f32>,
+    /// Gesture bindings from `BIND_*` keys, overlaid on the defaults at startup
+    pub bindings: Bindings,
+    pub hold_feedback: Feedback,
+    /// Extra evdev nodes to watch for chords, besides the power key
+    pub input_devices: Vec<PathBuf>,
+    /// Also watch the uConsole's internal keyboard/trackball
+    pub watch_internal_kb: bool,
+    /// USB `vid:pid` IDs identifying the internal keyboard (empty = built-in list)
+    pub internal_kb_ids: Vec<String>,
+    /// Hotkey chords from `CHORD_*` keys
+    pub chords: ChordBindings,
+    pub power_key_device: Option<PowerKeySelector>,
+    /// Key code that gestures are made with (default KEY_POWER)
+    pub power_key_code: Option<u16>,
+    /// Leave saving mode on internal keyboard/trackball input
+    pub wake: WakeConfig,
+    /// Re-emit power key device events the daemon doesn't consume via uinput
+    pub uinput_passthrough: bool,
+    /// Automatic saving mode after a period without input
+    pub idle: IdleConfig,
+    /// Dim stage before idle saving mode and backlight fades
+    pub dim: DimConfig,
+    /// Low-battery saving mode and critical poweroff thresholds
+    pub battery: LowBatteryConfig,
+    /// Saving mode settings that differ while a charger is connected
+    pub on_ac: SourceOverrides,
+    /// Saving mode settings that differ while running on battery
+    pub on_battery: SourceOverrides,
+    /// Named power profiles from `PROFILE_*` keys
+    pub profiles: Profiles,
+    /// FIFO accepting actions from other programs; `None` disables it
+    pub control_fifo: Option<PathBuf>,
+    /// How the `sleep` action suspends the system
+    pub suspend: SuspendConfig,
+    /// CPU frequency cap while running hot
+    pub thermal: ThermalConfig,
+    /// Values skipped while loading; logged once the logger is set up
+    pub warnings: Vec<String>,
 }
 
 // Default impl derived via #[derive(Default)]
@@ -30,8 +229,35 @@ fn parse_bool(s: &str) -> bool {
     matches!(s.to_ascii_lowercase().as_str(), "1" | "true" | "yes")
 }
 
-fn parse_value_map(content: &str) -> HashMap<String, String> {
-    let mut map = HashMap::new();
+fn parse_list(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+/// Parse a timeout in seconds; `0` means disabled
+fn parse_timeout(s: &str) -> Result<Option<Duration>, String> {
+    let secs = s.parse::<f32>().map_err(|e| format!("'{}': {}", s, e))?;
+    if !secs.is_finite() || secs < 0.0 {
+        return Err(format!("'{}' is not a valid timeout", s));
+    }
+    Ok((secs > 0.0).then(|| Duration::from_secs_f32(secs)))
+}
+
+/// Parse a percentage; `0` means disabled
+fn parse_percent(s: &str) -> Result<Option<u8>, String> {
+    match s.parse::<u8>() {
+        Ok(p) if p <= 100 => Ok((p > 0).then_some(p)),
+        Ok(p) => Err(format!("{} is over 100", p)),
+        Err(e) => Err(format!("'{}': {}", s, e)),
+    }
+}
+
+/// `KEY=VALUE` lines in file order, so of keys setting the same thing (e.g.
+/// `SAVING_CPU_OFFLINE` and `SAVING_CPU_ONLINE`) the later line wins
+fn parse_values(content: &str) -> Vec<(String, String)> {
+    let mut values = Vec::new();
     for line in content.lines() {
         let line = line.trim();
         if line.is_empty() || line.starts_with('#') {
@@ -40,48 +266,321 @@ fn parse_value_map(content: &str) -> HashMap<String, String> {
         if let Some(eq) = line.find('=') {
             let key = line[..eq].trim().to_string();
             let val = line[eq + 1..].trim().to_string();
-            map.insert(key, val);
+            values.push((key, val));
         }
     }
-    map
+    values
 }
 
 impl Config {
-    /// Load config by overlaying env variables with values from config file.
-    /// If `path` is None, we try repo-local `./etc/uconsole-sleep/config.default` first,
-    /// then `/etc/uconsole-sleep/config`.
-    pub fn load(path: Option<PathBuf>) -> Self {
-        let mut cfg = Config::default();
+    /// Apply a single KEY=VALUE pair. Unknown keys are ignored; bad values are
+    /// skipped with a message in `warnings`.
+    fn apply_value(&mut self, key: &str, val: &str) {
+        if let Some((kind, toggle)) = rf_key(key) {
+            let setting = self.rf.entry(kind).or_default();
+            if toggle {
+                setting.toggle = parse_bool(val);
+            } else {
+                match val.parse::<RfSelector>() {
+                    Ok(sel) => setting.select = sel,
+                    Err(e) => self.warnings.push(format!("Ignoring {}: {}", key, e)),
+                }
+            }
+            return;
+        }
+        match key {
+            "DRY_RUN" => self.dry_run = parse_bool(val),
+            "POLICY_PATH" => self.policy_path = Some(PathBuf::from(val)),
+            "SAVING_CPU_FREQ" => self.saving_cpu_freq = Some(val.to_string()),
+            k if k.starts_with("SAVING_CPU_FREQ_POLICY") && source_suffix(k).is_none() => {
+                match policy_range(k, val) {
+                    Ok((n, range)) => {
+                        self.saving_cpu_freq_policies.insert(n, range);
+                    }
+                    Err(e) => self.warnings.push(format!("Ignoring {}: {}", k, e)),
+                }
+            }
+            "SAVING_CPU_OFFLINE" | "SAVING_CPU_ONLINE" => match parse_cpu_offline(key, val) {
+                Ok(cores) => self.saving_cpu_offline = cores,
+                Err(e) => self.warnings.push(format!("Ignoring {}: {}", key, e)),
+            },
+            "SAVING_CPU_GOVERNOR" => {
+                self.saving_cpu_governor = (!val.is_empty()).then(|| val.to_string())
+            }
+            "SAVING_CPU_GOVERNOR_TUNABLES" => match parse_tunables(val) {
+                Ok(t) => self.saving_governor_tunables = t,
+                Err(e) => self
+                    .warnings
+                    .push(format!("Ignoring SAVING_CPU_GOVERNOR_TUNABLES: {}", e)),
+            },
+            "SAVING_DEVFREQ_MAX" => match val.to_ascii_lowercase().as_str() {
+                "" | "none" => self.saving_devfreq_max = None,
+                spec => match spec.parse::<FreqSpec>() {
+                    Ok(f) => self.saving_devfreq_max = Some(f),
+                    Err(e) => self
+                        .warnings
+                        .push(format!("Ignoring SAVING_DEVFREQ_MAX: {}", e)),
+                },
+            },
+            "SAVING_DEVFREQ_GOVERNOR" => {
+                self.saving_devfreq_governor = (!val.is_empty()).then(|| val.to_string())
+            }
+            "DEVFREQ_DEVICES" => {
+                self.devfreq_devices = val
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            }
+            "THERMAL_TRIP_C" => match val.to_ascii_lowercase().as_str() {
+                "" | "none" | "0" => self.thermal.trip_millic = None,
+                t => match parse_celsius(t) {
+                    Ok(t) => self.thermal.trip_millic = Some(t),
+                    Err(e) => self
+                        .warnings
+                        .push(format!("Ignoring THERMAL_TRIP_C: {}", e)),
+                },
+            },
+            "THERMAL_RELEASE_C" => match parse_celsius(val) {
+                Ok(t) => self.thermal.release_millic = Some(t),
+                Err(e) => self
+                    .warnings
+                    .push(format!("Ignoring THERMAL_RELEASE_C: {}", e)),
+            },
+            "THERMAL_CAP" => match val.parse::<FreqSpec>() {
+                Ok(f) => self.thermal.cap = f,
+                Err(e) => self.warnings.push(format!("Ignoring THERMAL_CAP: {}", e)),
+            },
+            "THERMAL_ZONES" => {
+                self.thermal.zones = val
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            }
+            "THERMAL_POLL_SEC" => match parse_timeout(val) {
+                Ok(Some(t)) => self.thermal.poll_interval = t,
+                Ok(None) => self
+                    .warnings
+                    .push("Ignoring THERMAL_POLL_SEC: must be positive".to_string()),
+                Err(e) => self
+                    .warnings
+                    .push(format!("Ignoring THERMAL_POLL_SEC: {}", e)),
+            },
+            "HOLD_TRIGGER_SEC" => self.hold_trigger_sec = val.parse::<f32>().ok(),
+            "LOG_LEVEL" => {
+                if let Ok(l) = val.parse::<log::Level>() {
+                    self.log_level = Some(l);
+                }
+            }
+            "LONG_PRESS_ACTION" => match val.parse::<Action>() {
+                Ok(a) => self.long_press_action = Some(a),
+                Err(e) => self
+                    .warnings
+                    .push(format!("Ignoring LONG_PRESS_ACTION: {}", e)),
+            },
+            "HOLD_FEEDBACK" => match val.parse::<Feedback>() {
+                Ok(f) => self.hold_feedback = f,
+                Err(e) => self.warnings.push(format!("Ignoring HOLD_FEEDBACK: {}", e)),
+            },
+            "POWER_KEY_DEVICE" => match val.parse::<PowerKeySelector>() {
+                Ok(sel) => self.power_key_device = Some(sel),
+                Err(e) => self
+                    .warnings
+                    .push(format!("Ignoring POWER_KEY_DEVICE: {}", e)),
+            },
+            "POWER_KEY_CODE" => match keycode_from_name(val) {
+                Some(code) => self.power_key_code = Some(code),
+                None => self
+                    .warnings
+                    .push(format!("Ignoring POWER_KEY_CODE: unknown key '{}'", val)),
+            },
+            "INPUT_DEVICES" => {
+                self.input_devices = parse_list(val).into_iter().map(PathBuf::from).collect()
+            }
+            "WATCH_INTERNAL_KB" => self.watch_internal_kb = parse_bool(val),
+            "INTERNAL_KB_IDS" => self.internal_kb_ids = parse_list(val),
+            "WAKE_ON_KEY" => match val.parse::<WakeKeys>() {
+                Ok(keys) => self.wake.keys = keys,
+                Err(e) => self.warnings.push(format!("Ignoring WAKE_ON_KEY: {}", e)),
+            },
+            "WAKE_MOTION_THRESHOLD" => match val.parse::<u32>() {
+                Ok(0) => self.wake.motion_threshold = None,
+                Ok(t) => self.wake.motion_threshold = Some(t),
+                Err(e) => self
+                    .warnings
+                    .push(format!("Ignoring WAKE_MOTION_THRESHOLD: {}", e)),
+            },
+            "WAKE_GRAB_INPUT" => self.wake.grab = parse_bool(val),
+            "UINPUT_PASSTHROUGH" => self.uinput_passthrough = parse_bool(val),
+            "IDLE_TIMEOUT_BATTERY_SEC" => match parse_timeout(val) {
+                Ok(t) => self.idle.battery_timeout = t,
+                Err(e) => self
+                    .warnings
+                    .push(format!("Ignoring IDLE_TIMEOUT_BATTERY_SEC: {}", e)),
+            },
+            "IDLE_TIMEOUT_AC_SEC" => match parse_timeout(val) {
+                Ok(t) => self.idle.ac_timeout = t,
+                Err(e) => self
+                    .warnings
+                    .push(format!("Ignoring IDLE_TIMEOUT_AC_SEC: {}", e)),
+            },
+            "IDLE_MOTION_THRESHOLD" => match val.parse::<u32>() {
+                Ok(t) => self.idle.motion_threshold = t,
+                Err(e) => self
+                    .warnings
+                    .push(format!("Ignoring IDLE_MOTION_THRESHOLD: {}", e)),
+            },
+            "DIM_PERCENT" => match parse_percent(val) {
+                Ok(p) => self.dim.percent = p,
+                Err(e) => self.warnings.push(format!("Ignoring DIM_PERCENT: {}", e)),
+            },
+            "DIM_SEC" => match parse_timeout(val) {
+                Ok(t) => self.dim.hold = t.unwrap_or_default(),
+                Err(e) => self.warnings.push(format!("Ignoring DIM_SEC: {}", e)),
+            },
+            "FADE_SEC" => match parse_timeout(val) {
+                Ok(t) => self.dim.fade = t.unwrap_or_default(),
+                Err(e) => self.warnings.push(format!("Ignoring FADE_SEC: {}", e)),
+            },
+            "FADE_CURVE" => match val.parse::<FadeCurve>() {
+                Ok(c) => self.dim.curve = c,
+                Err(e) => self.warnings.push(format!("Ignoring FADE_CURVE: {}", e)),
+            },
+            "BATTERY_LOW_PERCENT" => match parse_percent(val) {
+                Ok(p) => self.battery.low_percent = p,
+                Err(e) => self
+                    .warnings
+                    .push(format!("Ignoring BATTERY_LOW_PERCENT: {}", e)),
+            },
+            "BATTERY_CRITICAL_PERCENT" => match parse_percent(val) {
+                Ok(p) => self.battery.critical_percent = p,
+                Err(e) => self
+                    .warnings
+                    .push(format!("Ignoring BATTERY_CRITICAL_PERCENT: {}", e)),
+            },
+            "BATTERY_CRITICAL_GRACE_SEC" => match parse_timeout(val) {
+                Ok(t) => self.battery.critical_grace = t.unwrap_or_default(),
+                Err(e) => self
+                    .warnings
+                    .push(format!("Ignoring BATTERY_CRITICAL_GRACE_SEC: {}", e)),
+            },
+            "BATTERY_POLL_SEC" => match parse_timeout(val) {
+                Ok(Some(t)) => self.battery.poll_interval = t,
+                Ok(None) => self
+                    .warnings
+                    .push("Ignoring BATTERY_POLL_SEC: must be positive".to_string()),
+                Err(e) => self
+                    .warnings
+                    .push(format!("Ignoring BATTERY_POLL_SEC: {}", e)),
+            },
+            "SUSPEND_STATE" => match val.parse::<SleepState>() {
+                Ok(s) => self.suspend.state = s,
+                Err(e) => self.warnings.push(format!("Ignoring SUSPEND_STATE: {}", e)),
+            },
+            "SUSPEND_MEM_SLEEP" => {
+                self.suspend.mem_sleep = match val.to_ascii_lowercase().as_str() {
+                    "" | "none" => None,
+                    v => Some(v.to_string()),
+                }
+            }
+            "SUSPEND_WAKE_KEYBOARD" => self.suspend.wake_keyboard = parse_bool(val),
+            "RFKILL_DEV" => {
+                self.rfkill_dev = match val.to_ascii_lowercase().as_str() {
+                    "" | "none" | "0" | "false" | "no" => None,
+                    "1" | "true" | "yes" => Some(PathBuf::from(RFKILL_DEV_PATH)),
+                    _ => Some(PathBuf::from(val)),
+                }
+            }
+            "CONTROL_FIFO" => {
+                self.control_fifo = match val {
+                    "" | "none" => None,
+                    p => Some(PathBuf::from(p)),
+                }
+            }
+            k if k.starts_with("PROFILE_") => {
+                if let Err(e) = self.profiles.parse_key(&k["PROFILE_".len()..], val) {
+                    self.warnings.push(format!("Ignoring {}: {}", k, e));
+                }
+            }
+            k if k.starts_with("CHORD_") => match ChordBindings::parse_value(val) {
+                Ok((chord, action)) => self.chords.bind(chord, action),
+                Err(e) => self.warnings.push(format!("Ignoring {}: {}", k, e)),
+            },
+            "TAP_WINDOW_SEC" => self.tap_window_sec = val.parse::<f32>().ok(),
+            k if k.starts_with("BIND_") => match (Bindings::parse_key(k), val.parse::<Action>()) {
+                (Ok((mode, gesture)), Ok(action)) => self.bindings.bind(mode, gesture, action),
+                (Err(e), _) | (_, Err(e)) => self.warnings.push(format!("Ignoring {}: {}", k, e)),
+            },
+            k if source_suffix(k).is_some() => {
+                let Some((base, ac)) = source_suffix(k) else {
+                    return;
+                };
+                let overrides = if ac {
+                    &mut self.on_ac
+                } else {
+                    &mut self.on_battery
+                };
+                if !overrides.apply_value(base, val, &mut self.warnings) {
+                    self.warnings.push(format!(
+                        "Ignoring {}: {} has no per-source override",
+                        k, base
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
 
-        // Overlay from environment variables
-        if let Ok(v) = std::env::var("DRY_RUN") {
-            cfg.dry_run = parse_bool(&v);
+    /// This config with the `_AC` or `_BATTERY` overrides applied
+    pub fn for_power_source(&self, on_ac: bool) -> Config {
+        let o = if on_ac { &self.on_ac } else { &self.on_battery };
+        let mut cfg = self.clone();
+        if let Some(p) = &o.policy_path {
+            cfg.policy_path = Some(p.clone());
         }
-        if let Ok(v) = std::env::var("POLICY_PATH") {
-            cfg.policy_path = Some(PathBuf::from(v));
+        if let Some(f) = &o.saving_cpu_freq {
+            cfg.saving_cpu_freq = f.clone();
         }
-        if let Ok(v) = std::env::var("SAVING_CPU_FREQ") {
-            cfg.saving_cpu_freq = Some(v);
+        if let Some(g) = &o.saving_cpu_governor {
+            cfg.saving_cpu_governor = g.clone();
         }
-        if let Ok(v) = std::env::var("HOLD_TRIGGER_SEC") {
-            cfg.hold_trigger_sec = v.parse::<f32>().ok();
+        if let Some(t) = &o.saving_governor_tunables {
+            cfg.saving_governor_tunables = t.clone();
         }
-        if let Ok(v) = std::env::var("TOGGLE_WIFI") {
-            cfg.toggle_wifi = parse_bool(&v);
+        for (n, range) in &o.saving_cpu_freq_policies {
+            cfg.saving_cpu_freq_policies.insert(*n, range.clone());
         }
-        if let Ok(v) = std::env::var("WIFI_RFKILL") {
-            cfg.wifi_rfkill_path = Some(PathBuf::from(v));
+        if let Some(c) = &o.saving_cpu_offline {
+            cfg.saving_cpu_offline = c.clone();
         }
-        if let Ok(v) = std::env::var("TOGGLE_BT") {
-            cfg.toggle_bt = parse_bool(&v);
+        for (kind, toggle) in &o.rf_toggle {
+            cfg.rf.entry(*kind).or_default().toggle = *toggle;
         }
-        if let Ok(v) = std::env::var("BT_RFKILL") {
-            cfg.bt_rfkill_path = Some(PathBuf::from(v));
+        for (kind, sel) in &o.rf_select {
+            cfg.rf.entry(*kind).or_default().select = sel.clone();
         }
-        if let Ok(v) = std::env::var("LOG_LEVEL")
-            && let Ok(l) = v.parse::<log::Level>()
-        {
-            cfg.log_level = Some(l);
+        cfg
+    }
+
+    /// Settings of one radio type; not blocked unless configured
+    pub fn rf_setting(&self, kind: RfType) -> RfSetting {
+        self.rf.get(&kind).cloned().unwrap_or_default()
+    }
+
+    /// Load config by overlaying env variables with values from config file.
+    /// If `path` is None, we try repo-local `./etc/uconsole-sleep/config.default` first,
+    /// then `/etc/uconsole-sleep/config`.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let mut cfg = Config::default();
+
+        // Overlay from environment variables
+        for key in ENV_KEYS {
+            if let Ok(val) = std::env::var(key) {
+                cfg.apply_value(key, &val);
+            }
         }
 
         // Determine config file path
@@ -94,47 +593,11 @@ impl Config {
         };
 
         if let Ok(content) = fs::read_to_string(&cfg_path) {
-            let map = parse_value_map(&content);
-            if let Some(v) = map.get("DRY_RUN") {
-                cfg.dry_run = parse_bool(v);
-            }
-            if let Some(v) = map.get("POLICY_PATH") {
-                cfg.policy_path = Some(PathBuf::from(v));
-            }
-            if let Some(v) = map.get("SAVING_CPU_FREQ") {
-                cfg.saving_cpu_freq = Some(v.clone());
-            }
-            if let Some(v) = map.get("HOLD_TRIGGER_SEC") {
-                cfg.hold_trigger_sec = v.parse::<f32>().ok();
-            }
-            if let Some(v) = map.get("TOGGLE_WIFI") {
-                cfg.toggle_wifi = parse_bool(v);
-            }
-            if let Some(v) = map.get("WIFI_RFKILL") {
-                cfg.wifi_rfkill_path = Some(PathBuf::from(v));
-            }
-            if let Some(v) = map.get("TOGGLE_BT") {
-                cfg.toggle_bt = parse_bool(v);
-            }
-            if let Some(v) = map.get("BT_RFKILL") {
-                cfg.bt_rfkill_path = Some(PathBuf::from(v));
-            }
-            if let Some(v) = map.get("LOG_LEVEL")
-                && let Ok(l) = v.parse::<log::Level>()
-            {
-                cfg.log_level = Some(l);
+            for (key, val) in parse_values(&content) {
+                cfg.apply_value(&key, &val);
             }
         }
 
-        // final: if wifi enabled and no rfkill path provided, set default
-        if cfg.toggle_wifi && cfg.wifi_rfkill_path.is_none() {
-            cfg.wifi_rfkill_path = Some(PathBuf::from(rf::RFKILL_PATH_WIFI));
-        }
-        // final: if bt enabled and no rfkill path provided, set default
-        if cfg.toggle_bt && cfg.bt_rfkill_path.is_none() {
-            cfg.bt_rfkill_path = Some(PathBuf::from(rf::RFKILL_PATH_BT));
-        }
-
         cfg
     }
 
@@ -146,10 +609,8 @@ impl Config {
 
 #[cfg(test)]
 mod tests {
-    use crate::hardware::rf;
-
     use super::*;
-    use std::env;
+    use crate::test_util::TempDir;
     use std::fs;
 
     #[test]
@@ -163,59 +624,498 @@ mod tests {
 
     #[test]
     fn test_wifi_default_rfkill() {
-        let tmp = env::temp_dir().join(format!(
-            "uconsole_cfg_{}",
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis()
-        ));
-        let _ = fs::create_dir_all(&tmp);
+        let tmp = TempDir::new("cfg");
         let cfg_file = tmp.join("cfg");
         fs::write(&cfg_file, "TOGGLE_WIFI=true\n").unwrap();
         let cfg = Config::load(Some(cfg_file.clone()));
-        assert!(cfg.toggle_wifi);
+        // no fixed rfkillN: every wlan entry is looked up by type
         assert_eq!(
-            cfg.wifi_rfkill_path.unwrap(),
-            PathBuf::from(rf::RFKILL_PATH_WIFI)
+            cfg.rf_setting(RfType::Wlan),
+            RfSetting {
+                toggle: true,
+                select: RfSelector::All
+            }
         );
+        assert!(!cfg.rf_setting(RfType::Bluetooth).toggle);
+        assert_eq!(cfg.rfkill_dev, None);
+
+        fs::write(&cfg_file, "TOGGLE_WIFI=true\nRFKILL_DEV=yes\n").unwrap();
+        let cfg = Config::load(Some(cfg_file));
+        assert_eq!(cfg.rfkill_dev, Some(PathBuf::from("/dev/rfkill")));
     }
 
     #[test]
     fn test_bt_default_rfkill() {
-        let tmp = env::temp_dir().join(format!(
-            "uconsole_cfg_bt_{}",
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis()
-        ));
-        let _ = fs::create_dir_all(&tmp);
+        let tmp = TempDir::new("cfg_bt");
         let cfg_file = tmp.join("cfg_bt");
-        fs::write(&cfg_file, "TOGGLE_BT=true\n").unwrap();
+        fs::write(
+            &cfg_file,
+            "TOGGLE_BT=true\nTOGGLE_WWAN=yes\nWWAN_RFKILL=name:wwan0\nWIFI_RFKILL=/sys/class/rfkill/rfkill3\nTOGGLE_FM=true\nGPS_RFKILL=index:2\n",
+        )
+        .unwrap();
         let cfg = Config::load(Some(cfg_file.clone()));
-        assert!(cfg.toggle_bt);
+        assert!(cfg.rf_setting(RfType::Bluetooth).toggle);
+        assert_eq!(cfg.rf_setting(RfType::Bluetooth).select, RfSelector::All);
         assert_eq!(
-            cfg.bt_rfkill_path.unwrap(),
-            PathBuf::from(rf::RFKILL_PATH_BT)
+            cfg.rf_setting(RfType::Wwan),
+            RfSetting {
+                toggle: true,
+                select: RfSelector::Name("wwan0".to_string())
+            }
         );
+        assert_eq!(
+            cfg.rf_setting(RfType::Wlan).select,
+            RfSelector::Path(PathBuf::from("/sys/class/rfkill/rfkill3"))
+        );
+        // unknown type and bad selector are skipped
+        assert_eq!(cfg.rf_setting(RfType::Gps), RfSetting::default());
     }
 
     #[test]
     fn test_log_level_from_file() {
-        let tmp = env::temp_dir().join(format!(
-            "uconsole_cfg_{}",
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis()
-        ));
-        let _ = fs::create_dir_all(&tmp);
+        let tmp = TempDir::new("cfg");
         let cfg_file = tmp.join("cfg_log");
         fs::write(&cfg_file, "LOG_LEVEL=debug\n").unwrap();
         let cfg = Config::load(Some(cfg_file.clone()));
         assert_eq!(cfg.log_level, Some(log::Level::Debug));
     }
 
+    #[test]
+    fn test_bad_values_collected_as_warnings() {
+        let tmp = TempDir::new("cfg_warn");
+        let cfg_file = tmp.join("cfg_warn");
+        fs::write(
+            &cfg_file,
+            "DIM_PERCENT=lots\nBT_RFKILL_AC=name:\nFADE_SEC=1\n",
+        )
+        .unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert_eq!(cfg.warnings.len(), 2);
+        assert!(
+            cfg.warnings
+                .iter()
+                .any(|w| w.starts_with("Ignoring DIM_PERCENT"))
+        );
+        assert!(
+            cfg.warnings
+                .iter()
+                .any(|w| w.starts_with("Ignoring BT_RFKILL"))
+        );
+    }
+
+    #[test]
+    fn test_long_press_action_from_file() {
+        let tmp = TempDir::new("cfg_lp");
+        let cfg_file = tmp.join("cfg_lp");
+        fs::write(&cfg_file, "LONG_PRESS_ACTION=cmd:systemctl poweroff -i\n").unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert_eq!(
+            cfg.long_press_action,
+            Some(Action::Command("systemctl poweroff -i".to_string()))
+        );
+
+        // an unknown action is ignored rather than aborting the load
+        fs::write(
+            &cfg_file,
+            "LONG_PRESS_ACTION=explode\nHOLD_FEEDBACK=led:ACT\n",
+        )
+        .unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert_eq!(cfg.long_press_action, None);
+        assert_eq!(
+            cfg.hold_feedback,
+            Feedback::Led(PathBuf::from("/sys/class/leds/ACT"))
+        );
+    }
+
+    #[test]
+    fn test_bindings_from_file() {
+        use crate::gesture::Gesture;
+        use crate::power_mode::PowerMode;
+
+        let tmp = TempDir::new("cfg_bind");
+        let cfg_file = tmp.join("cfg_bind");
+        fs::write(
+            &cfg_file,
+            "TAP_WINDOW_SEC=0.3\nBIND_SAVING_TAP=none\nBIND_SAVING_DOUBLE_TAP=toggle\nBIND_HOLD=reboot\nBIND_SAVING_NAP=toggle\n",
+        )
+        .unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert_eq!(cfg.tap_window_sec, Some(0.3_f32));
+        let b = &cfg.bindings;
+        assert_eq!(
+            b.lookup(&PowerMode::Saving, Gesture::Tap(1)),
+            Some(&Action::None)
+        );
+        assert_eq!(
+            b.lookup(&PowerMode::Saving, Gesture::Tap(2)),
+            Some(&Action::ToggleSaving)
+        );
+        assert_eq!(
+            b.lookup(&PowerMode::Normal, Gesture::Hold),
+            Some(&Action::Reboot)
+        );
+        // the malformed key was skipped
+        assert_eq!(b.lookup(&PowerMode::Normal, Gesture::Tap(1)), None);
+    }
+
+    #[test]
+    fn test_input_devices_and_chords_from_file() {
+        use crate::chord::Chord;
+        use crate::input::{EV_KEY, KeyState};
+
+        let tmp = TempDir::new("cfg_chord");
+        let cfg_file = tmp.join("cfg_chord");
+        fs::write(
+            &cfg_file,
+            "INPUT_DEVICES=/dev/input/event3, /dev/input/event7\nWATCH_INTERNAL_KB=yes\nINTERNAL_KB_IDS=1eaf:0024,abcd:0001\nCHORD_WIFI=KEY_FN+KEY_F1:toggle_wifi\nCHORD_BAD=KEY_FN:toggle_bt\n",
+        )
+        .unwrap();
+        let mut cfg = Config::load(Some(cfg_file.clone()));
+        assert_eq!(
+            cfg.input_devices,
+            vec![
+                PathBuf::from("/dev/input/event3"),
+                PathBuf::from("/dev/input/event7")
+            ]
+        );
+        assert!(cfg.watch_internal_kb);
+        assert_eq!(cfg.internal_kb_ids, vec!["1eaf:0024", "abcd:0001"]);
+
+        let mut ks = KeyState::default();
+        let chord: Chord = "KEY_FN+KEY_F1".parse().unwrap();
+        for k in &chord.keys {
+            ks.update(0, EV_KEY, *k, 1);
+        }
+        assert_eq!(
+            cfg.chords.on_event(&ks, EV_KEY, chord.keys[1], 1),
+            Some((chord, Action::ToggleWifi))
+        );
+    }
+
+    #[test]
+    fn test_power_key_selection_from_file() {
+        let tmp = TempDir::new("cfg_pk");
+        let cfg_file = tmp.join("cfg_pk");
+        fs::write(
+            &cfg_file,
+            "POWER_KEY_DEVICE=name:gpio-keys\nPOWER_KEY_CODE=KEY_SLEEP\n",
+        )
+        .unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert_eq!(
+            cfg.power_key_device,
+            Some(PowerKeySelector::Name("gpio-keys".to_string()))
+        );
+        assert_eq!(cfg.power_key_code, Some(142));
+
+        fs::write(&cfg_file, "POWER_KEY_CODE=KEY_NOPE\n").unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert_eq!(cfg.power_key_code, None);
+        assert_eq!(cfg.power_key_device, None);
+    }
+
+    #[test]
+    fn test_wake_from_file() {
+        let tmp = TempDir::new("cfg_wake");
+        let cfg_file = tmp.join("cfg_wake");
+        fs::write(
+            &cfg_file,
+            "WAKE_ON_KEY=KEY_ENTER,KEY_SPACE\nWAKE_MOTION_THRESHOLD=40\nWAKE_GRAB_INPUT=true\n",
+        )
+        .unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert_eq!(cfg.wake.keys, WakeKeys::Keys(vec![28, 57]));
+        assert_eq!(cfg.wake.motion_threshold, Some(40));
+        assert!(cfg.wake.grab);
+        assert!(!cfg.uinput_passthrough);
+
+        fs::write(
+            &cfg_file,
+            "WAKE_ON_KEY=KEY_NOPE\nWAKE_MOTION_THRESHOLD=0\nUINPUT_PASSTHROUGH=yes\n",
+        )
+        .unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert!(!cfg.wake.enabled());
+        assert!(cfg.uinput_passthrough);
+    }
+
+    #[test]
+    fn test_idle_timeouts_from_file() {
+        let tmp = TempDir::new("cfg_idle");
+        let cfg_file = tmp.join("cfg_idle");
+        fs::write(
+            &cfg_file,
+            "IDLE_TIMEOUT_BATTERY_SEC=90\nIDLE_TIMEOUT_AC_SEC=0\nIDLE_MOTION_THRESHOLD=12\n",
+        )
+        .unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert_eq!(cfg.idle.battery_timeout, Some(Duration::from_secs(90)));
+        assert_eq!(cfg.idle.ac_timeout, None);
+        assert_eq!(cfg.idle.motion_threshold, 12);
+
+        fs::write(&cfg_file, "IDLE_TIMEOUT_BATTERY_SEC=-5\n").unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert!(!cfg.idle.enabled());
+    }
+
+    #[test]
+    fn test_dim_from_file() {
+        let tmp = TempDir::new("cfg_dim");
+        let cfg_file = tmp.join("cfg_dim");
+        fs::write(
+            &cfg_file,
+            "DIM_PERCENT=30\nDIM_SEC=5\nFADE_SEC=0.5\nFADE_CURVE=exp\n",
+        )
+        .unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert_eq!(cfg.dim.percent, Some(30));
+        assert_eq!(cfg.dim.hold, Duration::from_secs(5));
+        assert_eq!(cfg.dim.fade, Duration::from_millis(500));
+        assert_eq!(cfg.dim.curve, FadeCurve::Exponential);
+
+        fs::write(&cfg_file, "DIM_PERCENT=150\nFADE_CURVE=wobble\n").unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert_eq!(cfg.dim, DimConfig::default());
+    }
+
+    #[test]
+    fn test_battery_thresholds_from_file() {
+        let tmp = TempDir::new("cfg_bat");
+        let cfg_file = tmp.join("cfg_bat");
+        fs::write(
+            &cfg_file,
+            "BATTERY_LOW_PERCENT=15\nBATTERY_CRITICAL_PERCENT=4\nBATTERY_CRITICAL_GRACE_SEC=90\nBATTERY_POLL_SEC=20\n",
+        )
+        .unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert_eq!(cfg.battery.low_percent, Some(15));
+        assert_eq!(cfg.battery.critical_percent, Some(4));
+        assert_eq!(cfg.battery.critical_grace, Duration::from_secs(90));
+        assert_eq!(cfg.battery.poll_interval, Duration::from_secs(20));
+
+        fs::write(
+            &cfg_file,
+            "BATTERY_LOW_PERCENT=101\nBATTERY_CRITICAL_PERCENT=0\nBATTERY_POLL_SEC=0\n",
+        )
+        .unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert!(!cfg.battery.enabled());
+        assert_eq!(cfg.battery, LowBatteryConfig::default());
+    }
+
+    #[test]
+    fn test_power_source_overrides_from_file() {
+        let tmp = TempDir::new("cfg_src");
+        let cfg_file = tmp.join("cfg_src");
+        fs::write(
+            &cfg_file,
+            "SAVING_CPU_FREQ=300,900\nTOGGLE_WIFI=true\nSAVING_CPU_FREQ_AC=none\nTOGGLE_WIFI_AC=no\nSAVING_CPU_FREQ_BATTERY=100,600\nBT_RFKILL_BATTERY=/sys/class/rfkill/rfkill4\nNOT_A_KEY_AC=1\nSAVING_CPU_GOVERNOR=conservative\nSAVING_CPU_GOVERNOR_TUNABLES=up_threshold=95\nSAVING_CPU_GOVERNOR_AC=none\nSAVING_CPU_GOVERNOR_BATTERY=powersave\nSAVING_CPU_GOVERNOR_TUNABLES_BATTERY=\n",
+        )
+        .unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert_eq!(cfg.saving_cpu_freq.as_deref(), Some("300,900"));
+
+        let ac = cfg.for_power_source(true);
+        assert_eq!(ac.saving_cpu_freq, None);
+        assert_eq!(ac.saving_cpu_governor, None);
+        assert_eq!(ac.saving_governor_tunables, cfg.saving_governor_tunables);
+        assert!(!ac.rf_setting(RfType::Wlan).toggle);
+        assert_eq!(
+            ac.rf_setting(RfType::Bluetooth),
+            cfg.rf_setting(RfType::Bluetooth)
+        );
+
+        let bat = cfg.for_power_source(false);
+        assert_eq!(bat.saving_cpu_freq.as_deref(), Some("100,600"));
+        assert_eq!(bat.saving_cpu_governor.as_deref(), Some("powersave"));
+        assert!(bat.saving_governor_tunables.is_empty());
+        assert!(bat.rf_setting(RfType::Wlan).toggle);
+        assert_eq!(
+            bat.rf_setting(RfType::Bluetooth).select,
+            RfSelector::Path(PathBuf::from("/sys/class/rfkill/rfkill4"))
+        );
+        // unknown keys with the suffix are reported, not applied
+        assert!(!cfg.on_ac.is_empty());
+        assert_eq!(cfg.on_ac.policy_path, None);
+        assert_eq!(
+            cfg.warnings,
+            vec!["Ignoring NOT_A_KEY_AC: NOT_A_KEY has no per-source override".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_profiles_from_file() {
+        use crate::profile::{DisplayAction, RfAction};
+
+        let tmp = TempDir::new("cfg_profile");
+        let cfg_file = tmp.join("cfg_profile");
+        fs::write(
+            &cfg_file,
+            "PROFILE_DEEP_CPU_FREQ=100,600\nPROFILE_DEEP_WIFI=block\nPROFILE_SCREEN-OFF-AUDIO_CPU_FREQ=300,900\nPROFILE_SCREEN-OFF-AUDIO_DISPLAY=off\nPROFILE_DEEP_DISPLAY=sideways\nPROFILE_CYCLE=normal,screen-off-audio,deep\nPROFILE_DEFAULT=deep\nCONTROL_FIFO=/run/uconsole-sleep/control\nPROFILE_DEEP_CPU_ONLINE=1\nPROFILE_DEEP_CPU_OFFLINE=2-3\nPROFILE_SCREEN-OFF-AUDIO_CPU_OFFLINE=3\nPROFILE_SCREEN-OFF-AUDIO_CPU_ONLINE=2\n",
+        )
+        .unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        let deep = &cfg.profiles.specs["deep"];
+        assert_eq!(deep.cpu_freq.as_deref(), Some("100,600"));
+        assert_eq!(deep.rf(RfType::Wlan), RfAction::Block);
+        // the bad display value was skipped, leaving the default
+        assert_eq!(deep.display, DisplayAction::Off);
+        // of CPU_OFFLINE and CPU_ONLINE the later line wins
+        assert_eq!(deep.cpu_offline, Some(OfflineCores::List(vec![2, 3])));
+        assert_eq!(
+            cfg.profiles.specs["screen-off-audio"].cpu_offline,
+            Some(OfflineCores::KeepOnline(2))
+        );
+        assert_eq!(
+            cfg.profiles.cycle,
+            vec!["normal", "screen-off-audio", "deep"]
+        );
+        assert_eq!(cfg.profiles.default.as_deref(), Some("deep"));
+        assert!(cfg.profiles.unknown_names().is_empty());
+        assert_eq!(
+            cfg.control_fifo,
+            Some(PathBuf::from("/run/uconsole-sleep/control"))
+        );
+    }
+
+    #[test]
+    fn test_suspend_from_file() {
+        let tmp = TempDir::new("cfg_suspend");
+        let cfg_file = tmp.join("cfg_suspend");
+        fs::write(
+            &cfg_file,
+            "SUSPEND_STATE=freeze\nSUSPEND_MEM_SLEEP=Deep\nSUSPEND_WAKE_KEYBOARD=true\n",
+        )
+        .unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert_eq!(
+            cfg.suspend,
+            SuspendConfig {
+                state: SleepState::Freeze,
+                mem_sleep: Some("deep".to_string()),
+                wake_keyboard: true,
+            }
+        );
+
+        fs::write(&cfg_file, "SUSPEND_STATE=hibernate\n").unwrap();
+        let cfg = Config::load(Some(cfg_file));
+        assert_eq!(cfg.suspend.state, SleepState::Mem);
+    }
+
+    #[test]
+    fn test_cpu_offline_from_file() {
+        let tmp = TempDir::new("cfg_offline");
+        let cfg_file = tmp.join("cfg_offline");
+        fs::write(
+            &cfg_file,
+            "SAVING_CPU_OFFLINE=1-3\nSAVING_CPU_GOVERNOR=conservative\nSAVING_CPU_GOVERNOR_TUNABLES=up_threshold=95\nSAVING_CPU_FREQ_POLICY4=300,1200\nSAVING_CPU_FREQ_POLICY6=none\nSAVING_CPU_FREQ_POLICYX=1,2\n",
+        )
+        .unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert_eq!(
+            cfg.saving_cpu_offline,
+            Some(OfflineCores::List(vec![1, 2, 3]))
+        );
+        assert_eq!(cfg.saving_cpu_governor.as_deref(), Some("conservative"));
+        assert_eq!(
+            cfg.saving_cpu_freq_policies,
+            BTreeMap::from([(4, Some("300,1200".to_string())), (6, None)])
+        );
+        assert_eq!(
+            cfg.saving_governor_tunables,
+            vec![("up_threshold".to_string(), "95".to_string())]
+        );
+
+        fs::write(&cfg_file, "SAVING_CPU_ONLINE=2\n").unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert_eq!(cfg.saving_cpu_offline, Some(OfflineCores::KeepOnline(2)));
+
+        // per power source
+        fs::write(
+            &cfg_file,
+            "SAVING_CPU_OFFLINE=1-3\nSAVING_CPU_FREQ_POLICY4=300,1200\nSAVING_CPU_OFFLINE_AC=none\nSAVING_CPU_FREQ_POLICY4_AC=none\nSAVING_CPU_ONLINE_BATTERY=1\nSAVING_CPU_FREQ_POLICY0_BATTERY=100,600\n",
+        )
+        .unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert!(cfg.warnings.is_empty());
+        let ac = cfg.for_power_source(true);
+        assert_eq!(ac.saving_cpu_offline, None);
+        assert_eq!(ac.saving_cpu_freq_policies, BTreeMap::from([(4, None)]));
+        let bat = cfg.for_power_source(false);
+        assert_eq!(bat.saving_cpu_offline, Some(OfflineCores::KeepOnline(1)));
+        assert_eq!(
+            bat.saving_cpu_freq_policies,
+            BTreeMap::from([
+                (0, Some("100,600".to_string())),
+                (4, Some("300,1200".to_string()))
+            ])
+        );
+
+        fs::write(&cfg_file, "SAVING_CPU_ONLINE=0\nSAVING_CPU_OFFLINE=3-1\n").unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert_eq!(cfg.saving_cpu_offline, None);
+
+        // both set: the later line wins, for the overrides too
+        fs::write(
+            &cfg_file,
+            "SAVING_CPU_OFFLINE=1-3\nSAVING_CPU_ONLINE=2\nSAVING_CPU_ONLINE_AC=2\nSAVING_CPU_OFFLINE_AC=3\n",
+        )
+        .unwrap();
+        let cfg = Config::load(Some(cfg_file));
+        assert_eq!(cfg.saving_cpu_offline, Some(OfflineCores::KeepOnline(2)));
+        assert_eq!(
+            cfg.for_power_source(true).saving_cpu_offline,
+            Some(OfflineCores::List(vec![3]))
+        );
+    }
+
+    #[test]
+    fn test_thermal_from_file() {
+        let tmp = TempDir::new("cfg_thermal");
+        let cfg_file = tmp.join("cfg_thermal");
+        fs::write(
+            &cfg_file,
+            "THERMAL_TRIP_C=75\nTHERMAL_RELEASE_C=67.5\nTHERMAL_CAP=1GHz\nTHERMAL_ZONES=cpu-thermal\nTHERMAL_POLL_SEC=2\n",
+        )
+        .unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert_eq!(cfg.thermal.trip_millic, Some(75_000));
+        assert_eq!(cfg.thermal.release(), Some(67_500));
+        assert_eq!(cfg.thermal.cap, FreqSpec::Khz(1_000_000));
+        assert_eq!(cfg.thermal.zones, vec!["cpu-thermal".to_string()]);
+        assert_eq!(cfg.thermal.poll_interval, Duration::from_secs(2));
+
+        // a release point above the trip falls back to the default hysteresis
+        fs::write(
+            &cfg_file,
+            "THERMAL_TRIP_C=75\nTHERMAL_RELEASE_C=80\nTHERMAL_CAP=fast\n",
+        )
+        .unwrap();
+        let cfg = Config::load(Some(cfg_file));
+        assert_eq!(cfg.thermal.release(), Some(65_000));
+        assert_eq!(cfg.thermal.cap, FreqSpec::Percent(60));
+        assert!(!Config::default().thermal.enabled());
+    }
+
+    #[test]
+    fn test_devfreq_from_file() {
+        let tmp = TempDir::new("cfg_devfreq");
+        let cfg_file = tmp.join("cfg_devfreq");
+        fs::write(
+            &cfg_file,
+            "SAVING_DEVFREQ_MAX=250MHz\nSAVING_DEVFREQ_GOVERNOR=powersave\nDEVFREQ_DEVICES=fe000000.v3d, \n",
+        )
+        .unwrap();
+        let cfg = Config::load(Some(cfg_file.clone()));
+        assert_eq!(cfg.saving_devfreq_max, Some(FreqSpec::Khz(250_000)));
+        assert_eq!(cfg.saving_devfreq_governor.as_deref(), Some("powersave"));
+        assert_eq!(cfg.devfreq_devices, vec!["fe000000.v3d".to_string()]);
+
+        fs::write(&cfg_file, "SAVING_DEVFREQ_MAX=fast\n").unwrap();
+        let cfg = Config::load(Some(cfg_file));
+        assert_eq!(cfg.saving_devfreq_max, None);
+    }
+
     // env var override test removed due to global env mutation in tests
 }
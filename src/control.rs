@@ -0,0 +1,251 @@
+//! Control FIFO: lets other programs switch power modes and profiles, one command
+//! per line, e.g. `echo profile:deep > /run/uconsole-sleep/control` or
+//! `echo toggle > ...`. Only mode and profile actions are accepted; anything that
+//! runs commands or emits keys is refused, as is a FIFO others could write to.
+
+use std::ffi::CString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read};
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use crate::action::Action;
+use crate::error::Error;
+
+pub const CONTROL_PATH: &str = "/run/uconsole-sleep/control";
+/// Longest command line kept; anything longer is garbage
+const MAX_LINE: usize = 4096;
+
+pub struct ControlFifo {
+    file: File,
+    pub path: PathBuf,
+    partial: Vec<u8>,
+}
+
+impl ControlFifo {
+    /// Create the FIFO at `path` (and its directory) if needed and open it
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        match fs::metadata(path) {
+            Ok(m) if m.file_type().is_fifo() => {}
+            Ok(_) => {
+                return Err(Error::InvalidDevice(format!(
+                    "{} exists and is not a FIFO",
+                    path.display()
+                )));
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let c_path = CString::new(path.as_os_str().as_bytes())
+                    .map_err(|_| Error::InvalidValue(format!("bad path {}", path.display())))?;
+                if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } < 0 {
+                    return Err(Error::Io(format!(
+                        "mkfifo {} failed: {}",
+                        path.display(),
+                        std::io::Error::last_os_error()
+                    )));
+                }
+            }
+            Err(e) => return Err(Error::from(e)),
+        }
+        // Also opened for writing so the FIFO never reports EOF once a writer leaves
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC | libc::O_NOFOLLOW)
+            .open(path)
+            .map_err(|e| Error::Io(format!("failed to open {}: {}", path.display(), e)))?;
+        check_owner(&file, path)?;
+        Ok(ControlFifo {
+            file,
+            path: path.to_path_buf(),
+            partial: Vec::new(),
+        })
+    }
+
+    /// Read everything pending and parse the complete lines. Blank lines and `#`
+    /// comments are skipped.
+    pub fn read_commands(&mut self) -> Vec<Result<Action, Error>> {
+        let mut buf = [0u8; 512];
+        loop {
+            match self.file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => self.partial.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return vec![Err(Error::from(e))],
+            }
+        }
+        split_commands(&mut self.partial)
+    }
+}
+
+/// Refuse a FIFO that isn't ours or that others may write to: it runs actions as
+/// the daemon's user (root). Checked on the open file, so the path can't be
+/// swapped in between.
+fn check_owner(file: &File, path: &Path) -> Result<(), Error> {
+    let meta = file.metadata()?;
+    if !meta.file_type().is_fifo() {
+        return Err(Error::InvalidDevice(format!(
+            "{} is not a FIFO",
+            path.display()
+        )));
+    }
+    let euid = unsafe { libc::geteuid() };
+    if meta.uid() != euid {
+        return Err(Error::PermissionDenied(format!(
+            "{} is owned by uid {}, not {}",
+            path.display(),
+            meta.uid(),
+            euid
+        )));
+    }
+    let mode = meta.permissions().mode();
+    if mode & 0o022 != 0 {
+        return Err(Error::PermissionDenied(format!(
+            "{} is group or world writable (mode {:o})",
+            path.display(),
+            mode & 0o777
+        )));
+    }
+    Ok(())
+}
+
+/// Whether other programs may run `action` through the FIFO
+fn allowed(action: &Action) -> bool {
+    matches!(
+        action,
+        Action::ToggleSaving
+            | Action::EnterSaving
+            | Action::ExitSaving
+            | Action::Profile(_)
+            | Action::CycleProfile
+    )
+}
+
+/// Take the complete lines out of `buf` and parse them as actions
+fn split_commands(buf: &mut Vec<u8>) -> Vec<Result<Action, Error>> {
+    let Some(end) = buf.iter().rposition(|b| *b == b'\n') else {
+        if buf.len() > MAX_LINE {
+            buf.clear();
+            return vec![Err(Error::InvalidValue(
+                "control line too long".to_string(),
+            ))];
+        }
+        return Vec::new();
+    };
+    let lines: Vec<u8> = buf.drain(..=end).collect();
+    String::from_utf8_lossy(&lines)
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| {
+            let action: Action = l.parse()?;
+            if allowed(&action) {
+                Ok(action)
+            } else {
+                Err(Error::PermissionDenied(format!(
+                    "'{}' is not allowed over the control FIFO",
+                    action
+                )))
+            }
+        })
+        .collect()
+}
+
+impl AsFd for ControlFifo {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+impl Drop for ControlFifo {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::io::Write;
+
+    #[test]
+    fn test_split_commands() {
+        let mut buf =
+            b"toggle\n\n# comment\nprofile:Deep\nexplode\ncmd:sh\nkey:KEY_A\npoweroff\ncycle_pro"
+                .to_vec();
+        let cmds = split_commands(&mut buf);
+        assert_eq!(cmds.len(), 6);
+        // commands, keys and system actions are refused
+        for c in &cmds[3..] {
+            assert!(matches!(c, Err(Error::PermissionDenied(_))));
+        }
+        assert_eq!(cmds[0].as_ref().unwrap(), &Action::ToggleSaving);
+        assert_eq!(
+            cmds[1].as_ref().unwrap(),
+            &Action::Profile("deep".to_string())
+        );
+        assert!(cmds[2].is_err());
+        // the unfinished line waits for the rest
+        assert_eq!(buf, b"cycle_pro");
+        buf.extend_from_slice(b"file\n");
+        assert_eq!(
+            split_commands(&mut buf).pop().unwrap().unwrap(),
+            Action::CycleProfile
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_fifo_round_trip() {
+        let dir = TempDir::new("control");
+        let path = dir.join("control");
+        let mut fifo = match ControlFifo::open(&path) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("skipping FIFO test: {}", e);
+                return;
+            }
+        };
+        assert!(fifo.read_commands().is_empty());
+
+        let mut writer = OpenOptions::new().write(true).open(&path).unwrap();
+        writer.write_all(b"exit_saving\n").unwrap();
+        drop(writer);
+        let cmds = fifo.read_commands();
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0].as_ref().unwrap(), &Action::ExitSaving);
+        // no EOF spinning after the writer went away
+        assert!(fifo.read_commands().is_empty());
+
+        // reopening reuses the FIFO; dropping removes it
+        drop(fifo);
+        assert!(!path.exists());
+        fs::write(&path, "not a fifo").unwrap();
+        assert!(matches!(
+            ControlFifo::open(&path),
+            Err(Error::InvalidDevice(_))
+        ));
+
+        // a pre-existing FIFO others can write to is refused
+        fs::remove_file(&path).unwrap();
+        let fifo = ControlFifo::open(&path).unwrap();
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        drop(fifo);
+        let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o622)).unwrap();
+        assert!(matches!(
+            ControlFifo::open(&path),
+            Err(Error::PermissionDenied(_))
+        ));
+    }
+}
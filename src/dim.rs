@@ -0,0 +1,381 @@
+//! Backlight fades and the dim stage that precedes automatic power saving
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{JoinHandle, sleep, spawn};
+use std::time::Duration;
+
+use log::{debug, warn};
+
+use crate::error::Error;
+use crate::hardware::backlight;
+
+/// Time between brightness writes during a fade
+pub const FADE_STEP: Duration = Duration::from_millis(20);
+/// How long the display stays dimmed before saving mode, by default
+pub const DEFAULT_DIM_HOLD: Duration = Duration::from_secs(10);
+
+/// Shape of a fade over time
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FadeCurve {
+    #[default]
+    Linear,
+    /// Slow start and end (smoothstep)
+    Ease,
+    /// Even steps in perceived brightness, i.e. linear in log space
+    Exponential,
+}
+
+impl FromStr for FadeCurve {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "linear" => Ok(FadeCurve::Linear),
+            "ease" | "smooth" => Ok(FadeCurve::Ease),
+            "exp" | "exponential" => Ok(FadeCurve::Exponential),
+            other => Err(Error::InvalidValue(format!(
+                "unknown fade curve '{}'",
+                other
+            ))),
+        }
+    }
+}
+
+impl FadeCurve {
+    /// Brightness at progress `t` (0..=1) of a fade from `from` to `to`
+    pub fn level(self, from: u32, to: u32, t: f32) -> u32 {
+        let t = t.clamp(0.0, 1.0);
+        let (a, b) = (from as f32, to as f32);
+        let v = match self {
+            FadeCurve::Linear => a + (b - a) * t,
+            FadeCurve::Ease => a + (b - a) * t * t * (3.0 - 2.0 * t),
+            // +1 so fades to or from 0 stay finite
+            FadeCurve::Exponential => {
+                let (la, lb) = ((a + 1.0).ln(), (b + 1.0).ln());
+                (la + (lb - la) * t).exp() - 1.0
+            }
+        };
+        v.round().max(0.0) as u32
+    }
+
+    /// Brightness levels written over a fade of `duration`, ending exactly at `to`
+    pub fn levels(self, from: u32, to: u32, duration: Duration) -> Vec<u32> {
+        let steps = (duration.as_millis() / FADE_STEP.as_millis()).max(1) as usize;
+        let mut levels: Vec<u32> = (1..=steps)
+            .map(|i| self.level(from, to, i as f32 / steps as f32))
+            .collect();
+        levels.dedup();
+        levels
+    }
+}
+
+/// Fade and dim stage settings
+#[derive(Clone, Debug, PartialEq)]
+pub struct DimConfig {
+    /// Brightness of the dim stage in percent of max; `None` skips the dim stage
+    pub percent: Option<u8>,
+    /// How long to stay dimmed before entering saving mode
+    pub hold: Duration,
+    /// Fade duration; zero switches brightness instantly and skips fade-outs
+    pub fade: Duration,
+    pub curve: FadeCurve,
+}
+
+impl Default for DimConfig {
+    fn default() -> Self {
+        DimConfig {
+            percent: None,
+            hold: DEFAULT_DIM_HOLD,
+            fade: Duration::ZERO,
+            curve: FadeCurve::Linear,
+        }
+    }
+}
+
+/// Step the backlight from `from` to `to`. Returns false if `cancel` was set
+/// before the fade completed.
+pub fn fade(
+    path: &Path,
+    from: u32,
+    to: u32,
+    duration: Duration,
+    curve: FadeCurve,
+    cancel: &AtomicBool,
+) -> Result<bool, Error> {
+    for level in curve.levels(from, to, duration) {
+        if cancel.load(Ordering::Relaxed) {
+            return Ok(false);
+        }
+        backlight::set_brightness(path, level)?;
+        if !duration.is_zero() {
+            sleep(FADE_STEP);
+        }
+    }
+    Ok(true)
+}
+
+#[derive(Default)]
+struct DimState {
+    /// Brightness before the first dim/fade-out, to return to later
+    original: Option<u32>,
+    dimmed: bool,
+    /// Faded to black; the display was switched off and any later fade starts at 0
+    faded_out: bool,
+    running: Option<(Arc<AtomicBool>, JoinHandle<()>)>,
+}
+
+/// Owns backlight fades so that only one runs at a time and a newer fade (or an
+/// input event cancelling the dim stage) cleanly stops the previous one. Shared
+/// between the event loop and the mode switching threads.
+pub struct Dimmer {
+    pub config: DimConfig,
+    backlight: Option<PathBuf>,
+    dry_run: bool,
+    state: Mutex<DimState>,
+}
+
+impl Dimmer {
+    pub fn new(config: DimConfig, backlight: Option<PathBuf>, dry_run: bool) -> Self {
+        Dimmer {
+            config,
+            backlight,
+            dry_run,
+            state: Mutex::new(DimState::default()),
+        }
+    }
+
+    /// The display is dimmed (or faded out) and not yet restored
+    pub fn is_dimmed(&self) -> bool {
+        self.state.lock().unwrap().dimmed
+    }
+
+    fn stop_running(state: &mut DimState) {
+        if let Some((cancel, handle)) = state.running.take() {
+            cancel.store(true, Ordering::Relaxed);
+            let _ = handle.join();
+        }
+    }
+
+    /// The backlight was faded to black and hasn't been brought back yet
+    pub fn faded_out(&self) -> bool {
+        self.state.lock().unwrap().faded_out
+    }
+
+    /// Brightness from before dimming or fading out, while the display is dimmed
+    pub fn original(&self) -> Option<u32> {
+        self.state.lock().unwrap().original
+    }
+
+    /// Brightness a fade starts from: 0 after a fade-out, which the display is
+    /// switched back on at
+    fn current(state: &mut DimState, path: &Path) -> Result<u32, Error> {
+        if std::mem::take(&mut state.faded_out) {
+            return Ok(0);
+        }
+        backlight::get_brightness(path)
+    }
+
+    /// Stop any fade, remember the original brightness and return the current one
+    fn begin(&self, state: &mut DimState, path: &Path) -> Result<u32, Error> {
+        Self::stop_running(state);
+        let current = Self::current(state, path)?;
+        if state.original.is_none() {
+            state.original = Some(current);
+        }
+        state.dimmed = true;
+        Ok(current)
+    }
+
+    fn spawn_fade(&self, state: &mut DimState, path: PathBuf, from: u32, to: u32) {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&cancel);
+        let (duration, curve) = (self.config.fade, self.config.curve);
+        let handle = spawn(move || {
+            if let Err(e) = fade(&path, from, to, duration, curve, &flag) {
+                warn!("Backlight fade failed: {}", e);
+            }
+        });
+        state.running = Some((cancel, handle));
+    }
+
+    /// A dim stage is configured and there is a backlight to dim
+    pub fn enabled(&self) -> bool {
+        self.config.percent.is_some() && self.backlight.is_some()
+    }
+
+    /// Start fading down to the dim level in the background
+    pub fn dim(&self) {
+        let (Some(percent), Some(path)) = (self.config.percent, self.backlight.clone()) else {
+            return;
+        };
+        let mut state = self.state.lock().unwrap();
+        // the stage runs its course even if the backlight can't be written
+        state.dimmed = true;
+        if self.dry_run {
+            debug!("DRY-RUN: dim to {}% skipped", percent);
+            return;
+        }
+        let (from, target) = match (
+            self.begin(&mut state, &path),
+            backlight::get_max_brightness(&path),
+        ) {
+            (Ok(current), Ok(max)) => (current, max * percent.min(100) as u32 / 100),
+            (Err(e), _) | (_, Err(e)) => {
+                warn!("Cannot dim backlight: {}", e);
+                return;
+            }
+        };
+        // never brighter than before dimming; the display may be faded out right now
+        let to = target.min(state.original.unwrap_or(from));
+        self.spawn_fade(&mut state, path, from, to);
+    }
+
+    /// Fade to black and wait for it, right before the display is switched off.
+    /// Does nothing when fades are disabled.
+    pub fn fade_out(&self) {
+        let Some(path) = self.backlight.clone() else {
+            return;
+        };
+        if self.config.fade.is_zero() {
+            return;
+        }
+        if self.dry_run {
+            debug!("DRY-RUN: fade out skipped");
+            return;
+        }
+        let from = match self.begin(&mut self.state.lock().unwrap(), &path) {
+            Ok(b) => b,
+            Err(e) => {
+                warn!("Cannot fade out backlight: {}", e);
+                return;
+            }
+        };
+        // without the state lock, so `is_dimmed` doesn't stall the event loop
+        let never = AtomicBool::new(false);
+        if let Err(e) = fade(&path, from, 0, self.config.fade, self.config.curve, &never) {
+            warn!("Backlight fade failed: {}", e);
+        }
+        self.state.lock().unwrap().faded_out = true;
+    }
+
+    /// Fade back (in the background) to the brightness from before dimming or
+    /// fading out. Does nothing if the display wasn't dimmed.
+    pub fn restore(&self) {
+        let mut state = self.state.lock().unwrap();
+        Self::stop_running(&mut state);
+        state.dimmed = false;
+        let (Some(original), Some(path)) = (state.original.take(), self.backlight.clone()) else {
+            return;
+        };
+        if self.dry_run {
+            debug!("DRY-RUN: restore brightness {} skipped", original);
+            return;
+        }
+        match Self::current(&mut state, &path) {
+            Ok(current) => self.spawn_fade(&mut state, path, current, original),
+            Err(e) => warn!("Cannot restore backlight: {}", e),
+        }
+    }
+
+    /// Wait for a background fade to finish (used by tests)
+    pub fn wait(&self) {
+        if let Some((_, handle)) = self.state.lock().unwrap().running.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::fs;
+
+    fn fake_backlight(name: &str, brightness: u32) -> TempDir {
+        let dir = TempDir::new(&format!("dim_{}", name));
+        fs::write(dir.join("brightness"), format!("{}\n", brightness)).unwrap();
+        fs::write(dir.join("max_brightness"), "200\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_parse_curve() {
+        assert_eq!("linear".parse::<FadeCurve>().unwrap(), FadeCurve::Linear);
+        assert_eq!("Ease".parse::<FadeCurve>().unwrap(), FadeCurve::Ease);
+        assert_eq!("exp".parse::<FadeCurve>().unwrap(), FadeCurve::Exponential);
+        assert!("bounce".parse::<FadeCurve>().is_err());
+    }
+
+    #[test]
+    fn test_curves() {
+        for curve in [FadeCurve::Linear, FadeCurve::Ease, FadeCurve::Exponential] {
+            assert_eq!(curve.level(200, 20, 0.0), 200);
+            assert_eq!(curve.level(200, 20, 1.0), 20);
+            let levels = curve.levels(200, 20, Duration::from_millis(200));
+            assert_eq!(*levels.last().unwrap(), 20);
+            assert!(levels.windows(2).all(|w| w[0] > w[1]));
+        }
+        assert_eq!(FadeCurve::Linear.level(0, 100, 0.5), 50);
+        // ease starts slower than linear, exponential drops fastest at the top
+        assert!(FadeCurve::Ease.level(0, 100, 0.2) < 20);
+        assert!(FadeCurve::Exponential.level(200, 0, 0.5) < 100);
+        // a zero duration is a single jump
+        assert_eq!(FadeCurve::Linear.levels(200, 20, Duration::ZERO), vec![20]);
+    }
+
+    #[test]
+    fn test_cancelled_fade_stops_early() {
+        let bl = fake_backlight("cancel", 200);
+        let cancel = AtomicBool::new(true);
+        let done = fade(
+            &bl,
+            200,
+            0,
+            Duration::from_millis(100),
+            FadeCurve::Linear,
+            &cancel,
+        )
+        .unwrap();
+        assert!(!done);
+        assert_eq!(backlight::get_brightness(&bl).unwrap(), 200);
+    }
+
+    #[test]
+    fn test_dim_and_restore() {
+        let bl = fake_backlight("restore", 180);
+        let dimmer = Dimmer::new(
+            DimConfig {
+                percent: Some(25),
+                fade: Duration::from_millis(60),
+                ..Default::default()
+            },
+            Some(bl.to_path_buf()),
+            false,
+        );
+        dimmer.dim();
+        assert!(dimmer.is_dimmed());
+        dimmer.wait();
+        assert_eq!(backlight::get_brightness(&bl).unwrap(), 50);
+
+        // saving mode fades the rest of the way; exit returns to the original level
+        dimmer.fade_out();
+        assert_eq!(backlight::get_brightness(&bl).unwrap(), 0);
+        assert_eq!(dimmer.original(), Some(180));
+        // switching the display back on writes the level recorded before the fade;
+        // the fade in still starts from black
+        backlight::set_brightness(&bl, 180).unwrap();
+        dimmer.restore();
+        assert!(!dimmer.is_dimmed());
+        dimmer.wait();
+        assert_eq!(backlight::get_brightness(&bl).unwrap(), 180);
+
+        // nothing to restore a second time
+        backlight::set_brightness(&bl, 90).unwrap();
+        dimmer.restore();
+        dimmer.wait();
+        assert_eq!(backlight::get_brightness(&bl).unwrap(), 90);
+    }
+}
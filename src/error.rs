@@ -15,6 +15,8 @@ pub enum Error {
     InvalidDevice(String),
     /// Permission denied
     PermissionDenied(String),
+    /// Invalid configuration or CLI value
+    InvalidValue(String),
 }
 
 impl fmt::Display for Error {
@@ -24,6 +26,7 @@ impl fmt::Display for Error {
             Error::NotFound(msg) => write!(f, "Not found: {}", msg),
             Error::InvalidDevice(msg) => write!(f, "Invalid device: {}", msg),
             Error::PermissionDenied(msg) => write!(f, "Permission denied: {}", msg),
+            Error::InvalidValue(msg) => write!(f, "Invalid value: {}", msg),
         }
     }
 }
@@ -61,4 +64,10 @@ mod tests {
         let err = Error::PermissionDenied("access denied".to_string());
         assert_eq!(err.to_string(), "Permission denied: access denied");
     }
+
+    #[test]
+    fn test_error_display_invalid_value() {
+        let err = Error::InvalidValue("bad action".to_string());
+        assert_eq!(err.to_string(), "Invalid value: bad action");
+    }
 }
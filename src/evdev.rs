@@ -0,0 +1,369 @@
+//! Decoding of the evdev event stream: both `struct input_event` layouts, batches
+//! of events per read, monotonic timestamps and key state resync after the kernel
+//! dropped events (SYN_DROPPED).
+
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
+
+use nix::time::{ClockId, clock_gettime};
+
+use crate::input::{EV_KEY, EV_SYN, InputEvent};
+
+pub const SYN_REPORT: u16 = 0;
+pub const SYN_DROPPED: u16 = 3;
+
+/// Events requested per `read()`
+pub const READ_BATCH: usize = 64;
+
+/// Memory layout of `struct input_event`, which depends on the width of
+/// `__kernel_ulong_t` used for the timestamp
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// 64-bit seconds and microseconds: 24 bytes (aarch64, x86_64)
+    Wide,
+    /// 32-bit seconds and microseconds: 16 bytes (32-bit ARM, also with 64-bit time_t)
+    Compact,
+}
+
+impl Layout {
+    /// Layout used by the kernel for this build's target
+    pub const NATIVE: Layout = if size_of::<libc::c_ulong>() == 8 {
+        Layout::Wide
+    } else {
+        Layout::Compact
+    };
+
+    pub const fn size(self) -> usize {
+        match self {
+            Layout::Wide => 24,
+            Layout::Compact => 16,
+        }
+    }
+
+    /// Decode one event from the start of `buf`, which must hold at least
+    /// [`Layout::size`] bytes
+    pub fn decode(self, buf: &[u8]) -> InputEvent {
+        let (sec, usec, rest) = match self {
+            Layout::Wide => (
+                u64::from_ne_bytes(buf[0..8].try_into().unwrap()) as i64,
+                u64::from_ne_bytes(buf[8..16].try_into().unwrap()) as i64,
+                &buf[16..24],
+            ),
+            Layout::Compact => (
+                u32::from_ne_bytes(buf[0..4].try_into().unwrap()) as i64,
+                u32::from_ne_bytes(buf[4..8].try_into().unwrap()) as i64,
+                &buf[8..16],
+            ),
+        };
+        InputEvent {
+            sec,
+            usec,
+            etype: u16::from_ne_bytes(rest[0..2].try_into().unwrap()),
+            code: u16::from_ne_bytes(rest[2..4].try_into().unwrap()),
+            value: i32::from_ne_bytes(rest[4..8].try_into().unwrap()),
+        }
+    }
+
+    /// Encode an event in this layout, e.g. for writing to uinput
+    pub fn encode(self, ev: &InputEvent) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.size());
+        match self {
+            Layout::Wide => {
+                buf.extend_from_slice(&(ev.sec as u64).to_ne_bytes());
+                buf.extend_from_slice(&(ev.usec as u64).to_ne_bytes());
+            }
+            Layout::Compact => {
+                buf.extend_from_slice(&(ev.sec as u32).to_ne_bytes());
+                buf.extend_from_slice(&(ev.usec as u32).to_ne_bytes());
+            }
+        }
+        buf.extend_from_slice(&ev.etype.to_ne_bytes());
+        buf.extend_from_slice(&ev.code.to_ne_bytes());
+        buf.extend_from_slice(&ev.value.to_ne_bytes());
+        buf
+    }
+}
+
+/// Turns the bytes read from one device into events. Tracks the keys that device
+/// holds so the state can be repaired after the kernel's buffer overflowed.
+#[derive(Clone, Debug)]
+pub struct Decoder {
+    pub layout: Layout,
+    /// Trailing bytes of an event split across reads
+    partial: Vec<u8>,
+    /// SYN_DROPPED seen: discard events up to the next SYN_REPORT
+    dropping: bool,
+    needs_resync: bool,
+    pressed: BTreeSet<u16>,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder::new(Layout::NATIVE)
+    }
+}
+
+impl Decoder {
+    pub fn new(layout: Layout) -> Self {
+        Decoder {
+            layout,
+            partial: Vec::new(),
+            dropping: false,
+            needs_resync: false,
+            pressed: BTreeSet::new(),
+        }
+    }
+
+    /// Decode a chunk of bytes. After a SYN_DROPPED, events are discarded until
+    /// the packet is over; then [`Decoder::needs_resync`] turns true and the
+    /// caller should pass the device's current key bitmap to [`Decoder::resync`].
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<InputEvent> {
+        let size = self.layout.size();
+        let mut data = std::mem::take(&mut self.partial);
+        data.extend_from_slice(bytes);
+
+        let mut events = Vec::with_capacity(data.len() / size);
+        let mut chunks = data.chunks_exact(size);
+        for chunk in chunks.by_ref() {
+            let ev = self.layout.decode(chunk);
+            if ev.etype == EV_SYN && ev.code == SYN_DROPPED {
+                self.dropping = true;
+                continue;
+            }
+            if self.dropping {
+                if ev.etype == EV_SYN && ev.code == SYN_REPORT {
+                    // What follows is decoded as usual; the key state queried for
+                    // the resync already includes it
+                    self.dropping = false;
+                    self.needs_resync = true;
+                }
+                continue;
+            }
+            if ev.etype == EV_KEY {
+                match ev.value {
+                    1 => {
+                        self.pressed.insert(ev.code);
+                    }
+                    0 => {
+                        self.pressed.remove(&ev.code);
+                    }
+                    _ => {}
+                }
+            }
+            events.push(ev);
+        }
+        self.partial = chunks.remainder().to_vec();
+        events
+    }
+
+    pub fn needs_resync(&self) -> bool {
+        self.needs_resync
+    }
+
+    /// Compare the keys we believe are held with the EVIOCGKEY bitmap and return
+    /// synthetic key events (stamped `sec`/`usec`) that bring listeners in line
+    pub fn resync(&mut self, key_bits: &[u8], sec: i64, usec: i64) -> Vec<InputEvent> {
+        self.needs_resync = false;
+        let is_set = |code: u16| {
+            let byte = code as usize / 8;
+            byte < key_bits.len() && key_bits[byte] & (1 << (code % 8)) != 0
+        };
+        let actual: BTreeSet<u16> = (0..(key_bits.len() * 8) as u16)
+            .filter(|c| is_set(*c))
+            .collect();
+        let event = |code, value| InputEvent {
+            sec,
+            usec,
+            etype: EV_KEY,
+            code,
+            value,
+        };
+        let mut events: Vec<InputEvent> = self
+            .pressed
+            .difference(&actual)
+            .map(|c| event(*c, 0))
+            .chain(actual.difference(&self.pressed).map(|c| event(*c, 1)))
+            .collect();
+        if !events.is_empty() {
+            events.push(InputEvent {
+                etype: EV_SYN,
+                code: SYN_REPORT,
+                value: 0,
+                ..event(0, 0)
+            });
+        }
+        self.pressed = actual;
+        events
+    }
+}
+
+/// Current CLOCK_MONOTONIC time as evdev timestamp fields
+pub fn monotonic_now() -> (i64, i64) {
+    let d = monotonic_elapsed();
+    (d.as_secs() as i64, d.subsec_micros() as i64)
+}
+
+fn monotonic_elapsed() -> Duration {
+    clock_gettime(ClockId::CLOCK_MONOTONIC)
+        .map(Duration::from)
+        .unwrap_or_default()
+}
+
+/// Map a CLOCK_MONOTONIC event timestamp onto `Instant`, which counts on the same
+/// clock on Linux but can't be built from raw values. Timestamps from the future
+/// (or nonsense) map to now.
+pub fn monotonic_instant(sec: i64, usec: i64) -> Instant {
+    let now = Instant::now();
+    if sec < 0 || !(0..1_000_000).contains(&usec) {
+        return now;
+    }
+    let stamp = Duration::new(sec as u64, usec as u32 * 1000);
+    monotonic_elapsed()
+        .checked_sub(stamp)
+        .and_then(|age| now.checked_sub(age))
+        .unwrap_or(now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wide(sec: i64, usec: i64, etype: u16, code: u16, value: i32) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&sec.to_ne_bytes());
+        buf.extend_from_slice(&usec.to_ne_bytes());
+        buf.extend_from_slice(&etype.to_ne_bytes());
+        buf.extend_from_slice(&code.to_ne_bytes());
+        buf.extend_from_slice(&value.to_ne_bytes());
+        buf
+    }
+
+    fn compact(sec: u32, usec: u32, etype: u16, code: u16, value: i32) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&sec.to_ne_bytes());
+        buf.extend_from_slice(&usec.to_ne_bytes());
+        buf.extend_from_slice(&etype.to_ne_bytes());
+        buf.extend_from_slice(&code.to_ne_bytes());
+        buf.extend_from_slice(&value.to_ne_bytes());
+        buf
+    }
+
+    fn key(code: u16, value: i32) -> (u16, u16, i32) {
+        (EV_KEY, code, value)
+    }
+
+    fn summary(events: &[InputEvent]) -> Vec<(u16, u16, i32)> {
+        events.iter().map(|e| (e.etype, e.code, e.value)).collect()
+    }
+
+    #[test]
+    fn test_decode_wide_layout() {
+        let buf = wide(5, 250, EV_KEY, 116, 1);
+        assert_eq!(buf.len(), Layout::Wide.size());
+        assert_eq!(
+            Layout::Wide.decode(&buf),
+            InputEvent {
+                sec: 5,
+                usec: 250,
+                etype: EV_KEY,
+                code: 116,
+                value: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_decode_compact_layout() {
+        let mut bytes = compact(7, 999_999, EV_KEY, 116, 0);
+        bytes.extend(compact(7, 999_999, EV_SYN, SYN_REPORT, 0));
+        assert_eq!(bytes.len(), 2 * Layout::Compact.size());
+        let events = Decoder::new(Layout::Compact).feed(&bytes);
+        assert_eq!(events[0].sec, 7);
+        assert_eq!(events[0].usec, 999_999);
+        assert_eq!(summary(&events), vec![key(116, 0), (EV_SYN, SYN_REPORT, 0)]);
+    }
+
+    #[test]
+    fn test_encode_round_trips() {
+        let ev = InputEvent {
+            sec: 12,
+            usec: 34,
+            etype: EV_KEY,
+            code: 116,
+            value: -1,
+        };
+        for layout in [Layout::Wide, Layout::Compact] {
+            let buf = layout.encode(&ev);
+            assert_eq!(buf.len(), layout.size());
+            assert_eq!(layout.decode(&buf), ev);
+        }
+        assert_eq!(Layout::Wide.encode(&ev), wide(12, 34, EV_KEY, 116, -1));
+    }
+
+    #[test]
+    fn test_native_layout_matches_target() {
+        let expected = if cfg!(target_pointer_width = "64") {
+            24
+        } else {
+            16
+        };
+        assert_eq!(Layout::NATIVE.size(), expected);
+    }
+
+    #[test]
+    fn test_events_split_across_reads() {
+        let mut d = Decoder::new(Layout::Wide);
+        let mut bytes = wide(1, 0, EV_KEY, 30, 1);
+        bytes.extend(wide(1, 0, EV_KEY, 30, 0));
+        assert_eq!(summary(&d.feed(&bytes[..30])), vec![key(30, 1)]);
+        assert_eq!(summary(&d.feed(&bytes[30..])), vec![key(30, 0)]);
+    }
+
+    #[test]
+    fn test_syn_dropped_resync() {
+        let mut d = Decoder::new(Layout::Wide);
+        let mut bytes = wide(1, 0, EV_KEY, 116, 1);
+        bytes.extend(wide(1, 0, EV_KEY, 29, 1));
+        bytes.extend(wide(1, 0, EV_SYN, SYN_REPORT, 0));
+        assert_eq!(d.feed(&bytes).len(), 3);
+        assert!(!d.needs_resync());
+
+        // the release of 116 and press of 30 are lost in the overflow
+        let mut bytes = wide(2, 0, EV_SYN, SYN_DROPPED, 0);
+        bytes.extend(wide(2, 0, EV_KEY, 30, 1));
+        bytes.extend(wide(2, 0, EV_SYN, SYN_REPORT, 0));
+        // the rest of the batch still comes through, a split event included
+        bytes.extend(wide(2, 5, EV_KEY, 31, 1));
+        let split = wide(2, 5, EV_KEY, 32, 1);
+        bytes.extend(&split[..10]);
+        assert_eq!(summary(&d.feed(&bytes)), vec![key(31, 1)]);
+        assert!(d.needs_resync());
+        assert_eq!(summary(&d.feed(&split[10..])), vec![key(32, 1)]);
+
+        let mut bits = vec![0u8; 96];
+        for code in [29u16, 30, 31, 32] {
+            bits[code as usize / 8] |= 1 << (code % 8);
+        }
+        let events = d.resync(&bits, 3, 0);
+        assert!(!d.needs_resync());
+        assert_eq!(
+            summary(&events),
+            vec![key(116, 0), key(30, 1), (EV_SYN, SYN_REPORT, 0)]
+        );
+        assert!(events.iter().all(|e| e.sec == 3));
+        // in sync now: nothing more to report
+        assert!(d.resync(&bits, 4, 0).is_empty());
+    }
+
+    #[test]
+    fn test_monotonic_instant() {
+        let (sec, usec) = monotonic_now();
+        let stamp = Duration::new(sec as u64, usec as u32 * 1000) - Duration::from_millis(300);
+        let t = monotonic_instant(stamp.as_secs() as i64, stamp.subsec_micros() as i64);
+        let age = Instant::now().duration_since(t);
+        assert!(age >= Duration::from_millis(299) && age < Duration::from_secs(2));
+        // a timestamp from the future is clamped to now
+        let t = monotonic_instant(sec + 100, 0);
+        assert!(Instant::now().duration_since(t) < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,152 @@
+//! Short visual feedback pulses, e.g. to tell the user a hold has registered
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+
+use log::{debug, warn};
+
+use crate::error::Error;
+use crate::hardware::backlight;
+
+/// How long a feedback pulse lasts
+pub const PULSE_DURATION: Duration = Duration::from_millis(150);
+
+/// Kind of feedback given when a hold is recognized
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Feedback {
+    #[default]
+    None,
+    /// Briefly flip the backlight power state (off when on, on when off)
+    Backlight,
+    /// Briefly light an LED under `/sys/class/leds`
+    Led(PathBuf),
+}
+
+impl FromStr for Feedback {
+    type Err = Error;
+
+    /// Parse `none`, `backlight` or `led:<name or path>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(led) = s.strip_prefix("led:") {
+            let led = led.trim();
+            if led.is_empty() {
+                return Err(Error::InvalidValue("empty LED name".to_string()));
+            }
+            let path = if led.contains('/') {
+                PathBuf::from(led)
+            } else {
+                Path::new("/sys/class/leds").join(led)
+            };
+            return Ok(Feedback::Led(path));
+        }
+        match s.to_ascii_lowercase().as_str() {
+            "" | "none" => Ok(Feedback::None),
+            "backlight" => Ok(Feedback::Backlight),
+            other => Err(Error::InvalidValue(format!("unknown feedback '{}'", other))),
+        }
+    }
+}
+
+/// Write `value` to `path/attr`, wait `duration`, then put the previous value back.
+pub fn pulse_attr(path: &Path, attr: &str, value: &str, duration: Duration) -> Result<(), Error> {
+    let file = path.join(attr);
+    let previous = fs::read_to_string(&file)?;
+    fs::write(&file, value)?;
+    sleep(duration);
+    fs::write(&file, previous.trim())?;
+    Ok(())
+}
+
+impl Feedback {
+    /// Give the feedback pulse on a background thread, then run `then` on it. The
+    /// pulse puts the old value back, so anything that may touch the same attribute
+    /// (a mode switch turning the display off) has to go in `then`.
+    pub fn pulse_then(&self, dry_run: bool, then: impl FnOnce() + Send + 'static) {
+        if *self == Feedback::None {
+            then();
+            return;
+        }
+        if dry_run {
+            debug!("DRY-RUN: feedback pulse {:?} skipped", self);
+            then();
+            return;
+        }
+        let fb = self.clone();
+        spawn(move || {
+            if let Err(e) = fb.pulse_blocking() {
+                warn!("Feedback pulse failed: {}", e);
+            }
+            then();
+        });
+    }
+
+    fn pulse_blocking(&self) -> Result<(), Error> {
+        match self {
+            Feedback::None => Ok(()),
+            Feedback::Backlight => {
+                let path = backlight::find_backlight()?
+                    .ok_or_else(|| Error::NotFound("backlight".to_string()))?;
+                let current = fs::read_to_string(path.join("bl_power"))?;
+                let flipped = if current.trim() == "0" { "4" } else { "0" };
+                pulse_attr(&path, "bl_power", flipped, PULSE_DURATION)
+            }
+            Feedback::Led(path) => {
+                let max = fs::read_to_string(path.join("max_brightness"))
+                    .map(|s| s.trim().to_string())
+                    .unwrap_or_else(|_| "1".to_string());
+                pulse_attr(path, "brightness", &max, PULSE_DURATION)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_parse_feedback() {
+        assert_eq!("none".parse::<Feedback>().unwrap(), Feedback::None);
+        assert_eq!(
+            "Backlight".parse::<Feedback>().unwrap(),
+            Feedback::Backlight
+        );
+        assert_eq!(
+            "led:ACT".parse::<Feedback>().unwrap(),
+            Feedback::Led(PathBuf::from("/sys/class/leds/ACT"))
+        );
+        assert_eq!(
+            "led:/tmp/leds/x".parse::<Feedback>().unwrap(),
+            Feedback::Led(PathBuf::from("/tmp/leds/x"))
+        );
+        assert!("led:".parse::<Feedback>().is_err());
+        assert!("buzzer".parse::<Feedback>().is_err());
+    }
+
+    #[test]
+    fn test_led_pulse_restores_brightness() {
+        let tmp = TempDir::new("led");
+        fs::write(tmp.join("brightness"), "0\n").unwrap();
+        fs::write(tmp.join("max_brightness"), "255\n").unwrap();
+        Feedback::Led(tmp.to_path_buf()).pulse_blocking().unwrap();
+        assert_eq!(fs::read_to_string(tmp.join("brightness")).unwrap(), "0");
+    }
+
+    #[test]
+    fn test_action_runs_after_pulse() {
+        let tmp = TempDir::new("led_then");
+        fs::write(tmp.join("brightness"), "0\n").unwrap();
+        fs::write(tmp.join("max_brightness"), "255\n").unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let file = tmp.join("brightness");
+        Feedback::Led(tmp.to_path_buf()).pulse_then(false, move || {
+            tx.send(fs::read_to_string(file).unwrap()).unwrap();
+        });
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), "0");
+    }
+}
@@ -0,0 +1,648 @@
+//! Power key gesture recognition
+//!
+//! Turns the raw key-down/key-up stream of the power key into gestures (single,
+//! double or triple tap, press-and-hold) and maps them to actions through a binding
+//! table. All timing is driven by an injected `now` so the logic stays deterministic.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::action::Action;
+use crate::error::Error;
+use crate::power_mode::PowerMode;
+
+pub use crate::input::EV_KEY;
+
+// input_event classification constants
+pub const KEY_POWER: u16 = 116;
+
+/// Default maximum gap between a release and the next press of a multi-tap sequence
+pub const DEFAULT_TAP_WINDOW: Duration = Duration::from_millis(400);
+
+/// Outcome of classifying a single input_event for the power key.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum KeyDecision {
+    /// Power key was released after a press shorter than the hold threshold.
+    ShortPress,
+    /// Power key was released after a press at/longer than the hold threshold.
+    LongPress,
+    /// Event is not actionable yet (key-down, auto-repeat, non-power key, orphan key-up).
+    Continue,
+}
+
+/// Result of classifying an event: the decision plus the timestamp state to carry forward.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct KeyEventResult {
+    pub decision: KeyDecision,
+    /// New value for `last_key_down_timestamp` after this event.
+    pub last_key_down: Option<Instant>,
+}
+
+/// Pure classification of a raw input_event into a decision for the trigger `key`
+/// (normally `KEY_POWER`).
+///
+/// `now` is passed in (rather than read from the clock) so the logic is deterministic and
+/// unit-testable. A key-down (value 1) records the press time; a key-up (value 0)
+/// resolves to a short or long press based on the elapsed time since the last key-down.
+pub fn classify_key_event(
+    last_key_down: Option<Instant>,
+    key: u16,
+    etype: u16,
+    code: u16,
+    value: i32,
+    hold_trigger: Duration,
+    now: Instant,
+) -> KeyEventResult {
+    // Ignore everything that isn't the trigger key.
+    if etype != EV_KEY || code != key {
+        return KeyEventResult {
+            decision: KeyDecision::Continue,
+            last_key_down,
+        };
+    }
+    match value {
+        // key-down: remember when the press started
+        1 => KeyEventResult {
+            decision: KeyDecision::Continue,
+            last_key_down: Some(now),
+        },
+        // key-up: resolve short vs. long press and clear the press time
+        0 => {
+            let decision = match last_key_down {
+                Some(down_ts) if now.duration_since(down_ts) < hold_trigger => {
+                    KeyDecision::ShortPress
+                }
+                Some(_) => KeyDecision::LongPress,
+                // No preceding key-down (e.g. missed event): don't trigger a toggle.
+                None => KeyDecision::Continue,
+            };
+            KeyEventResult {
+                decision,
+                last_key_down: None,
+            }
+        }
+        // auto-repeat (value 2) or anything else: no state change
+        _ => KeyEventResult {
+            decision: KeyDecision::Continue,
+            last_key_down,
+        },
+    }
+}
+
+/// A recognized power key gesture
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Gesture {
+    /// `n` short presses in a row, each started within the tap window of the last release
+    Tap(u8),
+    /// A press held for at least the hold threshold
+    Hold,
+}
+
+/// Highest tap count that can be bound
+pub const MAX_TAPS: u8 = 3;
+
+impl FromStr for Gesture {
+    type Err = Error;
+
+    /// Parse `TAP`, `DOUBLE_TAP`, `TRIPLE_TAP` or `HOLD` (case-insensitive)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "TAP" => Ok(Gesture::Tap(1)),
+            "DOUBLE_TAP" => Ok(Gesture::Tap(2)),
+            "TRIPLE_TAP" => Ok(Gesture::Tap(3)),
+            "HOLD" => Ok(Gesture::Hold),
+            other => Err(Error::InvalidValue(format!("unknown gesture '{}'", other))),
+        }
+    }
+}
+
+impl fmt::Display for Gesture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Gesture::Tap(1) => write!(f, "tap"),
+            Gesture::Tap(2) => write!(f, "double_tap"),
+            Gesture::Tap(3) => write!(f, "triple_tap"),
+            Gesture::Tap(n) => write!(f, "{}x_tap", n),
+            Gesture::Hold => write!(f, "hold"),
+        }
+    }
+}
+
+/// Stateful recognizer for taps, multi-taps and holds on the power key.
+///
+/// A short press does not resolve immediately: the recognizer waits up to the tap
+/// window for another press, unless `max_taps` has been reached. A hold fires as soon
+/// as the key has been down for `hold_trigger`, without waiting for the release.
+/// Callers must call [`GestureRecognizer::poll`] once [`GestureRecognizer::deadline`]
+/// has passed to flush a pending tap sequence or fire a hold.
+#[derive(Clone, Debug)]
+pub struct GestureRecognizer {
+    /// Key code the gestures are made with
+    pub key: u16,
+    pub hold_trigger: Duration,
+    pub tap_window: Duration,
+    max_taps: u8,
+    last_key_down: Option<Instant>,
+    taps: u8,
+    last_release: Option<Instant>,
+    /// The current press already produced a hold via `poll`, or was cancelled
+    hold_fired: bool,
+}
+
+impl GestureRecognizer {
+    pub fn new(hold_trigger: Duration, tap_window: Duration) -> Self {
+        GestureRecognizer {
+            key: KEY_POWER,
+            hold_trigger,
+            tap_window,
+            max_taps: 1,
+            last_key_down: None,
+            taps: 0,
+            last_release: None,
+            hold_fired: false,
+        }
+    }
+
+    /// Highest tap count worth waiting for. With 1 a single tap fires on release.
+    pub fn set_max_taps(&mut self, max_taps: u8) {
+        self.max_taps = max_taps.clamp(1, MAX_TAPS);
+    }
+
+    /// Whether the power key is currently held down
+    pub fn is_pressed(&self) -> bool {
+        self.last_key_down.is_some()
+    }
+
+    /// Treat the current press as consumed elsewhere (e.g. by a chord): it will not
+    /// produce a hold, and its release will not count as a tap.
+    pub fn cancel_press(&mut self) {
+        if self.last_key_down.is_some() {
+            self.hold_fired = true;
+        }
+        self.taps = 0;
+        self.last_release = None;
+    }
+
+    /// Feed one input_event. Returns a gesture if this event completes one, or if a
+    /// pending tap sequence expired before this press started.
+    pub fn on_event(&mut self, etype: u16, code: u16, value: i32, now: Instant) -> Option<Gesture> {
+        let result = classify_key_event(
+            self.last_key_down,
+            self.key,
+            etype,
+            code,
+            value,
+            self.hold_trigger,
+            now,
+        );
+        // A fresh key-down after the window closed ends the previous sequence.
+        let new_press = self.last_key_down.is_none() && result.last_key_down.is_some();
+        let expired = if new_press { self.poll(now) } else { None };
+        self.last_key_down = result.last_key_down;
+
+        if new_press {
+            self.hold_fired = false;
+        }
+        // The hold was already reported while the key was down; the release is silent.
+        if self.hold_fired && self.last_key_down.is_none() {
+            self.hold_fired = false;
+            return expired;
+        }
+
+        match result.decision {
+            KeyDecision::Continue => expired,
+            KeyDecision::ShortPress => {
+                self.taps += 1;
+                self.last_release = Some(now);
+                if self.taps >= self.max_taps {
+                    return self.flush();
+                }
+                None
+            }
+            KeyDecision::LongPress => {
+                self.taps = 0;
+                self.last_release = None;
+                Some(Gesture::Hold)
+            }
+        }
+    }
+
+    /// When [`GestureRecognizer::poll`] must be called next, if anything is pending
+    pub fn deadline(&self) -> Option<Instant> {
+        match self.last_key_down {
+            Some(_) if self.hold_fired => None,
+            Some(down) => Some(down + self.hold_trigger),
+            None => self.last_release.map(|t| t + self.tap_window),
+        }
+    }
+
+    /// Fire a hold whose threshold was crossed, or resolve a pending tap sequence whose
+    /// window has elapsed at `now`
+    pub fn poll(&mut self, now: Instant) -> Option<Gesture> {
+        match self.deadline() {
+            Some(deadline) if now >= deadline => {
+                if self.last_key_down.is_some() {
+                    self.hold_fired = true;
+                    self.taps = 0;
+                    self.last_release = None;
+                    Some(Gesture::Hold)
+                } else {
+                    self.flush()
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn flush(&mut self) -> Option<Gesture> {
+        let taps = self.taps;
+        self.taps = 0;
+        self.last_release = None;
+        if taps > 0 {
+            Some(Gesture::Tap(taps))
+        } else {
+            None
+        }
+    }
+}
+
+/// Gesture to action table. Bindings can be global or specific to a power mode;
+/// a mode-specific binding takes precedence.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bindings {
+    map: HashMap<(Option<PowerMode>, Gesture), Action>,
+}
+
+impl Bindings {
+    /// Default table: a single tap toggles power-saving mode
+    pub fn with_defaults() -> Self {
+        let mut b = Bindings::default();
+        b.bind(None, Gesture::Tap(1), Action::ToggleSaving);
+        b
+    }
+
+    pub fn bind(&mut self, mode: Option<PowerMode>, gesture: Gesture, action: Action) {
+        self.map.insert((mode, gesture), action);
+    }
+
+    /// Overlay all bindings from `other` on top of this table
+    pub fn extend(&mut self, other: &Bindings) {
+        for (k, v) in &other.map {
+            self.map.insert(k.clone(), v.clone());
+        }
+    }
+
+    /// Every bound action, in no particular order
+    pub fn actions(&self) -> impl Iterator<Item = &Action> {
+        self.map.values()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Action bound to `gesture` in `mode`
+    pub fn lookup(&self, mode: &PowerMode, gesture: Gesture) -> Option<&Action> {
+        self.map
+            .get(&(Some(mode.clone()), gesture))
+            .or_else(|| self.map.get(&(None, gesture)))
+    }
+
+    /// Highest tap count with a real action in `mode`; used to avoid waiting for a
+    /// double tap that nothing listens for.
+    pub fn max_taps(&self, mode: &PowerMode) -> u8 {
+        (1..=MAX_TAPS)
+            .filter(
+                |n| matches!(self.lookup(mode, Gesture::Tap(*n)), Some(a) if *a != Action::None),
+            )
+            .max()
+            .unwrap_or(1)
+    }
+
+    /// Parse a config key of the form `BIND_<GESTURE>` or `BIND_<MODE>_<GESTURE>`
+    /// into the mode and gesture it refers to.
+    pub fn parse_key(key: &str) -> Result<(Option<PowerMode>, Gesture), Error> {
+        let rest = key
+            .strip_prefix("BIND_")
+            .ok_or_else(|| Error::InvalidValue(format!("not a binding key '{}'", key)))?;
+        if let Some(g) = rest.strip_prefix("NORMAL_") {
+            return Ok((Some(PowerMode::Normal), g.parse()?));
+        }
+        if let Some(g) = rest.strip_prefix("SAVING_") {
+            return Ok((Some(PowerMode::Saving), g.parse()?));
+        }
+        Ok((None, rest.parse()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ---- classify_key_event: short/long press detection ----
+
+    const HOLD: Duration = Duration::from_millis(700);
+
+    #[test]
+    fn classify_non_power_key_is_ignored() {
+        let now = Instant::now();
+        // A keyboard event (e.g. 'A' key) must not be treated as a power key.
+        let r = classify_key_event(None, KEY_POWER, EV_KEY, 30, 1, HOLD, now);
+        assert_eq!(r.decision, KeyDecision::Continue);
+        assert_eq!(r.last_key_down, None);
+    }
+
+    #[test]
+    fn classify_non_ev_key_type_is_ignored() {
+        let now = Instant::now();
+        // Synchronization events (type 0) on the power device must be ignored.
+        let r = classify_key_event(None, KEY_POWER, 0, KEY_POWER, 0, HOLD, now);
+        assert_eq!(r.decision, KeyDecision::Continue);
+        assert_eq!(r.last_key_down, None);
+    }
+
+    #[test]
+    fn classify_key_down_records_timestamp() {
+        let now = Instant::now();
+        let r = classify_key_event(None, KEY_POWER, EV_KEY, KEY_POWER, 1, HOLD, now);
+        assert_eq!(r.decision, KeyDecision::Continue);
+        assert_eq!(r.last_key_down, Some(now));
+    }
+
+    #[test]
+    fn classify_short_press_when_elapsed_below_threshold() {
+        // key-down happened 100ms ago; threshold is 700ms -> short press.
+        let now = Instant::now();
+        let down = now - Duration::from_millis(100);
+        let r = classify_key_event(Some(down), KEY_POWER, EV_KEY, KEY_POWER, 0, HOLD, now);
+        assert_eq!(r.decision, KeyDecision::ShortPress);
+        assert_eq!(r.last_key_down, None);
+    }
+
+    #[test]
+    fn classify_long_press_when_elapsed_at_or_above_threshold() {
+        // key-down happened 800ms ago; threshold is 700ms -> long press.
+        let now = Instant::now();
+        let down = now - Duration::from_millis(800);
+        let r = classify_key_event(Some(down), KEY_POWER, EV_KEY, KEY_POWER, 0, HOLD, now);
+        assert_eq!(r.decision, KeyDecision::LongPress);
+        assert_eq!(r.last_key_down, None);
+    }
+
+    #[test]
+    fn classify_key_up_without_prior_down_is_ignored() {
+        // An orphan key-up (no preceding key-down) must NOT toggle power mode.
+        let now = Instant::now();
+        let r = classify_key_event(None, KEY_POWER, EV_KEY, KEY_POWER, 0, HOLD, now);
+        assert_eq!(r.decision, KeyDecision::Continue);
+        assert_eq!(r.last_key_down, None);
+    }
+
+    #[test]
+    fn classify_auto_repeat_value_does_not_toggle() {
+        // The kernel reports value 2 for auto-repeat; it must be a no-op.
+        let now = Instant::now();
+        let down = now - Duration::from_millis(100);
+        let r = classify_key_event(Some(down), KEY_POWER, EV_KEY, KEY_POWER, 2, HOLD, now);
+        assert_eq!(r.decision, KeyDecision::Continue);
+        // auto-repeat must not clear the recorded key-down time
+        assert_eq!(r.last_key_down, Some(down));
+    }
+
+    #[test]
+    fn classify_boundary_just_below_and_at_threshold() {
+        // elapsed == 699ms -> short, elapsed == 700ms -> long (decision is `<`, not `<=`)
+        let now = Instant::now();
+        let short_down = now - Duration::from_millis(699);
+        assert_eq!(
+            classify_key_event(Some(short_down), KEY_POWER, EV_KEY, KEY_POWER, 0, HOLD, now)
+                .decision,
+            KeyDecision::ShortPress
+        );
+        let boundary_down = now - HOLD;
+        assert_eq!(
+            classify_key_event(
+                Some(boundary_down),
+                KEY_POWER,
+                EV_KEY,
+                KEY_POWER,
+                0,
+                HOLD,
+                now
+            )
+            .decision,
+            KeyDecision::LongPress
+        );
+    }
+
+    // ---- GestureRecognizer ----
+
+    const WINDOW: Duration = Duration::from_millis(400);
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    /// Press at `t0 + down`, release at `t0 + up`; returns what the release produced.
+    fn press(g: &mut GestureRecognizer, t0: Instant, down: u64, up: u64) -> Option<Gesture> {
+        assert_eq!(g.on_event(EV_KEY, KEY_POWER, 1, t0 + ms(down)), None);
+        g.on_event(EV_KEY, KEY_POWER, 0, t0 + ms(up))
+    }
+
+    #[test]
+    fn single_tap_fires_on_release_when_no_multi_tap_bound() {
+        let t0 = Instant::now();
+        let mut g = GestureRecognizer::new(HOLD, WINDOW);
+        assert_eq!(press(&mut g, t0, 0, 100), Some(Gesture::Tap(1)));
+        assert_eq!(g.deadline(), None);
+    }
+
+    #[test]
+    fn single_tap_waits_for_window_when_double_tap_bound() {
+        let t0 = Instant::now();
+        let mut g = GestureRecognizer::new(HOLD, WINDOW);
+        g.set_max_taps(2);
+        assert_eq!(press(&mut g, t0, 0, 100), None);
+        assert_eq!(g.deadline(), Some(t0 + ms(500)));
+        // not yet expired
+        assert_eq!(g.poll(t0 + ms(499)), None);
+        assert_eq!(g.poll(t0 + ms(500)), Some(Gesture::Tap(1)));
+        assert_eq!(g.deadline(), None);
+    }
+
+    #[test]
+    fn double_tap_within_window() {
+        let t0 = Instant::now();
+        let mut g = GestureRecognizer::new(HOLD, WINDOW);
+        g.set_max_taps(2);
+        assert_eq!(press(&mut g, t0, 0, 100), None);
+        // max reached -> resolves on the second release without waiting
+        assert_eq!(press(&mut g, t0, 300, 400), Some(Gesture::Tap(2)));
+    }
+
+    #[test]
+    fn triple_tap_within_window() {
+        let t0 = Instant::now();
+        let mut g = GestureRecognizer::new(HOLD, WINDOW);
+        g.set_max_taps(3);
+        assert_eq!(press(&mut g, t0, 0, 100), None);
+        assert_eq!(press(&mut g, t0, 200, 300), None);
+        assert_eq!(press(&mut g, t0, 400, 500), Some(Gesture::Tap(3)));
+    }
+
+    #[test]
+    fn press_after_window_flushes_previous_sequence() {
+        let t0 = Instant::now();
+        let mut g = GestureRecognizer::new(HOLD, WINDOW);
+        g.set_max_taps(2);
+        assert_eq!(press(&mut g, t0, 0, 100), None);
+        // next press starts 600ms after the release: the first tap is reported then
+        assert_eq!(
+            g.on_event(EV_KEY, KEY_POWER, 1, t0 + ms(700)),
+            Some(Gesture::Tap(1))
+        );
+        assert_eq!(g.on_event(EV_KEY, KEY_POWER, 0, t0 + ms(800)), None);
+        assert_eq!(g.poll(t0 + ms(1200)), Some(Gesture::Tap(1)));
+    }
+
+    #[test]
+    fn hold_resets_pending_taps() {
+        let t0 = Instant::now();
+        let mut g = GestureRecognizer::new(HOLD, WINDOW);
+        g.set_max_taps(2);
+        assert_eq!(press(&mut g, t0, 0, 100), None);
+        assert_eq!(press(&mut g, t0, 200, 1000), Some(Gesture::Hold));
+        assert_eq!(g.deadline(), None);
+        assert_eq!(g.poll(t0 + ms(5000)), None);
+    }
+
+    #[test]
+    fn other_keys_do_not_disturb_sequence() {
+        let t0 = Instant::now();
+        let mut g = GestureRecognizer::new(HOLD, WINDOW);
+        g.set_max_taps(2);
+        assert_eq!(press(&mut g, t0, 0, 100), None);
+        assert_eq!(g.on_event(EV_KEY, 30, 1, t0 + ms(150)), None);
+        assert_eq!(g.on_event(0, 0, 0, t0 + ms(150)), None);
+        assert_eq!(press(&mut g, t0, 200, 300), Some(Gesture::Tap(2)));
+    }
+
+    #[test]
+    fn hold_fires_when_threshold_crossed_while_down() {
+        let t0 = Instant::now();
+        let mut g = GestureRecognizer::new(HOLD, WINDOW);
+        assert_eq!(g.on_event(EV_KEY, KEY_POWER, 1, t0), None);
+        assert!(g.is_pressed());
+        assert_eq!(g.deadline(), Some(t0 + HOLD));
+        assert_eq!(g.poll(t0 + ms(699)), None);
+        assert_eq!(g.poll(t0 + ms(700)), Some(Gesture::Hold));
+        // fires only once per press, and auto-repeat doesn't re-arm it
+        assert_eq!(g.deadline(), None);
+        assert_eq!(g.poll(t0 + ms(900)), None);
+        assert_eq!(g.on_event(EV_KEY, KEY_POWER, 2, t0 + ms(950)), None);
+        assert_eq!(g.on_event(EV_KEY, 30, 1, t0 + ms(960)), None);
+        // the release after an early hold produces nothing
+        assert_eq!(g.on_event(EV_KEY, KEY_POWER, 0, t0 + ms(1500)), None);
+        assert_eq!(g.deadline(), None);
+        // the next press is a fresh tap
+        assert_eq!(press(&mut g, t0, 2000, 2100), Some(Gesture::Tap(1)));
+    }
+
+    #[test]
+    fn hold_during_multi_tap_sequence_discards_taps() {
+        let t0 = Instant::now();
+        let mut g = GestureRecognizer::new(HOLD, WINDOW);
+        g.set_max_taps(2);
+        assert_eq!(press(&mut g, t0, 0, 100), None);
+        assert_eq!(g.on_event(EV_KEY, KEY_POWER, 1, t0 + ms(300)), None);
+        // while down, the deadline is the hold threshold, not the tap window
+        assert_eq!(g.deadline(), Some(t0 + ms(1000)));
+        assert_eq!(g.poll(t0 + ms(1000)), Some(Gesture::Hold));
+        assert_eq!(g.on_event(EV_KEY, KEY_POWER, 0, t0 + ms(1200)), None);
+        assert_eq!(g.poll(t0 + ms(5000)), None);
+    }
+
+    #[test]
+    fn custom_trigger_key() {
+        const KEY_SLEEP: u16 = 142;
+        let t0 = Instant::now();
+        let mut g = GestureRecognizer::new(HOLD, WINDOW);
+        g.key = KEY_SLEEP;
+        // the power key no longer counts
+        assert_eq!(press(&mut g, t0, 0, 100), None);
+        assert_eq!(g.on_event(EV_KEY, KEY_SLEEP, 1, t0 + ms(200)), None);
+        assert_eq!(
+            g.on_event(EV_KEY, KEY_SLEEP, 0, t0 + ms(300)),
+            Some(Gesture::Tap(1))
+        );
+    }
+
+    #[test]
+    fn cancelled_press_is_silent() {
+        let t0 = Instant::now();
+        let mut g = GestureRecognizer::new(HOLD, WINDOW);
+        assert_eq!(g.on_event(EV_KEY, KEY_POWER, 1, t0), None);
+        g.cancel_press();
+        assert_eq!(g.deadline(), None);
+        assert_eq!(g.poll(t0 + ms(1000)), None);
+        assert_eq!(g.on_event(EV_KEY, KEY_POWER, 0, t0 + ms(1200)), None);
+        assert_eq!(press(&mut g, t0, 2000, 2100), Some(Gesture::Tap(1)));
+    }
+
+    // ---- Bindings ----
+
+    #[test]
+    fn gesture_names_round_trip() {
+        for g in [
+            Gesture::Tap(1),
+            Gesture::Tap(2),
+            Gesture::Tap(3),
+            Gesture::Hold,
+        ] {
+            assert_eq!(g.to_string().parse::<Gesture>().unwrap(), g);
+        }
+        assert!("quadruple_tap".parse::<Gesture>().is_err());
+    }
+
+    #[test]
+    fn mode_specific_binding_wins() {
+        let mut b = Bindings::with_defaults();
+        b.bind(Some(PowerMode::Saving), Gesture::Tap(1), Action::None);
+        b.bind(
+            Some(PowerMode::Saving),
+            Gesture::Tap(2),
+            Action::ToggleSaving,
+        );
+        assert_eq!(
+            b.lookup(&PowerMode::Normal, Gesture::Tap(1)),
+            Some(&Action::ToggleSaving)
+        );
+        assert_eq!(
+            b.lookup(&PowerMode::Saving, Gesture::Tap(1)),
+            Some(&Action::None)
+        );
+        assert_eq!(b.lookup(&PowerMode::Normal, Gesture::Hold), None);
+        assert_eq!(b.max_taps(&PowerMode::Normal), 1);
+        assert_eq!(b.max_taps(&PowerMode::Saving), 2);
+    }
+
+    #[test]
+    fn parse_binding_keys() {
+        assert_eq!(
+            Bindings::parse_key("BIND_HOLD").unwrap(),
+            (None, Gesture::Hold)
+        );
+        assert_eq!(
+            Bindings::parse_key("BIND_SAVING_DOUBLE_TAP").unwrap(),
+            (Some(PowerMode::Saving), Gesture::Tap(2))
+        );
+        assert_eq!(
+            Bindings::parse_key("BIND_NORMAL_TAP").unwrap(),
+            (Some(PowerMode::Normal), Gesture::Tap(1))
+        );
+        assert!(Bindings::parse_key("BIND_SAVING_WIGGLE").is_err());
+        assert!(Bindings::parse_key("HOLD").is_err());
+    }
+}
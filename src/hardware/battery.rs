@@ -0,0 +1,234 @@
+//! Power supply detection: the AXP PMIC's battery and charger
+
+use crate::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+/// Name prefix of the uConsole PMIC's supplies (axp20x-battery, axp22x-ac, ...)
+const AXP_PREFIX: &str = "axp";
+
+/// Battery `status` attribute
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ChargeStatus {
+    Charging,
+    Discharging,
+    Full,
+    NotCharging,
+    #[default]
+    Unknown,
+}
+
+impl ChargeStatus {
+    fn parse(s: &str) -> Self {
+        match s.trim() {
+            "Charging" => ChargeStatus::Charging,
+            "Discharging" => ChargeStatus::Discharging,
+            "Full" => ChargeStatus::Full,
+            "Not charging" => ChargeStatus::NotCharging,
+            _ => ChargeStatus::Unknown,
+        }
+    }
+}
+
+/// One reading of the battery; attributes the driver doesn't provide are `None`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BatteryStatus {
+    /// Charge in percent
+    pub capacity: Option<u8>,
+    pub status: ChargeStatus,
+    /// `voltage_now` in µV
+    pub voltage_uv: Option<i64>,
+    /// `current_now` in µA; the AXP driver reports discharge as negative
+    pub current_ua: Option<i64>,
+}
+
+impl BatteryStatus {
+    pub fn charging(&self) -> bool {
+        matches!(self.status, ChargeStatus::Charging | ChargeStatus::Full)
+    }
+}
+
+/// Supplies found under `/sys/class/power_supply`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PowerSupplies {
+    pub battery: Option<PathBuf>,
+    /// Mains/USB supplies whose `online` tells if a charger is plugged in
+    pub chargers: Vec<PathBuf>,
+}
+
+impl PowerSupplies {
+    pub fn read_battery(&self) -> Result<Option<BatteryStatus>, Error> {
+        self.battery.as_deref().map(read_battery).transpose()
+    }
+
+    /// Whether any charger is online; `None` if no charger was found
+    pub fn charger_online(&self) -> Option<bool> {
+        (!self.chargers.is_empty()).then(|| {
+            self.chargers
+                .iter()
+                .any(|c| read_attr(c, "online").as_deref() == Some("1"))
+        })
+    }
+}
+
+fn read_attr(dir: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(dir.join(name))
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+/// Find the battery and chargers, preferring the AXP PMIC's over others (e.g. a
+/// USB device reporting its own battery)
+pub fn find_supplies() -> Result<PowerSupplies, Error> {
+    find_supplies_in(Path::new(POWER_SUPPLY_PATH))
+}
+
+/// Same as [`find_supplies`], scanning `dir` instead of `/sys/class/power_supply`
+pub fn find_supplies_in(dir: &Path) -> Result<PowerSupplies, Error> {
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(PowerSupplies::default());
+        }
+        Err(e) => return Err(Error::from(e)),
+    };
+    let mut paths: Vec<PathBuf> = entries.flatten().map(|e| e.path()).collect();
+    // AXP supplies first, then by name for a stable order
+    paths.sort_by_key(|p| {
+        let name = p
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        (!name.starts_with(AXP_PREFIX), name)
+    });
+
+    let mut supplies = PowerSupplies::default();
+    for path in paths {
+        match read_attr(&path, "type").as_deref() {
+            Some("Battery") if supplies.battery.is_none() => supplies.battery = Some(path),
+            Some("Mains") | Some("USB") => supplies.chargers.push(path),
+            _ => {}
+        }
+    }
+    Ok(supplies)
+}
+
+/// Read capacity, status, voltage and current of the battery at `path`
+pub fn read_battery(path: &Path) -> Result<BatteryStatus, Error> {
+    if !path.join("type").exists() {
+        return Err(Error::NotFound(format!(
+            "battery {} is gone",
+            path.display()
+        )));
+    }
+    Ok(BatteryStatus {
+        capacity: read_attr(path, "capacity").and_then(|s| s.parse().ok()),
+        status: read_attr(path, "status")
+            .map(|s| ChargeStatus::parse(&s))
+            .unwrap_or_default(),
+        voltage_uv: read_attr(path, "voltage_now").and_then(|s| s.parse().ok()),
+        current_ua: read_attr(path, "current_now").and_then(|s| s.parse().ok()),
+    })
+}
+
+/// Check whether any external supply (mains/USB charger) is online
+///
+/// # Returns
+/// - Ok(true) if a charger reports `online` = 1
+/// - Ok(false) if none does, or there is no power supply class at all
+/// - Err(Error) if error occurred
+pub fn on_ac_power() -> Result<bool, Error> {
+    on_ac_power_in(Path::new(POWER_SUPPLY_PATH))
+}
+
+/// Same as [`on_ac_power`], scanning `dir` instead of `/sys/class/power_supply`
+pub fn on_ac_power_in(dir: &Path) -> Result<bool, Error> {
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(Error::from(e)),
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let kind = fs::read_to_string(path.join("type")).unwrap_or_default();
+        if kind.trim() == "Battery" {
+            continue;
+        }
+        let online = fs::read_to_string(path.join("online")).unwrap_or_default();
+        if online.trim() == "1" {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn supply(root: &Path, name: &str, kind: &str, online: Option<&str>) {
+        let dir = root.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("type"), format!("{}\n", kind)).unwrap();
+        if let Some(o) = online {
+            fs::write(dir.join("online"), format!("{}\n", o)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_on_ac_power_in() {
+        let tmp = TempDir::new("ps");
+        assert!(!on_ac_power_in(&tmp).unwrap());
+
+        supply(&tmp, "axp20x-battery", "Battery", Some("1"));
+        supply(&tmp, "axp22x-ac", "Mains", Some("0"));
+        assert!(!on_ac_power_in(&tmp).unwrap());
+
+        supply(&tmp, "axp20x-usb", "USB", Some("1"));
+        assert!(on_ac_power_in(&tmp).unwrap());
+    }
+
+    #[test]
+    fn test_find_and_read_battery() {
+        let tmp = TempDir::new("ps_bat");
+        assert_eq!(find_supplies_in(&tmp).unwrap(), PowerSupplies::default());
+
+        // a USB gadget's battery sorts before the PMIC's by name but loses to it
+        supply(&tmp, "a-hid-battery", "Battery", None);
+        supply(&tmp, "axp20x-battery", "Battery", Some("1"));
+        supply(&tmp, "axp22x-ac", "Mains", Some("0"));
+        let bat = tmp.join("axp20x-battery");
+        fs::write(bat.join("capacity"), "42\n").unwrap();
+        fs::write(bat.join("status"), "Discharging\n").unwrap();
+        fs::write(bat.join("voltage_now"), "3712000\n").unwrap();
+        fs::write(bat.join("current_now"), "-512000\n").unwrap();
+
+        let supplies = find_supplies_in(&tmp).unwrap();
+        assert_eq!(supplies.battery, Some(bat.clone()));
+        assert_eq!(supplies.chargers, vec![tmp.join("axp22x-ac")]);
+        assert_eq!(supplies.charger_online(), Some(false));
+
+        let status = supplies.read_battery().unwrap().unwrap();
+        assert_eq!(
+            status,
+            BatteryStatus {
+                capacity: Some(42),
+                status: ChargeStatus::Discharging,
+                voltage_uv: Some(3_712_000),
+                current_ua: Some(-512_000),
+            }
+        );
+        assert!(!status.charging());
+
+        fs::write(bat.join("status"), "Not charging\n").unwrap();
+        fs::remove_file(bat.join("current_now")).unwrap();
+        let status = read_battery(&bat).unwrap();
+        assert_eq!(status.status, ChargeStatus::NotCharging);
+        assert_eq!(status.current_ua, None);
+
+        fs::remove_dir_all(&bat).unwrap();
+        assert!(matches!(read_battery(&bat), Err(Error::NotFound(_))));
+    }
+}
@@ -1,17 +1,41 @@
 //! CPU frequency handling under `hardware` namespace
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
-use log::debug;
+use log::{debug, warn};
+
+use crate::error::Error;
+use crate::hardware::cpu_freq::{FreqLimits, parse_freq_range};
+use crate::hardware::cpu_hotplug::CpuHotplug;
+use crate::restore::Restored;
 
 pub const CPU_POLICY_PATH: &str = "/sys/devices/system/cpu/cpufreq/policy0";
+pub const CPUFREQ_PATH: &str = "/sys/devices/system/cpu/cpufreq";
 
 #[derive(Clone, Debug)]
 pub struct CpuFreqConfig {
     pub policy_path: PathBuf,
+    /// `scaling_min_freq` / `scaling_max_freq` when the config was created, restored
+    /// in normal mode if the values at saving mode entry couldn't be read
     pub default_min: Option<String>,
     pub default_max: Option<String>,
     pub saving_min: Option<String>,
     pub saving_max: Option<String>,
+    /// `scaling_governor` when the config was created, restored in normal mode if
+    /// the governor at saving mode entry couldn't be read
+    pub default_governor: Option<String>,
+    /// `scaling_available_governors`; empty if the kernel doesn't list them
+    pub available_governors: Vec<String>,
+    /// Governor to switch to in saving mode, if any
+    pub saving_governor: Option<String>,
+    /// `name=value` tunables of the saving governor, e.g. `up_threshold=95`
+    pub governor_tunables: Vec<(String, String)>,
+    /// Limits, governor and tunable values replaced on saving mode entry. Shared
+    /// between clones of one saving config.
+    replaced: Arc<Mutex<Option<Replaced>>>,
+    /// Upper bound on every `scaling_max_freq` written, e.g. a thermal cap
+    pub cap: FreqCap,
 }
 
 impl CpuFreqConfig {
@@ -29,17 +53,37 @@ impl CpuFreqConfig {
             .ok()
             .map(|s| s.trim().to_string());
 
-        let (saving_min, saving_max) = if let Some(s) = saving_cpu_freq {
-            let parts: Vec<&str> = s.split(',').collect();
-            if parts.len() == 2 {
-                let min = format!("{}000", parts[0].trim());
-                let max = format!("{}000", parts[1].trim());
-                (Some(min), Some(max))
-            } else {
+        let default_governor = std::fs::read_to_string(policy_path_clone.join("scaling_governor"))
+            .ok()
+            .map(|s| s.trim().to_string());
+        let available_governors =
+            std::fs::read_to_string(policy_path_clone.join("scaling_available_governors"))
+                .map(|s| s.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_default();
+
+        let (saving_min, saving_max) = match saving_cpu_freq
+            .as_deref()
+            .map(|s| saving_range(&policy_path, s))
+        {
+            Some(Ok((min, max))) => {
+                debug!(
+                    "{}: saving range {}-{} kHz",
+                    policy_path.display(),
+                    min,
+                    max
+                );
+                (Some(min.to_string()), Some(max.to_string()))
+            }
+            Some(Err(e)) => {
+                warn!(
+                    "{}: ignoring saving range '{}': {}",
+                    policy_path.display(),
+                    saving_cpu_freq.unwrap_or_default(),
+                    e
+                );
                 (None, None)
             }
-        } else {
-            (None, None)
+            None => (None, None),
         };
 
         CpuFreqConfig {
@@ -48,10 +92,156 @@ impl CpuFreqConfig {
             default_max,
             saving_min,
             saving_max,
+            default_governor,
+            available_governors,
+            saving_governor: None,
+            governor_tunables: Vec::new(),
+            replaced: Arc::new(Mutex::new(None)),
+            cap: FreqCap::default(),
+        }
+    }
+
+    /// Write `scaling_max_freq` through `cap`, shared with whatever else limits it
+    pub fn with_freq_cap(mut self, cap: FreqCap) -> Self {
+        self.cap = cap;
+        self
+    }
+
+    /// Also switch to `governor` in saving mode. A governor the kernel doesn't
+    /// offer is ignored with a warning.
+    pub fn with_saving_governor(mut self, governor: Option<String>) -> Self {
+        self.saving_governor = governor.filter(|g| {
+            let known = self.available_governors.is_empty() || self.available_governors.contains(g);
+            if !known {
+                warn!(
+                    "Ignoring CPU governor '{}': not in scaling_available_governors ({})",
+                    g,
+                    self.available_governors.join(" ")
+                );
+            }
+            known
+        });
+        self
+    }
+
+    /// Tunables to set after switching to the saving governor
+    pub fn with_governor_tunables(mut self, tunables: Vec<(String, String)>) -> Self {
+        self.governor_tunables = tunables;
+        self
+    }
+
+    /// Directory holding `governor`'s tunables: per policy if the driver has
+    /// per-policy governors, shared under `cpufreq/` otherwise
+    fn tunables_dir(&self, governor: &str) -> Option<PathBuf> {
+        let per_policy = self.policy_path.join(governor);
+        if per_policy.is_dir() {
+            return Some(per_policy);
+        }
+        let shared = self.policy_path.parent()?.join(governor);
+        shared.is_dir().then_some(shared)
+    }
+
+    /// Read what saving mode is about to change. Values that can't be read fall
+    /// back to those seen at startup.
+    fn snapshot(&self) -> Replaced {
+        let read = |name: &str| {
+            std::fs::read_to_string(self.policy_path.join(name))
+                .ok()
+                .map(|s| s.trim().to_string())
+        };
+        let limits = if self.saving_min.is_some() && self.saving_max.is_some() {
+            let min = read("scaling_min_freq").or_else(|| self.default_min.clone());
+            // under a thermal cap, what the max would be without it
+            let max = self
+                .cap
+                .get(&self.policy_path)
+                .and_then(|_| self.cap.wanted(&self.policy_path))
+                .or_else(|| read("scaling_max_freq"))
+                .or_else(|| self.default_max.clone());
+            min.zip(max)
+        } else {
+            None
+        };
+        let governor = self
+            .saving_governor
+            .as_ref()
+            .and_then(|_| read("scaling_governor").or_else(|| self.default_governor.clone()));
+        Replaced {
+            limits,
+            governor,
+            tunables: Vec::new(),
+        }
+    }
+
+    /// Switch to the saving governor and set its tunables, adding the old tunable
+    /// values to `saved`
+    fn apply_saving_governor(
+        &self,
+        governor: &str,
+        saved: &mut Vec<(PathBuf, String)>,
+        dry_run: bool,
+    ) {
+        self.write_governor(governor, dry_run);
+        if self.governor_tunables.is_empty() {
+            return;
+        }
+        let Some(dir) = self.tunables_dir(governor) else {
+            if dry_run {
+                debug!(
+                    "DRY-RUN: Would set {} tunables {:?}",
+                    governor, self.governor_tunables
+                );
+            } else {
+                warn!("No tunables directory for CPU governor {}", governor);
+            }
+            return;
+        };
+        for (name, value) in &self.governor_tunables {
+            let path = dir.join(name);
+            let Ok(old) = std::fs::read_to_string(&path) else {
+                warn!("CPU governor {} has no tunable {}", governor, name);
+                continue;
+            };
+            if dry_run {
+                debug!("DRY-RUN: Would write {} to {}", value, path.display());
+                continue;
+            }
+            match std::fs::write(&path, value) {
+                Ok(()) => {
+                    debug!("CPU: {} {}={}", governor, name, value);
+                    saved.push((path, old.trim().to_string()));
+                }
+                Err(e) => warn!("Failed to set {} tunable {}: {}", governor, name, e),
+            }
+        }
+    }
+
+    fn write_governor(&self, governor: &str, dry_run: bool) {
+        let path = self.policy_path.join("scaling_governor");
+        if dry_run {
+            debug!(
+                "DRY-RUN: Would write governor {} to {}",
+                governor,
+                path.display()
+            );
+        } else {
+            let _ = std::fs::write(path, governor);
         }
+        debug!("CPU: governor {}", governor);
+    }
+
+    /// Write `min`/`max`, with `max` lowered to the cap if there is one (and `min`
+    /// along with it if needed)
+    fn write_limits(&self, min: &str, max: &str) {
+        self.cap.write_limits(&self.policy_path, min, max);
     }
 
     pub fn apply_saving_mode(&self, dry_run: bool) {
+        if self.saving_min.is_none() && self.saving_governor.is_none() {
+            return;
+        }
+        let mut replaced = self.replaced.lock().unwrap();
+        let replaced = replaced.get_or_insert_with(|| self.snapshot());
         if let (Some(min), Some(max)) = (&self.saving_min, &self.saving_max) {
             if dry_run {
                 debug!(
@@ -61,49 +251,313 @@ impl CpuFreqConfig {
                     self.policy_path.display()
                 );
             } else {
-                let _ = std::fs::write(self.policy_path.join("scaling_min_freq"), min);
-                let _ = std::fs::write(self.policy_path.join("scaling_max_freq"), max);
+                self.write_limits(min, max);
             }
             debug!("CPU: saving mode {}/{}", min, max);
         }
+        if let Some(g) = &self.saving_governor {
+            self.apply_saving_governor(g, &mut replaced.tunables, dry_run);
+        }
     }
 
-    pub fn apply_normal_mode(&self, dry_run: bool) {
-        if let (Some(min), Some(max)) = (&self.default_min, &self.default_max) {
+    /// Put back what [`CpuFreqConfig::apply_saving_mode`] found on entry
+    pub fn apply_normal_mode(&self, dry_run: bool) -> Vec<Restored> {
+        let Some(replaced) = self.replaced.lock().unwrap().take() else {
+            return Vec::new();
+        };
+        let policy = self.policy_path.display().to_string();
+        let mut restored = Vec::new();
+        if let Some((min, max)) = &replaced.limits {
             if dry_run {
                 debug!(
                     "DRY-RUN: Would write CPU normal mode {}/{} to {}",
-                    min.trim(),
-                    max.trim(),
+                    min,
+                    max,
                     self.policy_path.display()
                 );
             } else {
-                let _ = std::fs::write(self.policy_path.join("scaling_min_freq"), min.trim());
-                let _ = std::fs::write(self.policy_path.join("scaling_max_freq"), max.trim());
+                self.write_limits(min, max);
+            }
+            restored.push(Restored::new(&policy, format!("{}-{} kHz", min, max)));
+        }
+        for (path, old) in replaced.tunables.iter().rev() {
+            let _ = std::fs::write(path, old);
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            restored.push(Restored::new(&policy, format!("{}={}", name, old)));
+        }
+        if let Some(g) = &replaced.governor {
+            self.write_governor(g, dry_run);
+            restored.push(Restored::new(&policy, format!("governor {}", g)));
+        }
+        restored
+    }
+}
+
+/// An upper bound on `scaling_max_freq` that outlives mode changes: configs
+/// sharing it write the lower of their own max and the cap, and lifting the cap
+/// puts back the max the current mode asked for.
+#[derive(Clone, Debug, Default)]
+pub struct FreqCap(Arc<Mutex<CapState>>);
+
+#[derive(Debug, Default)]
+struct CapState {
+    /// Active caps in kHz, by policy directory
+    caps: HashMap<PathBuf, u64>,
+    /// The max each policy would have without a cap
+    wanted: HashMap<PathBuf, String>,
+}
+
+impl FreqCap {
+    /// Record `max` as what `policy` should run at and write it, capped, along with
+    /// `min` (lowered to the capped max if above it). The lock is held throughout so
+    /// a [`FreqCap::set`] in between can't be overwritten with a stale max.
+    pub fn write_limits(&self, policy: &Path, min: &str, max: &str) {
+        let mut state = self.0.lock().unwrap();
+        state.wanted.insert(policy.to_path_buf(), max.to_string());
+        let max = capped(max, state.caps.get(policy).copied());
+        let min = match (min.parse::<u64>(), max.parse::<u64>()) {
+            (Ok(lo), Ok(hi)) if lo > hi => max.clone(),
+            _ => min.to_string(),
+        };
+        let _ = std::fs::write(policy.join("scaling_min_freq"), min);
+        let _ = std::fs::write(policy.join("scaling_max_freq"), max);
+    }
+
+    pub fn get(&self, policy: &Path) -> Option<u64> {
+        self.0.lock().unwrap().caps.get(policy).copied()
+    }
+
+    /// The max last asked for on `policy`, before capping
+    pub fn wanted(&self, policy: &Path) -> Option<String> {
+        self.0.lock().unwrap().wanted.get(policy).cloned()
+    }
+
+    /// Set (`Some`) or lift (`None`) the cap of `policy` and write the resulting
+    /// `scaling_max_freq`
+    pub fn set(&self, policy: &Path, cap: Option<u64>, dry_run: bool) {
+        let mut state = self.0.lock().unwrap();
+        match cap {
+            Some(c) => state.caps.insert(policy.to_path_buf(), c),
+            None => state.caps.remove(policy),
+        };
+        let wanted = match state.wanted.get(policy) {
+            Some(w) => w.clone(),
+            None => {
+                let Ok(current) = std::fs::read_to_string(policy.join("scaling_max_freq")) else {
+                    warn!("{}: no scaling_max_freq", policy.display());
+                    return;
+                };
+                let current = current.trim().to_string();
+                state.wanted.insert(policy.to_path_buf(), current.clone());
+                current
+            }
+        };
+        let max = capped(&wanted, cap);
+        let path = policy.join("scaling_max_freq");
+        if dry_run {
+            debug!("DRY-RUN: Would write {} to {}", max, path.display());
+        } else if let Err(e) = std::fs::write(&path, &max) {
+            warn!("Failed to write {} to {}: {}", max, path.display(), e);
+        }
+    }
+}
+
+fn capped(max: &str, cap: Option<u64>) -> String {
+    match (max.parse::<u64>(), cap) {
+        (Ok(m), Some(c)) if c < m => c.to_string(),
+        (Err(_), Some(c)) => c.to_string(),
+        _ => max.to_string(),
+    }
+}
+
+/// Parse `spec` and fit it to what the policy at `policy_path` supports, in kHz
+fn saving_range(policy_path: &Path, spec: &str) -> Result<(u64, u64), Error> {
+    let (min, max) = parse_freq_range(spec)?;
+    FreqLimits::read(policy_path).resolve_range(&policy_path.display().to_string(), min, max)
+}
+
+/// The cpufreq policies below `dir` (`policy0`, `policy4`, ...) by number
+pub fn find_policies(dir: &Path) -> Vec<(u32, PathBuf)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut policies: Vec<(u32, PathBuf)> = entries
+        .flatten()
+        .filter_map(|e| {
+            let n = e
+                .file_name()
+                .to_str()?
+                .strip_prefix("policy")?
+                .parse()
+                .ok()?;
+            Some((n, e.path()))
+        })
+        .collect();
+    policies.sort_unstable_by_key(|(n, _)| *n);
+    policies
+}
+
+/// Every cpufreq policy, each with its own defaults and saving range, plus core
+/// hot-unplug, which applies to the whole CPU
+#[derive(Clone, Debug)]
+pub struct CpuPolicies {
+    pub policies: Vec<CpuFreqConfig>,
+    /// Cores to take offline in saving mode, if any
+    pub hotplug: Option<CpuHotplug>,
+}
+
+impl CpuPolicies {
+    /// Just the policy at `policy_path`
+    pub fn single(policy: CpuFreqConfig) -> Self {
+        CpuPolicies {
+            policies: vec![policy],
+            hotplug: None,
+        }
+    }
+
+    /// All policies in the system, see [`CpuPolicies::from_dir`]
+    pub fn new(
+        saving_cpu_freq: Option<String>,
+        per_policy: &BTreeMap<u32, Option<String>>,
+    ) -> Self {
+        Self::from_dir(Path::new(CPUFREQ_PATH), saving_cpu_freq, per_policy)
+    }
+
+    /// All policies below `dir`. A policy's saving range comes from `per_policy`
+    /// (`None` there leaves it unclamped), else from `saving_cpu_freq`. Without any
+    /// policy directory this falls back to `policy0`.
+    pub fn from_dir(
+        dir: &Path,
+        saving_cpu_freq: Option<String>,
+        per_policy: &BTreeMap<u32, Option<String>>,
+    ) -> Self {
+        let mut found = find_policies(dir);
+        if found.is_empty() {
+            found.push((0, dir.join("policy0")));
+        }
+        for n in per_policy.keys() {
+            if !found.iter().any(|(p, _)| p == n) {
+                warn!("No cpufreq policy{}, ignoring its saving range", n);
             }
-            debug!("CPU: normal mode {}/{}", min.trim(), max.trim());
+        }
+        let policies = found
+            .into_iter()
+            .map(|(n, path)| {
+                let range = match per_policy.get(&n) {
+                    Some(r) => r.clone(),
+                    None => saving_cpu_freq.clone(),
+                };
+                CpuFreqConfig::with_policy_path(path, range)
+            })
+            .collect();
+        CpuPolicies {
+            policies,
+            hotplug: None,
         }
     }
+
+    /// Also switch every policy to `governor` in saving mode
+    pub fn with_saving_governor(mut self, governor: Option<String>) -> Self {
+        self.policies = self
+            .policies
+            .into_iter()
+            .map(|p| p.with_saving_governor(governor.clone()))
+            .collect();
+        self
+    }
+
+    pub fn with_governor_tunables(mut self, tunables: Vec<(String, String)>) -> Self {
+        self.policies = self
+            .policies
+            .into_iter()
+            .map(|p| p.with_governor_tunables(tunables.clone()))
+            .collect();
+        self
+    }
+
+    /// Share `cap` between all policies
+    pub fn with_freq_cap(mut self, cap: FreqCap) -> Self {
+        self.policies = self
+            .policies
+            .into_iter()
+            .map(|p| p.with_freq_cap(cap.clone()))
+            .collect();
+        self
+    }
+
+    /// Also take cores offline in saving mode
+    pub fn with_hotplug(mut self, hotplug: Option<CpuHotplug>) -> Self {
+        self.hotplug = hotplug;
+        self
+    }
+
+    pub fn apply_saving_mode(&self, dry_run: bool) {
+        for p in &self.policies {
+            p.apply_saving_mode(dry_run);
+        }
+        if let Some(h) = &self.hotplug {
+            h.take_offline(dry_run);
+        }
+    }
+
+    pub fn apply_normal_mode(&self, dry_run: bool) -> Vec<Restored> {
+        // back online first so the restored limits cover them too
+        let mut restored = match &self.hotplug {
+            Some(h) => h.restore(dry_run),
+            None => Vec::new(),
+        };
+        for p in &self.policies {
+            restored.extend(p.apply_normal_mode(dry_run));
+        }
+        restored
+    }
+}
+
+/// What saving mode replaced, as found on entry
+#[derive(Clone, Debug)]
+struct Replaced {
+    /// `scaling_min_freq` and (uncapped) `scaling_max_freq`, if the range is clamped
+    limits: Option<(String, String)>,
+    /// Previous governor, if the governor is switched
+    governor: Option<String>,
+    /// Tunable files written and their old values
+    tunables: Vec<(PathBuf, String)>,
+}
+
+/// Parse governor tunables given as `name=value` pairs separated by commas, e.g.
+/// `up_threshold=95,sampling_down_factor=10`
+pub fn parse_tunables(s: &str) -> Result<Vec<(String, String)>, Error> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|pair| {
+            let (name, value) = pair
+                .split_once('=')
+                .map(|(n, v)| (n.trim(), v.trim()))
+                .filter(|(n, v)| !n.is_empty() && !v.is_empty())
+                .ok_or_else(|| {
+                    Error::InvalidValue(format!("expected name=value, got '{}'", pair))
+                })?;
+            // a file name in the governor directory, nothing else
+            if Path::new(name).components().count() != 1 || name.starts_with('.') {
+                return Err(Error::InvalidValue(format!("bad tunable name '{}'", name)));
+            }
+            Ok((name.to_string(), value.to_string()))
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::env;
+    use crate::test_util::TempDir;
     use std::fs;
 
     #[test]
     fn test_cpu_apply_modes_writes_files() {
-        let tmp = env::temp_dir().join(format!(
-            "uconsole_sleep_test_{}",
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis()
-        ));
-        let _ = fs::create_dir_all(&tmp);
+        let tmp = TempDir::new("sleep_test");
 
-        let cpu = CpuFreqConfig::with_policy_path(tmp.clone(), Some(String::from("100,400")));
+        let cpu = CpuFreqConfig::with_policy_path(tmp.to_path_buf(), Some(String::from("100,400")));
         cpu.apply_saving_mode(false);
         let min = fs::read_to_string(tmp.join("scaling_min_freq")).unwrap();
         let max = fs::read_to_string(tmp.join("scaling_max_freq")).unwrap();
@@ -121,21 +575,13 @@ mod tests {
     /// and apply_saving_mode is a no-op rather than writing garbage.
     #[test]
     fn test_cpu_malformed_freq_is_noop() {
-        let tmp = env::temp_dir().join(format!(
-            "uconsole_cpu_malformed_{}_{}",
-            std::process::id(),
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis()
-        ));
-        let _ = fs::create_dir_all(&tmp);
+        let tmp = TempDir::new("cpu_malformed");
         // seed defaults so the config can read them back
         fs::write(tmp.join("scaling_min_freq"), "400000\n").unwrap();
         fs::write(tmp.join("scaling_max_freq"), "1400000\n").unwrap();
 
         // no comma -> not two parts -> saving range is None
-        let cpu = CpuFreqConfig::with_policy_path(tmp.clone(), Some(String::from("100")));
+        let cpu = CpuFreqConfig::with_policy_path(tmp.to_path_buf(), Some(String::from("100")));
         assert!(cpu.saving_min.is_none());
         assert!(cpu.saving_max.is_none());
 
@@ -155,18 +601,100 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_governor_validated_and_restored() {
+        let root = TempDir::new("cpu_governor");
+        let policy = root.join("policy0");
+        fs::create_dir_all(&policy).unwrap();
+        fs::write(policy.join("scaling_governor"), "ondemand\n").unwrap();
+        fs::write(
+            policy.join("scaling_available_governors"),
+            "conservative ondemand userspace powersave performance schedutil\n",
+        )
+        .unwrap();
+
+        let cpu = CpuFreqConfig::with_policy_path(policy.clone(), None)
+            .with_saving_governor(Some("turbo".to_string()));
+        assert_eq!(cpu.saving_governor, None);
+
+        // shared tunables directory, as without per-policy governors
+        fs::create_dir_all(root.join("conservative")).unwrap();
+        fs::write(root.join("conservative/up_threshold"), "80\n").unwrap();
+        let cpu = CpuFreqConfig::with_policy_path(policy.clone(), None)
+            .with_saving_governor(Some("conservative".to_string()))
+            .with_governor_tunables(parse_tunables("up_threshold=95, freq_step=5").unwrap());
+        // the governor was changed after startup: that one comes back
+        fs::write(policy.join("scaling_governor"), "schedutil\n").unwrap();
+        cpu.apply_saving_mode(false);
+        assert_eq!(
+            fs::read_to_string(policy.join("scaling_governor")).unwrap(),
+            "conservative"
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("conservative/up_threshold")).unwrap(),
+            "95"
+        );
+        cpu.apply_normal_mode(false);
+        assert_eq!(
+            fs::read_to_string(policy.join("scaling_governor")).unwrap(),
+            "schedutil"
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("conservative/up_threshold")).unwrap(),
+            "80"
+        );
+
+        assert!(parse_tunables("up_threshold").is_err());
+        assert!(parse_tunables("../scaling_max_freq=1").is_err());
+    }
+
+    #[test]
+    fn test_all_policies_with_per_policy_ranges() {
+        let root = TempDir::new("cpu_policies");
+        for (n, max) in [(0, "1800000"), (4, "2400000"), (6, "2400000")] {
+            let policy = root.join(format!("policy{}", n));
+            fs::create_dir_all(&policy).unwrap();
+            fs::write(policy.join("scaling_min_freq"), "600000\n").unwrap();
+            fs::write(policy.join("scaling_max_freq"), format!("{}\n", max)).unwrap();
+        }
+        fs::create_dir_all(root.join("ondemand")).unwrap();
+
+        let per_policy = BTreeMap::from([(4, Some("300,1200".to_string())), (6, None)]);
+        let cpu = CpuPolicies::from_dir(&root, Some("100,600".to_string()), &per_policy);
+        let paths: Vec<_> = cpu.policies.iter().map(|p| p.policy_path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                root.join("policy0"),
+                root.join("policy4"),
+                root.join("policy6")
+            ]
+        );
+        assert_eq!(cpu.policies[2].default_max.as_deref(), Some("2400000"));
+
+        cpu.apply_saving_mode(false);
+        let max = |n: u32| {
+            fs::read_to_string(root.join(format!("policy{}/scaling_max_freq", n)))
+                .unwrap()
+                .trim()
+                .to_string()
+        };
+        assert_eq!(max(0), "600000");
+        assert_eq!(max(4), "1200000");
+        // explicitly left alone
+        assert_eq!(max(6), "2400000");
+
+        cpu.apply_normal_mode(false);
+        assert_eq!(max(0), "1800000");
+        assert_eq!(max(4), "2400000");
+    }
+
     /// Whitespace inside the value ("100, 400") is tolerated via trim().
     #[test]
     fn test_cpu_freq_parsing_trims_whitespace() {
-        let tmp = env::temp_dir().join(format!(
-            "uconsole_sleep_test_{}",
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis()
-        ));
-        let _ = fs::create_dir_all(&tmp);
-        let cpu = CpuFreqConfig::with_policy_path(tmp.clone(), Some(String::from(" 100 , 400 ")));
+        let tmp = TempDir::new("sleep_test");
+        let cpu =
+            CpuFreqConfig::with_policy_path(tmp.to_path_buf(), Some(String::from(" 100 , 400 ")));
         cpu.apply_saving_mode(false);
         assert_eq!(
             fs::read_to_string(tmp.join("scaling_min_freq")).unwrap(),
@@ -0,0 +1,312 @@
+//! Saving frequency ranges: parsing `min,max` specs with units and keywords, and
+//! fitting them to what a cpufreq policy actually supports
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use log::{info, warn};
+
+use crate::error::Error;
+
+/// One end of a saving range
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FreqSpec {
+    Khz(u64),
+    /// `cpuinfo_min_freq`
+    Min,
+    /// `cpuinfo_max_freq`
+    Max,
+    /// Percentage of `cpuinfo_max_freq`
+    Percent(u8),
+}
+
+impl FromStr for FreqSpec {
+    type Err = Error;
+
+    /// A number with an optional unit (`600`, `600MHz`, `1.2GHz`, `600000kHz`; plain
+    /// numbers are MHz), `min`, `max` or a percentage (`50%`)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        let bad = |why: &str| Error::InvalidValue(format!("bad frequency '{}': {}", s, why));
+        match s.as_str() {
+            "min" => return Ok(FreqSpec::Min),
+            "max" => return Ok(FreqSpec::Max),
+            _ => {}
+        }
+        if let Some(p) = s.strip_suffix('%') {
+            return match p.trim().parse::<u8>() {
+                Ok(p) if (1..=100).contains(&p) => Ok(FreqSpec::Percent(p)),
+                _ => Err(bad("percentage must be 1-100")),
+            };
+        }
+        let (number, scale) = [("khz", 1.0), ("mhz", 1e3), ("ghz", 1e6)]
+            .iter()
+            .find_map(|(unit, scale)| Some((s.strip_suffix(unit)?, *scale)))
+            .unwrap_or((s.as_str(), 1e3));
+        let value: f64 = number.trim().parse().map_err(|_| bad("not a number"))?;
+        if !value.is_finite() || value <= 0.0 {
+            return Err(bad("must be positive"));
+        }
+        Ok(FreqSpec::Khz((value * scale).round() as u64))
+    }
+}
+
+impl fmt::Display for FreqSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FreqSpec::Khz(k) => write!(f, "{} kHz", k),
+            FreqSpec::Min => write!(f, "min"),
+            FreqSpec::Max => write!(f, "max"),
+            FreqSpec::Percent(p) => write!(f, "{}%", p),
+        }
+    }
+}
+
+/// Parse a `min,max` saving range
+pub fn parse_freq_range(s: &str) -> Result<(FreqSpec, FreqSpec), Error> {
+    match s.split(',').collect::<Vec<_>>().as_slice() {
+        [min, max] => Ok((min.parse()?, max.parse()?)),
+        _ => Err(Error::InvalidValue(format!(
+            "'{}' is not a min,max pair",
+            s.trim()
+        ))),
+    }
+}
+
+/// Which way a value between two available frequencies moves, so a clamp is never
+/// weaker than asked: a minimum goes up, a maximum goes down
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Snap {
+    Up,
+    Down,
+}
+
+/// What a policy supports, in kHz. Anything the kernel doesn't report is left out
+/// of the checks.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FreqLimits {
+    pub cpuinfo_min: Option<u64>,
+    pub cpuinfo_max: Option<u64>,
+    /// `scaling_available_frequencies`, sorted
+    pub available: Vec<u64>,
+}
+
+impl FreqLimits {
+    pub fn read(policy: &Path) -> Self {
+        let read = |name: &str| -> Option<u64> {
+            fs::read_to_string(policy.join(name))
+                .ok()?
+                .trim()
+                .parse()
+                .ok()
+        };
+        let mut available: Vec<u64> =
+            fs::read_to_string(policy.join("scaling_available_frequencies"))
+                .map(|s| {
+                    s.split_whitespace()
+                        .filter_map(|f| f.parse().ok())
+                        .collect()
+                })
+                .unwrap_or_default();
+        available.sort_unstable();
+        FreqLimits {
+            cpuinfo_min: read("cpuinfo_min_freq").or_else(|| available.first().copied()),
+            cpuinfo_max: read("cpuinfo_max_freq").or_else(|| available.last().copied()),
+            available,
+        }
+    }
+
+    /// Turn `spec` into kHz within the hardware range, on a supported step in the
+    /// direction of `snap`. `what` names the value in log messages.
+    pub fn resolve(&self, spec: FreqSpec, what: &str, snap: Snap) -> Result<u64, Error> {
+        let unknown =
+            |name: &str| Error::InvalidValue(format!("{} {}: {} unknown", what, spec, name));
+        let mut khz = match spec {
+            FreqSpec::Khz(k) => k,
+            FreqSpec::Min => self
+                .cpuinfo_min
+                .ok_or_else(|| unknown("cpuinfo_min_freq"))?,
+            FreqSpec::Max => self
+                .cpuinfo_max
+                .ok_or_else(|| unknown("cpuinfo_max_freq"))?,
+            FreqSpec::Percent(p) => {
+                self.cpuinfo_max
+                    .ok_or_else(|| unknown("cpuinfo_max_freq"))?
+                    * u64::from(p)
+                    / 100
+            }
+        };
+        if let Some(min) = self.cpuinfo_min
+            && khz < min
+        {
+            info!(
+                "{} {} kHz is below cpuinfo_min_freq, raised to {} kHz",
+                what, khz, min
+            );
+            khz = min;
+        }
+        if let Some(max) = self.cpuinfo_max
+            && khz > max
+        {
+            info!(
+                "{} {} kHz is above cpuinfo_max_freq, lowered to {} kHz",
+                what, khz, max
+            );
+            khz = max;
+        }
+        let (step, side) = match snap {
+            Snap::Up => (self.available.iter().find(|&&f| f >= khz), "up"),
+            Snap::Down => (self.available.iter().rev().find(|&&f| f <= khz), "down"),
+        };
+        match step {
+            Some(&f) if f != khz => {
+                info!(
+                    "{} {} kHz is not an available frequency, snapped {} to {} kHz",
+                    what, khz, side, f
+                );
+                khz = f;
+            }
+            Some(_) => {}
+            // the available steps don't span the cpuinfo range: the closest one on
+            // the wrong side, i.e. the lowest step when snapping down
+            None => {
+                let fallback = match snap {
+                    Snap::Up => self.available.iter().max(),
+                    Snap::Down => self.available.iter().min(),
+                };
+                if let Some(&f) = fallback {
+                    warn!(
+                        "{} {} kHz: no available frequency {} from it, using the {} step {} kHz",
+                        what,
+                        khz,
+                        side,
+                        if snap == Snap::Up {
+                            "highest"
+                        } else {
+                            "lowest"
+                        },
+                        f
+                    );
+                    khz = f;
+                }
+            }
+        }
+        Ok(khz)
+    }
+
+    /// Resolve a saving range; fails if min ends up above max. `context` (e.g. the
+    /// policy) prefixes the log messages about adjusted values.
+    pub fn resolve_range(
+        &self,
+        context: &str,
+        min: FreqSpec,
+        max: FreqSpec,
+    ) -> Result<(u64, u64), Error> {
+        let (min_khz, max_khz) = (
+            self.resolve(min, &format!("{}: saving min", context), Snap::Up)?,
+            self.resolve(max, &format!("{}: saving max", context), Snap::Down)?,
+        );
+        if min_khz > max_khz {
+            return Err(Error::InvalidValue(format!(
+                "min {} kHz is above max {} kHz",
+                min_khz, max_khz
+            )));
+        }
+        Ok((min_khz, max_khz))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_parse_freq_specs() {
+        assert_eq!("600".parse::<FreqSpec>().unwrap(), FreqSpec::Khz(600_000));
+        assert_eq!(
+            " 600 MHz".parse::<FreqSpec>().unwrap(),
+            FreqSpec::Khz(600_000)
+        );
+        assert_eq!(
+            "1.2GHz".parse::<FreqSpec>().unwrap(),
+            FreqSpec::Khz(1_200_000)
+        );
+        assert_eq!(
+            "750000kHz".parse::<FreqSpec>().unwrap(),
+            FreqSpec::Khz(750_000)
+        );
+        assert_eq!("MIN".parse::<FreqSpec>().unwrap(), FreqSpec::Min);
+        assert_eq!("50%".parse::<FreqSpec>().unwrap(), FreqSpec::Percent(50));
+        assert!("0%".parse::<FreqSpec>().is_err());
+        assert!("fast".parse::<FreqSpec>().is_err());
+        assert!("-5".parse::<FreqSpec>().is_err());
+        assert!(parse_freq_range("600").is_err());
+        assert!(parse_freq_range("1,2,3").is_err());
+    }
+
+    #[test]
+    fn test_resolve_snaps_and_clamps() {
+        let policy = TempDir::new("cpu_freq");
+        fs::write(policy.join("cpuinfo_min_freq"), "600000\n").unwrap();
+        fs::write(policy.join("cpuinfo_max_freq"), "1800000\n").unwrap();
+        fs::write(
+            policy.join("scaling_available_frequencies"),
+            "600000 750000 1000000 1500000 1800000 \n",
+        )
+        .unwrap();
+        let limits = FreqLimits::read(&policy);
+
+        let range = |s: &str| {
+            let (min, max) = parse_freq_range(s).unwrap();
+            limits.resolve_range("policy0", min, max)
+        };
+        // below the hardware minimum, and between steps
+        assert_eq!(range("100,800").unwrap(), (600_000, 750_000));
+        assert_eq!(range("min,max").unwrap(), (600_000, 1_800_000));
+        assert_eq!(range("min,50%").unwrap(), (600_000, 750_000));
+        assert_eq!(range("1GHz,3GHz").unwrap(), (1_000_000, 1_800_000));
+        // min goes up and max down to the next step, never the nearer one
+        assert_eq!(range("800,1400").unwrap(), (1_000_000, 1_000_000));
+        assert!(range("800,900").is_err());
+        assert!(range("1500,1000").is_err());
+
+        // steps that don't reach down to cpuinfo_min_freq: the lowest one it is
+        let sparse = FreqLimits {
+            cpuinfo_min: Some(100_000),
+            cpuinfo_max: Some(1_000_000),
+            available: vec![600_000, 1_000_000],
+        };
+        assert_eq!(
+            sparse
+                .resolve(FreqSpec::Khz(300_000), "max", Snap::Down)
+                .unwrap(),
+            600_000
+        );
+        // nor up to cpuinfo_max_freq: the highest one, even if another is nearer
+        let low_steps = FreqLimits {
+            cpuinfo_min: Some(100_000),
+            cpuinfo_max: Some(2_000_000),
+            available: vec![200_000, 600_000],
+        };
+        assert_eq!(
+            low_steps
+                .resolve(FreqSpec::Khz(1_900_000), "min", Snap::Up)
+                .unwrap(),
+            600_000
+        );
+
+        // nothing known: values pass through, keywords can't be resolved
+        let none = FreqLimits::default();
+        assert_eq!(
+            none.resolve_range("policy0", FreqSpec::Khz(100_000), FreqSpec::Khz(400_000))
+                .unwrap(),
+            (100_000, 400_000)
+        );
+        assert!(
+            none.resolve_range("policy0", FreqSpec::Min, FreqSpec::Max)
+                .is_err()
+        );
+    }
+}
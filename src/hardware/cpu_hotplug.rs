@@ -0,0 +1,263 @@
+//! CPU core hot-unplug: take cores offline in saving mode through
+//! `/sys/devices/system/cpu/cpuN/online` and bring back exactly those on exit
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use log::{debug, info, warn};
+
+use crate::error::Error;
+use crate::restore::Restored;
+
+pub const CPU_SYS_PATH: &str = "/sys/devices/system/cpu";
+
+/// Which cores go offline in saving mode
+#[derive(Clone, Debug, PartialEq)]
+pub enum OfflineCores {
+    /// Keep this many cores online, taking the highest-numbered ones offline
+    KeepOnline(usize),
+    /// Take exactly these cores offline
+    List(Vec<u32>),
+}
+
+impl OfflineCores {
+    /// A CPU list as in `SAVING_CPU_OFFLINE`; `none` or empty for no hotplug
+    pub fn parse_offline(s: &str) -> Result<Option<Self>, Error> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "none" => Ok(None),
+            list => parse_cpu_list(list).map(|cpus| Some(OfflineCores::List(cpus))),
+        }
+    }
+
+    /// The number of cores to keep online, as in `SAVING_CPU_ONLINE`
+    pub fn parse_online(s: &str) -> Result<Self, Error> {
+        match s.trim().parse::<usize>() {
+            Ok(0) => Err(Error::InvalidValue(
+                "at least one core stays online".to_string(),
+            )),
+            Ok(n) => Ok(OfflineCores::KeepOnline(n)),
+            Err(e) => Err(Error::InvalidValue(format!("'{}': {}", s, e))),
+        }
+    }
+}
+
+/// Parse a kernel style CPU list such as `1-3` or `0,2-3`
+pub fn parse_cpu_list(s: &str) -> Result<Vec<u32>, Error> {
+    let mut cpus = Vec::new();
+    for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let bad = || Error::InvalidValue(format!("bad CPU list entry '{}'", part));
+        match part.split_once('-') {
+            Some((a, b)) => {
+                let a: u32 = a.trim().parse().map_err(|_| bad())?;
+                let b: u32 = b.trim().parse().map_err(|_| bad())?;
+                if a > b {
+                    return Err(bad());
+                }
+                cpus.extend(a..=b);
+            }
+            None => cpus.push(part.parse().map_err(|_| bad())?),
+        }
+    }
+    if cpus.is_empty() {
+        return Err(Error::InvalidValue("empty CPU list".to_string()));
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+#[derive(Clone, Debug)]
+pub struct CpuHotplug {
+    pub cpu_path: PathBuf,
+    pub offline: OfflineCores,
+    /// Cores taken offline by [`CpuHotplug::take_offline`], brought back by
+    /// [`CpuHotplug::restore`]. Shared between clones of one saving config.
+    taken: Arc<Mutex<Vec<u32>>>,
+}
+
+impl CpuHotplug {
+    pub fn new(offline: OfflineCores) -> Self {
+        Self::with_cpu_path(PathBuf::from(CPU_SYS_PATH), offline)
+    }
+
+    pub fn with_cpu_path(cpu_path: PathBuf, offline: OfflineCores) -> Self {
+        CpuHotplug {
+            cpu_path,
+            offline,
+            taken: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Present cores and whether they are online, sorted by number. `None` means
+    /// the core has no `online` file and can't be taken offline (usually cpu0).
+    pub fn cores(&self) -> Vec<(u32, Option<bool>)> {
+        let Ok(entries) = fs::read_dir(&self.cpu_path) else {
+            return Vec::new();
+        };
+        let mut cores: Vec<(u32, Option<bool>)> = entries
+            .flatten()
+            .filter_map(|e| {
+                let name = e.file_name();
+                let n: u32 = name.to_str()?.strip_prefix("cpu")?.parse().ok()?;
+                let online = fs::read_to_string(e.path().join("online"))
+                    .ok()
+                    .map(|s| s.trim() == "1");
+                Some((n, online))
+            })
+            .collect();
+        cores.sort_unstable_by_key(|(n, _)| *n);
+        cores
+    }
+
+    /// Online cores that should go offline, highest first
+    fn targets(&self, cores: &[(u32, Option<bool>)]) -> Vec<u32> {
+        let mut targets: Vec<u32> = match &self.offline {
+            OfflineCores::KeepOnline(keep) => {
+                let online = cores.iter().filter(|(_, o)| *o != Some(false)).count();
+                cores
+                    .iter()
+                    .rev()
+                    .filter(|(_, o)| *o == Some(true))
+                    .take(online.saturating_sub(*keep))
+                    .map(|(n, _)| *n)
+                    .collect()
+            }
+            OfflineCores::List(list) => list
+                .iter()
+                .filter(|n| match cores.iter().find(|(c, _)| c == *n) {
+                    None => {
+                        warn!("cpu{} not present, not taking it offline", n);
+                        false
+                    }
+                    Some((_, None)) => {
+                        warn!("cpu{} can't be taken offline (no online file)", n);
+                        false
+                    }
+                    Some((_, Some(online))) => *online,
+                })
+                .copied()
+                .collect(),
+        };
+        targets.sort_unstable_by(|a, b| b.cmp(a));
+        targets
+    }
+
+    pub fn take_offline(&self, dry_run: bool) {
+        let mut taken = self.taken.lock().unwrap();
+        if !taken.is_empty() {
+            debug!("CPU: cores {:?} already offline", taken);
+            return;
+        }
+        for n in self.targets(&self.cores()) {
+            let path = self.cpu_path.join(format!("cpu{}/online", n));
+            if dry_run {
+                debug!("DRY-RUN: Would take cpu{} offline", n);
+                continue;
+            }
+            match fs::write(&path, "0") {
+                Ok(()) => taken.push(n),
+                Err(e) => warn!("Failed to take cpu{} offline: {}", n, e),
+            }
+        }
+        if !taken.is_empty() {
+            info!("CPU: cores {:?} offline", taken);
+        }
+    }
+
+    /// Bring back the cores [`CpuHotplug::take_offline`] took; cores that were
+    /// offline before stay offline
+    pub fn restore(&self, dry_run: bool) -> Vec<Restored> {
+        let mut taken = self.taken.lock().unwrap();
+        if taken.is_empty() {
+            return Vec::new();
+        }
+        for n in taken.iter().rev() {
+            let path = self.cpu_path.join(format!("cpu{}/online", n));
+            if dry_run {
+                debug!("DRY-RUN: Would bring cpu{} back online", n);
+            } else if let Err(e) = fs::write(&path, "1") {
+                warn!("Failed to bring cpu{} back online: {}", n, e);
+            }
+        }
+        let restored = vec![Restored::new("CPU", format!("cores {:?} online", taken))];
+        taken.clear();
+        restored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    /// cpu0 without an `online` file, cpu1-3 online except `offline`
+    fn fake_cpus(offline: &[u32]) -> TempDir {
+        let root = TempDir::new("hotplug");
+        fs::create_dir_all(root.join("cpu0")).unwrap();
+        fs::create_dir_all(root.join("cpufreq")).unwrap();
+        for n in 1..4 {
+            let dir = root.join(format!("cpu{}", n));
+            fs::create_dir_all(&dir).unwrap();
+            let state = if offline.contains(&n) { "0\n" } else { "1\n" };
+            fs::write(dir.join("online"), state).unwrap();
+        }
+        root
+    }
+
+    fn online(root: &std::path::Path) -> Vec<String> {
+        (1..4)
+            .map(|n| {
+                fs::read_to_string(root.join(format!("cpu{}/online", n)))
+                    .unwrap()
+                    .trim()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("1-3").unwrap(), vec![1, 2, 3]);
+        assert_eq!(parse_cpu_list("3, 1,2-2").unwrap(), vec![1, 2, 3]);
+        assert!(parse_cpu_list("3-1").is_err());
+        assert!(parse_cpu_list("one").is_err());
+        assert!(parse_cpu_list("").is_err());
+    }
+
+    #[test]
+    fn test_keep_online_restores_prior_set() {
+        // cpu2 was already offline before saving mode
+        let root = fake_cpus(&[2]);
+        let h = CpuHotplug::with_cpu_path(root.to_path_buf(), OfflineCores::KeepOnline(1));
+        assert_eq!(
+            h.cores(),
+            vec![
+                (0, None),
+                (1, Some(true)),
+                (2, Some(false)),
+                (3, Some(true))
+            ]
+        );
+        h.take_offline(false);
+        assert_eq!(online(&root), vec!["0", "0", "0"]);
+        assert_eq!(
+            h.restore(false),
+            vec![Restored::new("CPU", "cores [3, 1] online")]
+        );
+        assert_eq!(online(&root), vec!["1", "0", "1"]);
+        // nothing left to restore
+        assert!(h.restore(false).is_empty());
+    }
+
+    #[test]
+    fn test_list_skips_unpluggable_cores() {
+        let root = fake_cpus(&[]);
+        let h = CpuHotplug::with_cpu_path(root.to_path_buf(), OfflineCores::List(vec![0, 2, 3, 7]));
+        h.take_offline(false);
+        assert_eq!(online(&root), vec!["1", "0", "0"]);
+        // a second entry doesn't lose track of what to restore
+        h.clone().take_offline(false);
+        h.restore(false);
+        assert_eq!(online(&root), vec!["1", "1", "1"]);
+    }
+}
@@ -0,0 +1,350 @@
+//! devfreq (GPU and other devfreq-governed blocks) handling under `hardware`
+//! namespace: clamp `max_freq` and switch `governor` in saving mode
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use log::{debug, info, warn};
+
+use crate::hardware::cpu_freq::{FreqLimits, FreqSpec, Snap};
+use crate::restore::Restored;
+
+pub const DEVFREQ_PATH: &str = "/sys/class/devfreq";
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+/// `max_freq` and `governor` as they were on saving mode entry
+#[derive(Clone, Debug)]
+struct Replaced {
+    max_freq: Option<String>,
+    governor: Option<String>,
+}
+
+/// One devfreq device and what saving mode does to it
+#[derive(Clone, Debug)]
+pub struct DevfreqDevice {
+    pub path: PathBuf,
+    /// `max_freq` in saving mode, in Hz
+    pub saving_max: Option<u64>,
+    pub saving_governor: Option<String>,
+    /// Shared between clones of one saving config
+    replaced: Arc<Mutex<Option<Replaced>>>,
+}
+
+impl DevfreqDevice {
+    /// Fit `saving_max` to the device's `available_frequencies` and check
+    /// `saving_governor` against `available_governors`; either is dropped with a
+    /// warning if the device can't take it
+    pub fn with_device_path(
+        path: PathBuf,
+        saving_max: Option<FreqSpec>,
+        saving_governor: Option<String>,
+    ) -> Self {
+        let name = path.display().to_string();
+        // devfreq works in Hz, the shared limits code in kHz: resolve in kHz, then
+        // write the exact Hz step back (e.g. 333333333, not 333333000)
+        let mut available_hz: Vec<u64> = read_trimmed(&path.join("available_frequencies"))
+            .map(|s| {
+                s.split_whitespace()
+                    .filter_map(|f| f.parse::<u64>().ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        available_hz.sort_unstable();
+        let available_khz: Vec<u64> = available_hz.iter().map(|hz| hz / 1000).collect();
+        let limits = FreqLimits {
+            cpuinfo_min: available_khz.first().copied(),
+            cpuinfo_max: available_khz.last().copied(),
+            available: available_khz,
+        };
+        let saving_max = saving_max.and_then(|spec| {
+            match limits.resolve(spec, &format!("{}: saving max", name), Snap::Down) {
+                Ok(khz) => Some(
+                    available_hz
+                        .iter()
+                        .copied()
+                        .find(|hz| hz / 1000 == khz)
+                        .unwrap_or(khz * 1000),
+                ),
+                Err(e) => {
+                    warn!("{}: ignoring saving max: {}", name, e);
+                    None
+                }
+            }
+        });
+
+        let governors: Vec<String> = read_trimmed(&path.join("available_governors"))
+            .map(|s| s.split_whitespace().map(str::to_string).collect())
+            .unwrap_or_default();
+        let saving_governor = saving_governor.filter(|g| {
+            let known = governors.is_empty() || governors.contains(g);
+            if !known {
+                warn!(
+                    "{}: ignoring governor '{}': not in available_governors ({})",
+                    name,
+                    g,
+                    governors.join(" ")
+                );
+            }
+            known
+        });
+
+        DevfreqDevice {
+            path,
+            saving_max,
+            saving_governor,
+            replaced: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn write(&self, file: &str, value: &str, dry_run: bool) {
+        let path = self.path.join(file);
+        if dry_run {
+            debug!("DRY-RUN: Would write {} to {}", value, path.display());
+        } else if let Err(e) = fs::write(&path, value) {
+            warn!("Failed to write {} to {}: {}", value, path.display(), e);
+        }
+    }
+
+    pub fn apply_saving_mode(&self, dry_run: bool) {
+        if self.saving_max.is_none() && self.saving_governor.is_none() {
+            return;
+        }
+        let mut replaced = self.replaced.lock().unwrap();
+        if replaced.is_none() {
+            *replaced = Some(Replaced {
+                max_freq: read_trimmed(&self.path.join("max_freq")),
+                governor: read_trimmed(&self.path.join("governor")),
+            });
+        }
+        if let Some(g) = &self.saving_governor {
+            self.write("governor", g, dry_run);
+        }
+        if let Some(max) = self.saving_max {
+            // the kernel rejects a max below min_freq
+            let min = read_trimmed(&self.path.join("min_freq")).and_then(|m| m.parse::<u64>().ok());
+            let max = match min {
+                Some(min) if min > max => {
+                    warn!(
+                        "devfreq {}: saving max {} Hz is below min_freq {} Hz, using min_freq",
+                        self.path.display(),
+                        max,
+                        min
+                    );
+                    min
+                }
+                _ => max,
+            };
+            self.write("max_freq", &max.to_string(), dry_run);
+        }
+        info!(
+            "devfreq {}: saving mode max {:?} Hz, governor {:?}",
+            self.path.display(),
+            self.saving_max,
+            self.saving_governor
+        );
+    }
+
+    /// Put back `max_freq` and `governor` as found on saving mode entry
+    pub fn apply_normal_mode(&self, dry_run: bool) -> Vec<Restored> {
+        let Some(Replaced { max_freq, governor }) = self.replaced.lock().unwrap().take() else {
+            return Vec::new();
+        };
+        let mut restored = Vec::new();
+        if self.saving_governor.is_some()
+            && let Some(g) = &governor
+        {
+            self.write("governor", g, dry_run);
+            restored.push(format!("governor {}", g));
+        }
+        if self.saving_max.is_some()
+            && let Some(m) = &max_freq
+        {
+            self.write("max_freq", m, dry_run);
+            restored.push(format!("max {} Hz", m));
+        }
+        if restored.is_empty() {
+            return Vec::new();
+        }
+        vec![Restored::new(
+            format!("devfreq {}", self.path.display()),
+            restored.join(", "),
+        )]
+    }
+}
+
+/// Every devfreq device that saving mode clamps
+#[derive(Clone, Debug, Default)]
+pub struct DevfreqConfig {
+    pub devices: Vec<DevfreqDevice>,
+}
+
+impl DevfreqConfig {
+    pub fn new(
+        saving_max: Option<FreqSpec>,
+        saving_governor: Option<String>,
+        names: &[String],
+    ) -> Self {
+        Self::with_devfreq_path(
+            PathBuf::from(DEVFREQ_PATH),
+            saving_max,
+            saving_governor,
+            names,
+        )
+    }
+
+    /// Devices below `dir`, all of them or only those in `names`. Nothing is set up
+    /// when there is nothing to change.
+    pub fn with_devfreq_path(
+        dir: PathBuf,
+        saving_max: Option<FreqSpec>,
+        saving_governor: Option<String>,
+        names: &[String],
+    ) -> Self {
+        if saving_max.is_none() && saving_governor.is_none() {
+            return DevfreqConfig::default();
+        }
+        let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+            .map(|entries| entries.flatten().map(|e| e.path()).collect())
+            .unwrap_or_default();
+        paths.sort();
+        for name in names {
+            if !paths
+                .iter()
+                .any(|p| p.file_name().is_some_and(|f| f == name.as_str()))
+            {
+                warn!("devfreq device {} not found in {}", name, dir.display());
+            }
+        }
+        let devices = paths
+            .into_iter()
+            .filter(|p| {
+                names.is_empty()
+                    || p.file_name()
+                        .is_some_and(|f| names.iter().any(|n| f == n.as_str()))
+            })
+            .map(|p| DevfreqDevice::with_device_path(p, saving_max, saving_governor.clone()))
+            .collect();
+        DevfreqConfig { devices }
+    }
+
+    pub fn apply_saving_mode(&self, dry_run: bool) {
+        for d in &self.devices {
+            d.apply_saving_mode(dry_run);
+        }
+    }
+
+    pub fn apply_normal_mode(&self, dry_run: bool) -> Vec<Restored> {
+        self.devices
+            .iter()
+            .flat_map(|d| d.apply_normal_mode(dry_run))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn fake_devfreq() -> TempDir {
+        let root = TempDir::new("devfreq");
+        for name in ["fe000000.v3d", "soc:dmc"] {
+            let dev = root.join(name);
+            fs::create_dir_all(&dev).unwrap();
+            fs::write(
+                dev.join("available_frequencies"),
+                "250000000 333333333 500000000\n",
+            )
+            .unwrap();
+            fs::write(
+                dev.join("available_governors"),
+                "simple_ondemand powersave performance\n",
+            )
+            .unwrap();
+            fs::write(dev.join("max_freq"), "500000000\n").unwrap();
+            fs::write(dev.join("governor"), "simple_ondemand\n").unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_devfreq_clamp_and_restore() {
+        let root = fake_devfreq();
+        let v3d = root.join("fe000000.v3d");
+        let cfg = DevfreqConfig::with_devfreq_path(
+            root.to_path_buf(),
+            Some("300MHz".parse().unwrap()),
+            Some("powersave".to_string()),
+            &["fe000000.v3d".to_string()],
+        );
+        assert_eq!(cfg.devices.len(), 1);
+        // snapped down to an available frequency
+        assert_eq!(cfg.devices[0].saving_max, Some(250_000_000));
+
+        cfg.apply_saving_mode(false);
+        assert_eq!(
+            fs::read_to_string(v3d.join("max_freq")).unwrap(),
+            "250000000"
+        );
+        assert_eq!(
+            fs::read_to_string(v3d.join("governor")).unwrap(),
+            "powersave"
+        );
+        let restored = cfg.apply_normal_mode(false);
+        assert_eq!(restored.len(), 1);
+        assert_eq!(
+            restored[0].detail,
+            "governor simple_ondemand, max 500000000 Hz"
+        );
+        assert_eq!(
+            fs::read_to_string(v3d.join("max_freq")).unwrap(),
+            "500000000"
+        );
+        assert_eq!(
+            fs::read_to_string(v3d.join("governor")).unwrap(),
+            "simple_ondemand"
+        );
+        // not selected, untouched
+        assert_eq!(
+            fs::read_to_string(root.join("soc:dmc/max_freq")).unwrap(),
+            "500000000\n"
+        );
+
+        // a min_freq above the saving max holds it up
+        fs::write(v3d.join("min_freq"), "333333333\n").unwrap();
+        cfg.apply_saving_mode(false);
+        assert_eq!(
+            fs::read_to_string(v3d.join("max_freq")).unwrap(),
+            "333333333"
+        );
+        cfg.apply_normal_mode(false);
+        assert_eq!(
+            fs::read_to_string(v3d.join("max_freq")).unwrap(),
+            "500000000"
+        );
+    }
+
+    #[test]
+    fn test_devfreq_rejects_unknown_governor() {
+        let root = fake_devfreq();
+        let cfg = DevfreqConfig::with_devfreq_path(
+            root.to_path_buf(),
+            Some(FreqSpec::Percent(70)),
+            Some("turbo".to_string()),
+            &[],
+        );
+        assert_eq!(cfg.devices.len(), 2);
+        assert_eq!(cfg.devices[0].saving_governor, None);
+        // the exact step, not one rounded through kHz
+        assert_eq!(cfg.devices[0].saving_max, Some(333_333_333));
+
+        assert!(
+            DevfreqConfig::with_devfreq_path(root.to_path_buf(), None, None, &[])
+                .devices
+                .is_empty()
+        );
+    }
+}
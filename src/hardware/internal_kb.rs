@@ -2,9 +2,14 @@
 
 use crate::error::Error;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 const USB_DEVICES_PATH: &str = "/sys/bus/usb/devices";
+const INPUT_CLASS_PATH: &str = "/sys/class/input";
+const DEV_INPUT_PATH: &str = "/dev/input";
+
+/// USB IDs of the uConsole built-in keyboard/trackball
+pub const UCONSOLE_KB_IDS: &[&str] = &["1eaf:0024"];
 
 /// Find internal keyboard USB device
 ///
@@ -45,3 +50,90 @@ pub fn find_internal_kb(ids: &[&str]) -> Result<Option<PathBuf>, Error> {
         Err(e) => Err(Error::from(e)),
     }
 }
+
+/// Find the evdev nodes (`/dev/input/eventN`) that belong to a USB device
+///
+/// # Arguments
+/// * `device_path` - sysfs path of the USB device, as returned by [`find_internal_kb`]
+///
+/// # Returns
+/// - Ok(Vec<PathBuf>) with the event nodes, sorted (may be empty)
+/// - Err(Error) if error occurred
+pub fn find_event_nodes(device_path: &Path) -> Result<Vec<PathBuf>, Error> {
+    find_event_nodes_in(
+        Path::new(INPUT_CLASS_PATH),
+        Path::new(DEV_INPUT_PATH),
+        device_path,
+    )
+}
+
+/// [`find_event_nodes`] with injectable sysfs input class and /dev directories
+pub fn find_event_nodes_in(
+    input_class: &Path,
+    dev_dir: &Path,
+    device_path: &Path,
+) -> Result<Vec<PathBuf>, Error> {
+    let device_real = fs::canonicalize(device_path)?;
+    let mut nodes = Vec::new();
+
+    match fs::read_dir(input_class) {
+        Ok(entries) => {
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                let Some(name) = name.to_str() else { continue };
+                if !name.starts_with("event") {
+                    continue;
+                }
+                // eventN/device points at inputM, which lives below the USB interface
+                if let Ok(parent) = fs::canonicalize(entry.path().join("device"))
+                    && parent.starts_with(&device_real)
+                {
+                    nodes.push(dev_dir.join(name));
+                }
+            }
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(Error::from(e)),
+    }
+    nodes.sort();
+    Ok(nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_find_internal_kb_event_nodes() {
+        let tmp = TempDir::new("kb");
+        let usb = tmp.join("usb/1-1.4");
+        let kb_input = usb.join("1-1.4:1.0/input/input5");
+        let mouse_input = usb.join("1-1.4:1.1/input/input6");
+        let other_input = tmp.join("usb/1-1.2/1-1.2:1.0/input/input7");
+        for d in [&kb_input, &mouse_input, &other_input] {
+            fs::create_dir_all(d).unwrap();
+        }
+        let class = tmp.join("class");
+        for (ev, target) in [
+            ("event3", &kb_input),
+            ("event4", &mouse_input),
+            ("event5", &other_input),
+        ] {
+            fs::create_dir_all(class.join(ev)).unwrap();
+            symlink(target, class.join(ev).join("device")).unwrap();
+        }
+        // non-event entries (e.g. mouse0, input5) are skipped
+        fs::create_dir_all(class.join("mouse0")).unwrap();
+
+        let nodes = find_event_nodes_in(&class, Path::new("/dev/input"), &usb).unwrap();
+        assert_eq!(
+            nodes,
+            vec![
+                PathBuf::from("/dev/input/event3"),
+                PathBuf::from("/dev/input/event4")
+            ]
+        );
+    }
+}
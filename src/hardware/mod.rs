@@ -1,16 +1,21 @@
 //! Hardware detection modules
 
 pub mod backlight;
+pub mod battery;
 pub mod cpu;
+pub mod cpu_freq;
+pub mod cpu_hotplug;
+pub mod devfreq;
 pub mod drm_panel;
 pub mod framebuffer;
 pub mod internal_kb;
 pub mod power_key;
 pub mod rf;
+pub mod rfkill;
 
 // pub use backlight::find_backlight;
 // pub use cpu::CpuFreqConfig;
 // pub use drm_panel::find_drm_panel;
 // pub use internal_kb::find_internal_kb;
 // pub use power_key::find_power_key;
-// pub use rf::find_default_rfkill_path;
+// pub use rf::find_rfkill;
@@ -1,11 +1,95 @@
 //! Power key event detection
 use crate::error::Error;
+use crate::input::{DeviceInfo, InputDevice, keycode_from_name};
+use crate::uinput::VIRTUAL_DEVICE_NAME;
+use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 const EVENT_PATH: &str = "/dev/input/by-path";
+const DEV_INPUT_PATH: &str = "/dev/input";
 const POWER_KEY_IDENTIFIER: &str = "axp221-pek";
 
+/// How to pick the power key input device
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum PowerKeySelector {
+    /// The AXP221 PEK entry under `/dev/input/by-path`
+    #[default]
+    Default,
+    /// An explicit evdev node
+    Path(PathBuf),
+    /// First device whose EVIOCGNAME contains this string
+    Name(String),
+    /// First device whose EVIOCGPHYS contains this string
+    Phys(String),
+    /// First device that advertises this key code via EVIOCGBIT
+    Keycode(u16),
+}
+
+impl FromStr for PowerKeySelector {
+    type Err = Error;
+
+    /// Parse `default`, `path:<node>` (or a bare absolute path), `name:<str>`,
+    /// `phys:<str>` or `keycode:<KEY_NAME or number>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (kind, val) = match s.split_once(':') {
+            Some((k, v)) => (k.to_ascii_lowercase(), v.trim()),
+            None if s.starts_with('/') => ("path".to_string(), s),
+            None if s.eq_ignore_ascii_case("default") || s.is_empty() => {
+                return Ok(PowerKeySelector::Default);
+            }
+            None => {
+                return Err(Error::InvalidValue(format!(
+                    "unknown power key selector '{}'",
+                    s
+                )));
+            }
+        };
+        if val.is_empty() {
+            return Err(Error::InvalidValue(format!("empty value in '{}'", s)));
+        }
+        match kind.as_str() {
+            "path" => Ok(PowerKeySelector::Path(PathBuf::from(val))),
+            "name" => Ok(PowerKeySelector::Name(val.to_string())),
+            "phys" => Ok(PowerKeySelector::Phys(val.to_string())),
+            "keycode" => keycode_from_name(val)
+                .map(PowerKeySelector::Keycode)
+                .ok_or_else(|| Error::InvalidValue(format!("unknown key '{}'", val))),
+            other => Err(Error::InvalidValue(format!(
+                "unknown power key selector '{}'",
+                other
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for PowerKeySelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PowerKeySelector::Default => write!(f, "default ({})", POWER_KEY_IDENTIFIER),
+            PowerKeySelector::Path(p) => write!(f, "path:{}", p.display()),
+            PowerKeySelector::Name(n) => write!(f, "name:{}", n),
+            PowerKeySelector::Phys(p) => write!(f, "phys:{}", p),
+            PowerKeySelector::Keycode(k) => write!(f, "keycode:{}", k),
+        }
+    }
+}
+
+impl PowerKeySelector {
+    /// Whether a device with the given identification satisfies the selector.
+    /// `Default` and `Path` select by location, so they never match on info alone.
+    pub fn matches(&self, info: &DeviceInfo) -> bool {
+        match self {
+            PowerKeySelector::Default | PowerKeySelector::Path(_) => false,
+            PowerKeySelector::Name(n) => info.name.contains(n.as_str()),
+            PowerKeySelector::Phys(p) => info.phys.contains(p.as_str()),
+            PowerKeySelector::Keycode(k) => info.supports_key(*k),
+        }
+    }
+}
+
 /// Find power key input device
 ///
 /// # Returns
@@ -31,6 +115,57 @@ pub fn find_power_key() -> Result<Option<PathBuf>, Error> {
     }
 }
 
+/// Find the power key input device using a selector
+///
+/// # Arguments
+/// * `selector` - How to identify the device
+///
+/// # Returns
+/// - Ok(Some(PathBuf)) if a matching device was found
+/// - Ok(None) if not found
+/// - Err(Error) if error occurred
+pub fn find_power_key_by(selector: &PowerKeySelector) -> Result<Option<PathBuf>, Error> {
+    match selector {
+        PowerKeySelector::Default => find_power_key(),
+        PowerKeySelector::Path(p) => Ok(p.exists().then(|| p.clone())),
+        _ => {
+            for node in list_event_nodes(Path::new(DEV_INPUT_PATH))? {
+                // nodes we can't open (permissions, races with unplug) are just skipped
+                let Ok(dev) = InputDevice::open(&node) else {
+                    continue;
+                };
+                let info = dev.info();
+                // our own uinput device may advertise KEY_POWER too
+                if info.name == VIRTUAL_DEVICE_NAME {
+                    continue;
+                }
+                if selector.matches(&info) {
+                    return Ok(Some(node));
+                }
+            }
+            Ok(None)
+        }
+    }
+}
+
+/// List `eventN` nodes in `dir`, in numeric order
+pub fn list_event_nodes(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut nodes: Vec<(u32, PathBuf)> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|e| {
+                let name = e.file_name();
+                let n = name.to_str()?.strip_prefix("event")?.parse::<u32>().ok()?;
+                Some((n, e.path()))
+            })
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(Error::from(e)),
+    };
+    nodes.sort();
+    Ok(nodes.into_iter().map(|(_, p)| p).collect())
+}
+
 /// Check if power key device is readable
 ///
 /// # Arguments
@@ -53,3 +188,70 @@ pub fn is_power_key_readable(device_path: &Path) -> Result<bool, Error> {
         Err(e) => Err(Error::from(e)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_parse_selector() {
+        assert_eq!(
+            "default".parse::<PowerKeySelector>().unwrap(),
+            PowerKeySelector::Default
+        );
+        assert_eq!(
+            "/dev/input/event2".parse::<PowerKeySelector>().unwrap(),
+            PowerKeySelector::Path(PathBuf::from("/dev/input/event2"))
+        );
+        assert_eq!(
+            "name:gpio-keys".parse::<PowerKeySelector>().unwrap(),
+            PowerKeySelector::Name("gpio-keys".to_string())
+        );
+        assert_eq!(
+            "PHYS: gpio-keys/input0"
+                .parse::<PowerKeySelector>()
+                .unwrap(),
+            PowerKeySelector::Phys("gpio-keys/input0".to_string())
+        );
+        assert_eq!(
+            "keycode:KEY_SLEEP".parse::<PowerKeySelector>().unwrap(),
+            PowerKeySelector::Keycode(142)
+        );
+        assert!("keycode:KEY_BOGUS".parse::<PowerKeySelector>().is_err());
+        assert!("name:".parse::<PowerKeySelector>().is_err());
+        assert!("serial:abc".parse::<PowerKeySelector>().is_err());
+        assert!("gpio-keys".parse::<PowerKeySelector>().is_err());
+    }
+
+    #[test]
+    fn test_selector_matches_info() {
+        let mut key_bits = vec![0u8; 32];
+        key_bits[116 / 8] |= 1 << (116 % 8);
+        let info = DeviceInfo {
+            name: "axp20x-pek".to_string(),
+            phys: "m1kbd/input2".to_string(),
+            key_bits,
+            ..Default::default()
+        };
+        assert!(PowerKeySelector::Name("axp20x".to_string()).matches(&info));
+        assert!(!PowerKeySelector::Name("gpio-keys".to_string()).matches(&info));
+        assert!(PowerKeySelector::Phys("input2".to_string()).matches(&info));
+        assert!(PowerKeySelector::Keycode(116).matches(&info));
+        assert!(!PowerKeySelector::Keycode(142).matches(&info));
+        assert!(!PowerKeySelector::Default.matches(&info));
+    }
+
+    #[test]
+    fn test_list_event_nodes_sorted_numerically() {
+        let tmp = TempDir::new("evnodes");
+        for n in ["event10", "event2", "mouse0", "eventX"] {
+            fs::write(tmp.join(n), "").unwrap();
+        }
+        assert_eq!(
+            list_event_nodes(&tmp).unwrap(),
+            vec![tmp.join("event2"), tmp.join("event10")]
+        );
+        assert!(list_event_nodes(&tmp.join("missing")).unwrap().is_empty());
+    }
+}
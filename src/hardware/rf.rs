@@ -1,13 +1,221 @@
 //! RF (rfkill) helpers
+//!
+//! rfkill entries are found by their `type` (and optionally `name`) under
+//! `/sys/class/rfkill` each time they are used, so the numbering changing with a
+//! USB Wi-Fi dongle or a modem doesn't make us block the wrong radio.
+//!
+//! Blocking writes each entry's `state` file, or with [`RfConfig::with_rfkill_dev`]
+//! sends `RFKILL_OP_CHANGE` through `/dev/rfkill` (see [`crate::hardware::rfkill`]).
 use std::{
-    fs,
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
     path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
 };
 
 use log::{debug, info, warn};
 
-pub const RFKILL_PATH_BT: &str = "/sys/class/rfkill/rfkill0";
-pub const RFKILL_PATH_WIFI: &str = "/sys/class/rfkill/rfkill1";
+use crate::error::Error;
+use crate::hardware::rfkill::write_change;
+use crate::restore::Restored;
+
+pub const RFKILL_CLASS_PATH: &str = "/sys/class/rfkill";
+
+/// Entries we unblocked whose change event hasn't come through `/dev/rfkill` yet
+static OWN_UNBLOCKS: Mutex<BTreeSet<u32>> = Mutex::new(BTreeSet::new());
+
+/// Whether the unblock of entry `idx` seen on the event stream was one of ours;
+/// each unblock is only claimed once
+pub fn take_own_unblock(idx: u32) -> bool {
+    OWN_UNBLOCKS.lock().unwrap().remove(&idx)
+}
+
+/// Note that we're about to unblock `path`, if it's blocked now. Done before the
+/// write, as the event loop may see the change before the write returns.
+fn mark_own_unblock(path: &Path) -> Option<u32> {
+    let idx = rfkill_index(path)?;
+    if !soft_blocked(path) {
+        // no change event will follow
+        return None;
+    }
+    OWN_UNBLOCKS.lock().unwrap().insert(idx);
+    Some(idx)
+}
+
+fn unmark_own_unblock(idx: Option<u32>) {
+    if let Some(idx) = idx {
+        OWN_UNBLOCKS.lock().unwrap().remove(&idx);
+    }
+}
+
+/// Radio types as reported in rfkill's `type` file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum RfType {
+    Wlan,
+    Bluetooth,
+    Wwan,
+    Gps,
+    Nfc,
+}
+
+impl RfType {
+    pub const ALL: [RfType; 5] = [
+        RfType::Wlan,
+        RfType::Bluetooth,
+        RfType::Wwan,
+        RfType::Gps,
+        RfType::Nfc,
+    ];
+
+    /// Name used in log messages
+    pub fn label(&self) -> &'static str {
+        match self {
+            RfType::Wlan => "WiFi",
+            RfType::Bluetooth => "BT",
+            RfType::Wwan => "WWAN",
+            RfType::Gps => "GPS",
+            RfType::Nfc => "NFC",
+        }
+    }
+}
+
+impl FromStr for RfType {
+    type Err = Error;
+
+    /// The kernel type name, or `wifi` / `bt` as used in config keys
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "wlan" | "wifi" => Ok(RfType::Wlan),
+            "bluetooth" | "bt" => Ok(RfType::Bluetooth),
+            "wwan" => Ok(RfType::Wwan),
+            "gps" => Ok(RfType::Gps),
+            "nfc" => Ok(RfType::Nfc),
+            other => Err(Error::InvalidValue(format!(
+                "unknown radio type '{}' (expected wlan, bluetooth, wwan, gps or nfc)",
+                other
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for RfType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RfType::Wlan => write!(f, "wlan"),
+            RfType::Bluetooth => write!(f, "bluetooth"),
+            RfType::Wwan => write!(f, "wwan"),
+            RfType::Gps => write!(f, "gps"),
+            RfType::Nfc => write!(f, "nfc"),
+        }
+    }
+}
+
+/// One `/sys/class/rfkill/rfkillN` entry
+#[derive(Clone, Debug, PartialEq)]
+pub struct RfkillDevice {
+    pub path: PathBuf,
+    pub index: u32,
+    /// `type`, e.g. `wlan`; kept as a string since the kernel knows more types
+    pub kind: String,
+    /// `name`, e.g. `phy0` or `hci0`
+    pub name: String,
+}
+
+/// Every rfkill entry below `dir`, by index
+pub fn list_rfkill(dir: &Path) -> Vec<RfkillDevice> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let read = |p: &Path, f: &str| {
+        fs::read_to_string(p.join(f))
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default()
+    };
+    let mut devices: Vec<RfkillDevice> = entries
+        .flatten()
+        .filter_map(|e| {
+            let index = e
+                .file_name()
+                .to_str()?
+                .strip_prefix("rfkill")?
+                .parse()
+                .ok()?;
+            let path = e.path();
+            Some(RfkillDevice {
+                index,
+                kind: read(&path, "type"),
+                name: read(&path, "name"),
+                path,
+            })
+        })
+        .collect();
+    devices.sort_unstable_by_key(|d| d.index);
+    devices
+}
+
+/// The rfkill entries of one type in the system
+pub fn find_rfkill(kind: RfType) -> Vec<PathBuf> {
+    RfConfig::new(kind, false, RfSelector::All).devices()
+}
+
+/// Which entries of a radio type to act on
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum RfSelector {
+    /// Every entry of the type
+    #[default]
+    All,
+    /// Entries with this `name`
+    Name(String),
+    /// One fixed entry directory, whatever its type
+    Path(PathBuf),
+}
+
+impl FromStr for RfSelector {
+    type Err = Error;
+
+    /// Parse `all`, `name:<name>` (or a bare name like `phy0`) or `path:<dir>` (or a
+    /// bare absolute path)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (kind, val) = match s.split_once(':') {
+            Some((k, v)) => (k.to_ascii_lowercase(), v.trim()),
+            None if s.is_empty() || s.eq_ignore_ascii_case("all") => {
+                return Ok(RfSelector::All);
+            }
+            None if s.starts_with('/') => ("path".to_string(), s),
+            None => ("name".to_string(), s),
+        };
+        if val.is_empty() {
+            return Err(Error::InvalidValue(format!("empty value in '{}'", s)));
+        }
+        match kind.as_str() {
+            "path" => Ok(RfSelector::Path(PathBuf::from(val))),
+            "name" => Ok(RfSelector::Name(val.to_string())),
+            other => Err(Error::InvalidValue(format!(
+                "unknown rfkill selector '{}'",
+                other
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for RfSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RfSelector::All => write!(f, "all"),
+            RfSelector::Name(n) => write!(f, "name:{}", n),
+            RfSelector::Path(p) => write!(f, "path:{}", p.display()),
+        }
+    }
+}
+
+/// Config for one radio type: whether saving mode blocks it, and which entries
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RfSetting {
+    pub toggle: bool,
+    pub select: RfSelector,
+}
 
 pub fn rfkill_state_path(path: &std::path::Path) -> PathBuf {
     path.join("state")
@@ -25,151 +233,444 @@ pub fn write_rfkill_state(path: &Path, block: bool, dry_run: bool) {
     }
     let _ = std::fs::write(&state, if block { "0" } else { "1" });
     info!(
-        "WiFi: {} via {}",
+        "rfkill: {} via {}",
         if block { "blocked" } else { "unblocked" },
         state.display()
     );
 }
 
-pub fn find_default_rfkill_path() -> Option<PathBuf> {
-    let p = PathBuf::from(RFKILL_PATH_WIFI);
-    if p.exists() { Some(p) } else { None }
-}
-
-pub fn find_default_rfkill_path_bt() -> Option<PathBuf> {
-    let p = PathBuf::from(RFKILL_PATH_BT);
-    if p.exists() { Some(p) } else { None }
+/// Flip an rfkill soft block: unblocked ("1") becomes blocked, anything else unblocked.
+/// Returns the new blocked state.
+pub fn toggle_rfkill_state(path: &Path, dry_run: bool) -> Result<bool, crate::Error> {
+    let state = rfkill_state_path(path);
+    let current = fs::read_to_string(&state)?;
+    let block = current.trim() == "1";
+    if dry_run {
+        debug!(
+            "DRY-RUN: would write '{}' to {}",
+            if block { "0" } else { "1" },
+            state.display()
+        );
+        return Ok(block);
+    }
+    fs::write(&state, if block { "0" } else { "1" })?;
+    Ok(block)
 }
 
-/// Enter power-saving mode: turn display off and reduce CPU freq (order matters)
-/// RF toggling configuration
+/// RF toggling configuration for one radio type
 #[derive(Clone, Debug)]
-pub struct WifiConfig {
+pub struct RfConfig {
+    pub kind: RfType,
+    /// Block in saving mode
     pub enabled: bool,
-    pub rfkill_path: Option<PathBuf>,
+    pub select: RfSelector,
+    rfkill_dir: PathBuf,
+    /// rfkill character device to block through instead of `state` files
+    rfkill_dev: Option<PathBuf>,
+    /// Whether each entry was soft blocked when saving mode blocked it. Shared
+    /// between clones of one saving config.
+    blocked_before: Arc<Mutex<BTreeMap<PathBuf, bool>>>,
 }
 
-impl WifiConfig {
-    pub fn new(enabled: bool, rfkill_path: Option<PathBuf>) -> Self {
-        let mut p = rfkill_path;
-        if enabled && p.is_none() {
-            p = Some(PathBuf::from(RFKILL_PATH_WIFI));
-        }
-        WifiConfig {
+/// Whether the entry at `path` is soft blocked; `state` stands in on kernels
+/// without a `soft` file
+fn soft_blocked(path: &Path) -> bool {
+    match fs::read_to_string(path.join("soft")) {
+        Ok(s) => s.trim() == "1",
+        Err(_) => fs::read_to_string(rfkill_state_path(path)).is_ok_and(|s| s.trim() != "1"),
+    }
+}
+
+/// Index of an `rfkillN` entry directory
+fn rfkill_index(path: &Path) -> Option<u32> {
+    path.file_name()?
+        .to_str()?
+        .strip_prefix("rfkill")?
+        .parse()
+        .ok()
+}
+
+impl RfConfig {
+    pub fn new(kind: RfType, enabled: bool, select: RfSelector) -> Self {
+        Self::with_rfkill_dir(kind, enabled, select, PathBuf::from(RFKILL_CLASS_PATH))
+    }
+
+    pub fn with_rfkill_dir(
+        kind: RfType,
+        enabled: bool,
+        select: RfSelector,
+        rfkill_dir: PathBuf,
+    ) -> Self {
+        RfConfig {
+            kind,
             enabled,
-            rfkill_path: p,
+            select,
+            rfkill_dir,
+            rfkill_dev: None,
+            blocked_before: Arc::new(Mutex::new(BTreeMap::new())),
         }
     }
 
-    pub fn block(&self, dry_run: bool) {
-        if !self.enabled {
-            return;
+    /// Block and unblock through the rfkill character device `dev` (normally
+    /// `/dev/rfkill`); `None` writes `state` files
+    pub fn with_rfkill_dev(mut self, dev: Option<PathBuf>) -> Self {
+        self.rfkill_dev = dev;
+        self
+    }
+
+    /// Whether rfkill entry `idx` is one this config acts on
+    pub fn covers(&self, idx: u32) -> bool {
+        self.devices().iter().any(|p| rfkill_index(p) == Some(idx))
+    }
+
+    /// The entries this config acts on, looked up now
+    pub fn devices(&self) -> Vec<PathBuf> {
+        if let RfSelector::Path(p) = &self.select {
+            return vec![p.clone()];
         }
-        if let Some(path) = &self.rfkill_path {
-            let state = path.join("state");
+        let kind = self.kind.to_string();
+        list_rfkill(&self.rfkill_dir)
+            .into_iter()
+            .filter(|d| d.kind == kind)
+            .filter(|d| match &self.select {
+                RfSelector::Name(n) => d.name == *n,
+                _ => true,
+            })
+            .map(|d| d.path)
+            .collect()
+    }
+
+    fn write_all(&self, devices: &[PathBuf], block: bool, dry_run: bool) {
+        let value = if block { "0" } else { "1" };
+        for path in devices {
+            let marked = if block || dry_run {
+                None
+            } else {
+                mark_own_unblock(path)
+            };
+            if let (Some(dev), Some(idx)) = (&self.rfkill_dev, rfkill_index(path)) {
+                if dry_run {
+                    debug!(
+                        "DRY-RUN: would {} rfkill{} via {}",
+                        if block { "block" } else { "unblock" },
+                        idx,
+                        dev.display()
+                    );
+                    continue;
+                }
+                // the index is only trusted for the type we looked it up as
+                let kind = (!matches!(self.select, RfSelector::Path(_))).then_some(self.kind);
+                match write_change(dev, idx, kind, block) {
+                    Ok(()) => {
+                        debug!(
+                            "{}: {} rfkill{} via {}",
+                            self.kind.label(),
+                            if block { "blocked" } else { "unblocked" },
+                            idx,
+                            dev.display()
+                        );
+                        continue;
+                    }
+                    Err(e) => warn!("{}, falling back to sysfs", e),
+                }
+            }
+            let state = rfkill_state_path(path);
             if dry_run {
-                debug!("DRY-RUN: would write '0' to {}", state.display());
-                return;
+                debug!("DRY-RUN: would write '{}' to {}", value, state.display());
+                continue;
+            }
+            match fs::write(&state, value) {
+                Ok(()) => debug!(
+                    "{}: {} via {}",
+                    self.kind.label(),
+                    if block { "blocked" } else { "unblocked" },
+                    state.display()
+                ),
+                Err(e) => {
+                    unmark_own_unblock(marked);
+                    warn!("Failed to write {}: {}", state.display(), e)
+                }
             }
-            let _ = fs::write(&state, "0");
-            debug!("WiFi: blocked via {}", state.display());
-        } else {
-            warn!("WiFi toggling enabled but no rfkill path provided");
         }
     }
 
-    pub fn unblock(&self, dry_run: bool) {
+    /// Block the entries, first recording which of them were already blocked
+    pub fn block(&self, dry_run: bool) {
         if !self.enabled {
             return;
         }
-        if let Some(path) = &self.rfkill_path {
-            let state = path.join("state");
-            if dry_run {
-                debug!("DRY-RUN: would write '1' to {}", state.display());
-                return;
-            }
-            let _ = fs::write(&state, "1");
-            debug!("WiFi: unblocked via {}", state.display());
-        } else {
-            warn!("WiFi toggling enabled but no rfkill path provided");
+        let devices = self.devices();
+        if devices.is_empty() {
+            warn!(
+                "{} toggling enabled but no {} rfkill entry ({}) found",
+                self.kind.label(),
+                self.kind,
+                self.select
+            );
+        }
+        let mut before = self.blocked_before.lock().unwrap();
+        for path in &devices {
+            before
+                .entry(path.clone())
+                .or_insert_with(|| soft_blocked(path));
         }
+        self.write_all(&devices, true, dry_run);
     }
-}
-
-/// Bluetooth (BT) toggling configuration
-#[derive(Clone, Debug)]
-pub struct BTConfig {
-    pub enabled: bool,
-    pub rfkill_path: Option<PathBuf>,
-}
 
-impl BTConfig {
-    pub fn new(enabled: bool, rfkill_path: Option<PathBuf>) -> Self {
-        let mut p = rfkill_path;
-        if enabled && p.is_none() {
-            p = Some(PathBuf::from(RFKILL_PATH_BT));
-        }
-        BTConfig {
-            enabled,
-            rfkill_path: p,
-        }
+    /// Unblock the entries that weren't blocked when [`RfConfig::block`] first ran;
+    /// those that were stay blocked
+    pub fn restore(&self, dry_run: bool) -> Vec<Restored> {
+        let before = std::mem::take(&mut *self.blocked_before.lock().unwrap());
+        let unblock: Vec<PathBuf> = before
+            .iter()
+            .filter(|(_, blocked)| !**blocked)
+            .map(|(p, _)| p.clone())
+            .collect();
+        self.write_all(&unblock, false, dry_run);
+        before
+            .iter()
+            .map(|(path, blocked)| {
+                let entry = path.file_name().unwrap_or_default().to_string_lossy();
+                Restored::new(
+                    format!("{} {}", self.kind.label(), entry),
+                    if *blocked { "blocked" } else { "unblocked" },
+                )
+            })
+            .collect()
     }
 
-    pub fn block(&self, dry_run: bool) {
-        if !self.enabled {
+    /// Pass what [`RfConfig::block`] recorded on to `next`, which keeps the radio
+    /// blocked after a profile switch, so leaving saving mode still restores it
+    pub fn hand_over(&self, next: &RfConfig) {
+        if Arc::ptr_eq(&self.blocked_before, &next.blocked_before) {
             return;
         }
-        if let Some(path) = &self.rfkill_path {
-            let state = path.join("state");
-            if dry_run {
-                debug!("DRY-RUN: would write '0' to {}", state.display());
-                return;
-            }
-            let _ = fs::write(&state, "0");
-            debug!("BT: blocked via {}", state.display());
-        } else {
-            warn!("BT toggling enabled but no rfkill path provided");
+        let before = std::mem::take(&mut *self.blocked_before.lock().unwrap());
+        let mut next_before = next.blocked_before.lock().unwrap();
+        for (path, blocked) in before {
+            next_before.entry(path).or_insert(blocked);
         }
     }
 
-    pub fn unblock(&self, dry_run: bool) {
-        if !self.enabled {
+    /// Flip the radio on demand (e.g. from a hotkey), regardless of `enabled`. With
+    /// several entries, any unblocked one means block them all.
+    pub fn toggle(&self, dry_run: bool) {
+        let devices = self.devices();
+        let Some(first) = devices.first() else {
+            warn!(
+                "{} toggle: no {} rfkill entry ({}) found",
+                self.kind.label(),
+                self.kind,
+                self.select
+            );
             return;
-        }
-        if let Some(path) = &self.rfkill_path {
-            let state = path.join("state");
-            if dry_run {
-                debug!("DRY-RUN: would write '1' to {}", state.display());
-                return;
+        };
+        if devices.len() == 1 && self.rfkill_dev.is_none() {
+            let marked = if dry_run {
+                None
+            } else {
+                mark_own_unblock(first)
+            };
+            match toggle_rfkill_state(first, dry_run) {
+                Ok(blocked) => info!(
+                    "{}: {} via {}",
+                    self.kind.label(),
+                    if blocked { "blocked" } else { "unblocked" },
+                    first.display()
+                ),
+                Err(e) => {
+                    unmark_own_unblock(marked);
+                    warn!(
+                        "{} toggle via {} failed: {}",
+                        self.kind.label(),
+                        first.display(),
+                        e
+                    )
+                }
             }
-            let _ = fs::write(&state, "1");
-            debug!("BT: unblocked via {}", state.display());
-        } else {
-            warn!("BT toggling enabled but no rfkill path provided");
+            return;
         }
+        let block = devices
+            .iter()
+            .any(|p| fs::read_to_string(rfkill_state_path(p)).is_ok_and(|s| s.trim() == "1"));
+        self.write_all(&devices, block, dry_run);
+        info!(
+            "{}: {} {} entries",
+            self.kind.label(),
+            if block { "blocked" } else { "unblocked" },
+            devices.len()
+        );
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::env;
+    use crate::test_util::TempDir;
     use std::fs;
 
+    /// rfkill0 bluetooth (hci0), rfkill1 wlan (phy0), rfkill2 wwan, rfkill3 wlan (phy1)
+    fn fake_rfkill() -> TempDir {
+        let root = TempDir::new("rfkill");
+        for (n, kind, name) in [
+            (0, "bluetooth", "hci0"),
+            (1, "wlan", "phy0"),
+            (2, "wwan", "wwan0"),
+            (3, "wlan", "phy1"),
+        ] {
+            let dir = root.join(format!("rfkill{}", n));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("type"), format!("{}\n", kind)).unwrap();
+            fs::write(dir.join("name"), format!("{}\n", name)).unwrap();
+            fs::write(dir.join("state"), "1\n").unwrap();
+        }
+        root
+    }
+
+    fn state(root: &Path, n: u32) -> String {
+        fs::read_to_string(root.join(format!("rfkill{}/state", n)))
+            .unwrap()
+            .trim()
+            .to_string()
+    }
+
     #[test]
     fn test_write_rfkill_state_dry_run() {
-        let tmp = env::temp_dir().join(format!(
-            "uconsole_wifi_{}",
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis()
-        ));
-        let _ = fs::create_dir_all(&tmp);
+        let tmp = TempDir::new("wifi");
         fs::write(tmp.join("state"), "0").unwrap();
         write_rfkill_state(&tmp, true, true);
         // dry run should not change
         let s = fs::read_to_string(tmp.join("state")).unwrap();
         assert_eq!(s, "0");
     }
+
+    #[test]
+    fn test_toggle_flips_state() {
+        let tmp = TempDir::new("rf_toggle");
+        fs::write(tmp.join("state"), "1\n").unwrap();
+        let wifi = RfConfig::new(RfType::Wlan, false, RfSelector::Path(tmp.to_path_buf()));
+        wifi.toggle(false);
+        assert_eq!(fs::read_to_string(tmp.join("state")).unwrap(), "0");
+        wifi.toggle(false);
+        assert_eq!(fs::read_to_string(tmp.join("state")).unwrap(), "1");
+        // dry-run leaves it alone
+        assert!(toggle_rfkill_state(&tmp, true).unwrap());
+        assert_eq!(fs::read_to_string(tmp.join("state")).unwrap(), "1");
+    }
+
+    #[test]
+    fn test_parse_selectors() {
+        assert_eq!("WIFI".parse::<RfType>().unwrap(), RfType::Wlan);
+        assert_eq!("bluetooth".parse::<RfType>().unwrap(), RfType::Bluetooth);
+        assert!("fm".parse::<RfType>().is_err());
+        assert_eq!("".parse::<RfSelector>().unwrap(), RfSelector::All);
+        assert_eq!(
+            "phy1".parse::<RfSelector>().unwrap(),
+            RfSelector::Name("phy1".to_string())
+        );
+        assert_eq!(
+            "/sys/class/rfkill/rfkill4".parse::<RfSelector>().unwrap(),
+            RfSelector::Path(PathBuf::from("/sys/class/rfkill/rfkill4"))
+        );
+        assert!("name:".parse::<RfSelector>().is_err());
+        assert!("index:3".parse::<RfSelector>().is_err());
+    }
+
+    #[test]
+    fn test_discovery_by_type_and_name() {
+        let root = fake_rfkill();
+        let kinds: Vec<String> = list_rfkill(&root).into_iter().map(|d| d.kind).collect();
+        assert_eq!(kinds, vec!["bluetooth", "wlan", "wwan", "wlan"]);
+
+        let wifi =
+            RfConfig::with_rfkill_dir(RfType::Wlan, true, RfSelector::All, root.to_path_buf());
+        assert_eq!(
+            wifi.devices(),
+            vec![root.join("rfkill1"), root.join("rfkill3")]
+        );
+        wifi.block(false);
+        assert_eq!(
+            (0..4).map(|n| state(&root, n)).collect::<Vec<_>>(),
+            vec!["1", "0", "1", "0"]
+        );
+        wifi.restore(false);
+
+        // only the dongle, by name
+        let dongle = RfConfig::with_rfkill_dir(
+            RfType::Wlan,
+            true,
+            RfSelector::Name("phy1".to_string()),
+            root.to_path_buf(),
+        );
+        dongle.block(false);
+        assert_eq!((state(&root, 1), state(&root, 3)), ("1".into(), "0".into()));
+        dongle.toggle(false);
+        assert_eq!(state(&root, 3), "1");
+
+        // not enabled: saving mode leaves it alone, toggle still works
+        let gps =
+            RfConfig::with_rfkill_dir(RfType::Gps, false, RfSelector::All, root.to_path_buf());
+        assert!(gps.devices().is_empty());
+        let wwan =
+            RfConfig::with_rfkill_dir(RfType::Wwan, false, RfSelector::All, root.to_path_buf());
+        wwan.block(false);
+        assert_eq!(state(&root, 2), "1");
+        wwan.toggle(false);
+        assert_eq!(state(&root, 2), "0");
+    }
+
+    #[test]
+    fn test_block_through_rfkill_dev() {
+        let root = fake_rfkill();
+        let dev = root.join("dev_rfkill");
+        fs::write(&dev, "").unwrap();
+        let dongle = RfConfig::with_rfkill_dir(
+            RfType::Wlan,
+            true,
+            RfSelector::Name("phy1".to_string()),
+            root.to_path_buf(),
+        )
+        .with_rfkill_dev(Some(dev.clone()));
+        assert!(dongle.covers(3));
+        assert!(!dongle.covers(1));
+        dongle.block(false);
+        // an RFKILL_OP_CHANGE for rfkill3 instead of a state write
+        let mut expected = 3u32.to_ne_bytes().to_vec();
+        expected.extend_from_slice(&[1, 2, 1, 0]);
+        assert_eq!(fs::read(&dev).unwrap(), expected);
+        assert_eq!(state(&root, 3), "1");
+
+        // the device can't be opened: state files instead
+        let wwan =
+            RfConfig::with_rfkill_dir(RfType::Wwan, true, RfSelector::All, root.to_path_buf())
+                .with_rfkill_dev(Some(root.join("missing")));
+        wwan.block(false);
+        assert_eq!(state(&root, 2), "0");
+    }
+
+    #[test]
+    fn test_own_unblocks_are_claimed_once() {
+        // an index no other test uses, as the record is process-wide
+        let root = fake_rfkill();
+        let entry = root.join("rfkill9041");
+        fs::create_dir_all(&entry).unwrap();
+        fs::write(entry.join("state"), "1\n").unwrap();
+        let wifi = RfConfig::new(RfType::Wlan, true, RfSelector::Path(entry.clone()));
+        wifi.block(false);
+        assert!(!take_own_unblock(9041));
+        wifi.restore(false);
+        assert_eq!(fs::read_to_string(entry.join("state")).unwrap(), "1");
+        assert!(take_own_unblock(9041));
+        assert!(!take_own_unblock(9041));
+
+        wifi.toggle(false);
+        wifi.toggle(false);
+        assert!(take_own_unblock(9041));
+
+        // turned back on by someone else first: no event will follow ours
+        wifi.block(false);
+        fs::write(entry.join("state"), "1\n").unwrap();
+        wifi.restore(false);
+        assert!(!take_own_unblock(9041));
+    }
 }
@@ -0,0 +1,341 @@
+//! `/dev/rfkill` event API: block radios with `RFKILL_OP_CHANGE` and follow the
+//! event stream for soft and hard block changes, including ones made by others
+//! (NetworkManager, `rfkill`, a hardware switch).
+//!
+//! The device speaks `struct rfkill_event` from `<linux/rfkill.h>`: a native-endian
+//! `u32` index followed by `u8` type, op, soft and hard. Newer kernels append more
+//! fields, which are ignored here.
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+use crate::error::Error;
+use crate::hardware::rf::RfType;
+
+pub const RFKILL_DEV_PATH: &str = "/dev/rfkill";
+/// Size of the original `rfkill_event`; the kernel accepts writes of this size
+pub const RFKILL_EVENT_SIZE: usize = 8;
+
+/// `RFKILL_TYPE_*` values
+const TYPE_ALL: u8 = 0;
+const TYPE_WLAN: u8 = 1;
+const TYPE_BLUETOOTH: u8 = 2;
+const TYPE_WWAN: u8 = 5;
+const TYPE_GPS: u8 = 6;
+const TYPE_NFC: u8 = 8;
+
+/// `RFKILL_OP_*`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RfkillOp {
+    Add,
+    Del,
+    Change,
+    ChangeAll,
+}
+
+impl RfkillOp {
+    fn from_raw(op: u8) -> Option<Self> {
+        match op {
+            0 => Some(RfkillOp::Add),
+            1 => Some(RfkillOp::Del),
+            2 => Some(RfkillOp::Change),
+            3 => Some(RfkillOp::ChangeAll),
+            _ => None,
+        }
+    }
+
+    fn raw(self) -> u8 {
+        match self {
+            RfkillOp::Add => 0,
+            RfkillOp::Del => 1,
+            RfkillOp::Change => 2,
+            RfkillOp::ChangeAll => 3,
+        }
+    }
+}
+
+fn type_raw(kind: RfType) -> u8 {
+    match kind {
+        RfType::Wlan => TYPE_WLAN,
+        RfType::Bluetooth => TYPE_BLUETOOTH,
+        RfType::Wwan => TYPE_WWAN,
+        RfType::Gps => TYPE_GPS,
+        RfType::Nfc => TYPE_NFC,
+    }
+}
+
+/// One `struct rfkill_event`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RfkillEvent {
+    pub idx: u32,
+    /// Raw `RFKILL_TYPE_*`; types we don't handle (UWB, FM, ...) are kept as-is
+    pub kind: u8,
+    pub op: RfkillOp,
+    pub soft: bool,
+    pub hard: bool,
+}
+
+impl RfkillEvent {
+    /// Request to soft block (or unblock) entry `idx`, which must be of type `kind`
+    pub fn change(idx: u32, kind: Option<RfType>, block: bool) -> Self {
+        RfkillEvent {
+            idx,
+            kind: kind.map_or(TYPE_ALL, type_raw),
+            op: RfkillOp::Change,
+            soft: block,
+            hard: false,
+        }
+    }
+
+    /// Decode an event as read from the device. Bytes past the original 8-byte
+    /// layout are ignored.
+    pub fn decode(buf: &[u8]) -> Result<Self, Error> {
+        if buf.len() < RFKILL_EVENT_SIZE {
+            return Err(Error::InvalidValue(format!(
+                "short rfkill event: {} bytes",
+                buf.len()
+            )));
+        }
+        let op = RfkillOp::from_raw(buf[5])
+            .ok_or_else(|| Error::InvalidValue(format!("unknown rfkill op {}", buf[5])))?;
+        Ok(RfkillEvent {
+            idx: u32::from_ne_bytes([buf[0], buf[1], buf[2], buf[3]]),
+            kind: buf[4],
+            op,
+            soft: buf[6] != 0,
+            hard: buf[7] != 0,
+        })
+    }
+
+    pub fn encode(&self) -> [u8; RFKILL_EVENT_SIZE] {
+        let idx = self.idx.to_ne_bytes();
+        [
+            idx[0],
+            idx[1],
+            idx[2],
+            idx[3],
+            self.kind,
+            self.op.raw(),
+            u8::from(self.soft),
+            u8::from(self.hard),
+        ]
+    }
+
+    /// The radio type, if it is one we handle
+    pub fn rf_type(&self) -> Option<RfType> {
+        RfType::ALL.into_iter().find(|k| type_raw(*k) == self.kind)
+    }
+}
+
+/// Block state of one rfkill entry
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RfkillState {
+    pub kind: u8,
+    pub soft: bool,
+    pub hard: bool,
+}
+
+/// A block state change learned from the event stream
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RfkillChange {
+    /// Soft block set (`true`) or cleared, by us or anyone else
+    Soft(bool),
+    /// Hardware switch engaged or released
+    Hard(bool),
+}
+
+/// Every entry's state, kept up to date from events
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RfkillStates {
+    pub entries: BTreeMap<u32, RfkillState>,
+}
+
+impl RfkillStates {
+    /// Apply `ev` and report what changed. Added entries report nothing; the
+    /// kernel sends an add event for each existing entry when the device is opened.
+    pub fn update(&mut self, ev: &RfkillEvent) -> Vec<RfkillChange> {
+        let state = RfkillState {
+            kind: ev.kind,
+            soft: ev.soft,
+            hard: ev.hard,
+        };
+        match ev.op {
+            RfkillOp::Add => {
+                self.entries.insert(ev.idx, state);
+                Vec::new()
+            }
+            RfkillOp::Del => {
+                self.entries.remove(&ev.idx);
+                Vec::new()
+            }
+            // only ever written to the device, never read from it
+            RfkillOp::ChangeAll => Vec::new(),
+            RfkillOp::Change => {
+                let mut changes = Vec::new();
+                let prev = self.entries.insert(ev.idx, state);
+                if prev.is_none_or(|p| p.soft != ev.soft) {
+                    changes.push(RfkillChange::Soft(ev.soft));
+                }
+                if prev.map_or(ev.hard, |p| p.hard != ev.hard) {
+                    changes.push(RfkillChange::Hard(ev.hard));
+                }
+                changes
+            }
+        }
+    }
+
+    pub fn get(&self, idx: u32) -> Option<&RfkillState> {
+        self.entries.get(&idx)
+    }
+}
+
+/// Soft block entry `idx` through the device at `dev`
+pub fn write_change(dev: &Path, idx: u32, kind: Option<RfType>, block: bool) -> Result<(), Error> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_CLOEXEC)
+        .open(dev)
+        .map_err(|e| Error::Io(format!("failed to open {}: {}", dev.display(), e)))?;
+    file.write_all(&RfkillEvent::change(idx, kind, block).encode())
+        .map_err(|e| Error::Io(format!("rfkill{} via {}: {}", idx, dev.display(), e)))
+}
+
+/// Open `/dev/rfkill` for reading the event stream
+pub struct RfkillMonitor {
+    file: File,
+    pub path: PathBuf,
+    pub states: RfkillStates,
+}
+
+impl RfkillMonitor {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let file = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+            .open(path)
+            .map_err(|e| Error::Io(format!("failed to open {}: {}", path.display(), e)))?;
+        Ok(RfkillMonitor {
+            file,
+            path: path.to_path_buf(),
+            states: RfkillStates::default(),
+        })
+    }
+
+    /// Read every pending event, update `states` and return the changes. The
+    /// kernel hands out one event per read.
+    pub fn read_changes(&mut self) -> Vec<(RfkillEvent, RfkillChange)> {
+        let mut changes = Vec::new();
+        let mut buf = [0u8; 64];
+        loop {
+            match self.file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => match RfkillEvent::decode(&buf[..n]) {
+                    Ok(ev) => changes.extend(self.states.update(&ev).into_iter().map(|c| (ev, c))),
+                    Err(e) => warn!("{}: {}", self.path.display(), e),
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    warn!("Failed to read {}: {}", self.path.display(), e);
+                    break;
+                }
+            }
+        }
+        changes
+    }
+}
+
+impl AsFd for RfkillMonitor {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(idx: u32, kind: u8, op: u8, soft: u8, hard: u8) -> Vec<u8> {
+        let mut buf = idx.to_ne_bytes().to_vec();
+        buf.extend_from_slice(&[kind, op, soft, hard]);
+        buf
+    }
+
+    #[test]
+    fn test_decode_fixtures() {
+        let ev = RfkillEvent::decode(&fixture(1, 1, 0, 0, 0)).unwrap();
+        assert_eq!(
+            ev,
+            RfkillEvent {
+                idx: 1,
+                kind: 1,
+                op: RfkillOp::Add,
+                soft: false,
+                hard: false,
+            }
+        );
+        assert_eq!(ev.rf_type(), Some(RfType::Wlan));
+        // extended event with hard_block_reasons
+        let mut ext = fixture(300, 2, 2, 1, 1);
+        ext.push(0x01);
+        let ev = RfkillEvent::decode(&ext).unwrap();
+        assert_eq!(
+            (ev.idx, ev.op, ev.soft, ev.hard),
+            (300, RfkillOp::Change, true, true)
+        );
+        assert_eq!(ev.rf_type(), Some(RfType::Bluetooth));
+        // FM radio: decoded, but not a type we handle
+        assert_eq!(
+            RfkillEvent::decode(&fixture(4, 7, 0, 0, 0))
+                .unwrap()
+                .rf_type(),
+            None
+        );
+        assert!(RfkillEvent::decode(&fixture(1, 1, 9, 0, 0)).is_err());
+        assert!(RfkillEvent::decode(&[1, 0, 0, 0, 1, 2]).is_err());
+    }
+
+    #[test]
+    fn test_encode_change() {
+        let ev = RfkillEvent::change(3, Some(RfType::Wwan), true);
+        assert_eq!(ev.encode().to_vec(), fixture(3, 5, 2, 1, 0));
+        assert_eq!(RfkillEvent::decode(&ev.encode()).unwrap(), ev);
+        assert_eq!(
+            RfkillEvent::change(0, None, false).encode().to_vec(),
+            fixture(0, 0, 2, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_states_follow_events() {
+        let mut states = RfkillStates::default();
+        let mut feed = |bytes: Vec<u8>| states.update(&RfkillEvent::decode(&bytes).unwrap());
+        // initial adds on open
+        assert!(feed(fixture(0, 2, 0, 0, 0)).is_empty());
+        assert!(feed(fixture(1, 1, 0, 1, 0)).is_empty());
+        // NetworkManager turns Wi-Fi back on
+        assert_eq!(
+            feed(fixture(1, 1, 2, 0, 0)),
+            vec![RfkillChange::Soft(false)]
+        );
+        // hardware switch
+        assert_eq!(feed(fixture(1, 1, 2, 0, 1)), vec![RfkillChange::Hard(true)]);
+        // repeated state: nothing new
+        assert!(feed(fixture(1, 1, 2, 0, 1)).is_empty());
+        assert!(feed(fixture(0, 2, 1, 0, 0)).is_empty());
+        assert_eq!(states.entries.len(), 1);
+        assert_eq!(
+            states.get(1),
+            Some(&RfkillState {
+                kind: 1,
+                soft: false,
+                hard: true
+            })
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! Waiting for input devices to (re)appear: inotify on `/dev/input` and its
+//! `by-path` directory, plus a retry backoff for nodes that exist but can't be
+//! opened yet (udev may still be fixing up permissions).
+
+use std::ffi::OsStr;
+use std::os::fd::{AsFd, BorrowedFd};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::debug;
+use nix::errno::Errno;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+
+use crate::error::Error;
+
+pub const INPUT_DIR: &str = "/dev/input";
+pub const BY_PATH_SUBDIR: &str = "by-path";
+
+/// First retry delay after a device went away or failed to open
+pub const MIN_RETRY: Duration = Duration::from_millis(250);
+/// Retries never wait longer than this
+pub const MAX_RETRY: Duration = Duration::from_secs(30);
+
+/// Exponential retry delay: doubles on every failure, starts over on success
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    pub min: Duration,
+    pub max: Duration,
+    next: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff::new(MIN_RETRY, MAX_RETRY)
+    }
+}
+
+impl Backoff {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Backoff {
+            min,
+            max,
+            next: min,
+        }
+    }
+
+    /// Delay before the next attempt; each call doubles the following one
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(self.max);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.next = self.min;
+    }
+}
+
+/// Reports when entries are created in (or change permissions in) the input
+/// directory or its `by-path` subdirectory. The subdirectory is watched as soon as
+/// it appears, since udev creates it only after the first device with a path.
+pub struct InputDirWatcher {
+    inotify: Inotify,
+    dir: PathBuf,
+    by_path: Option<WatchDescriptor>,
+}
+
+fn watch_flags() -> AddWatchFlags {
+    AddWatchFlags::IN_CREATE | AddWatchFlags::IN_ATTRIB | AddWatchFlags::IN_MOVED_TO
+}
+
+impl InputDirWatcher {
+    pub fn new() -> Result<Self, Error> {
+        Self::with_dir(Path::new(INPUT_DIR))
+    }
+
+    pub fn with_dir(dir: &Path) -> Result<Self, Error> {
+        let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)
+            .map_err(|e| Error::Io(format!("inotify_init failed: {}", e)))?;
+        inotify
+            .add_watch(dir, watch_flags())
+            .map_err(|e| Error::Io(format!("failed to watch {}: {}", dir.display(), e)))?;
+        let mut watcher = InputDirWatcher {
+            inotify,
+            dir: dir.to_path_buf(),
+            by_path: None,
+        };
+        watcher.watch_by_path();
+        Ok(watcher)
+    }
+
+    fn watch_by_path(&mut self) {
+        if self.by_path.is_some() {
+            return;
+        }
+        let sub = self.dir.join(BY_PATH_SUBDIR);
+        // not there yet: the IN_CREATE for it on the parent will bring us back
+        if let Ok(wd) = self.inotify.add_watch(&sub, watch_flags()) {
+            debug!("Watching {}", sub.display());
+            self.by_path = Some(wd);
+        }
+    }
+
+    /// Drain pending notifications; returns true if any entry appeared or changed
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        loop {
+            let events = match self.inotify.read_events() {
+                Ok(ev) => ev,
+                Err(Errno::EAGAIN) => break,
+                Err(e) => {
+                    debug!("inotify read failed: {}", e);
+                    break;
+                }
+            };
+            if events.is_empty() {
+                break;
+            }
+            for ev in events {
+                if ev.mask.contains(AddWatchFlags::IN_IGNORED) && Some(ev.wd) == self.by_path {
+                    // by-path was removed along with its last device
+                    self.by_path = None;
+                    continue;
+                }
+                if ev.name.as_deref() == Some(OsStr::new(BY_PATH_SUBDIR)) {
+                    self.watch_by_path();
+                }
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+impl AsFd for InputDirWatcher {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inotify.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::fs;
+
+    #[test]
+    fn test_backoff_doubles_and_caps() {
+        let mut b = Backoff::new(Duration::from_millis(100), Duration::from_millis(350));
+        assert_eq!(b.next_delay(), Duration::from_millis(100));
+        assert_eq!(b.next_delay(), Duration::from_millis(200));
+        assert_eq!(b.next_delay(), Duration::from_millis(350));
+        assert_eq!(b.next_delay(), Duration::from_millis(350));
+        b.reset();
+        assert_eq!(b.next_delay(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_watcher_sees_new_nodes_and_by_path() {
+        let tmp = TempDir::new("hotplug");
+        let mut w = InputDirWatcher::with_dir(&tmp).unwrap();
+        assert!(!w.changed());
+
+        fs::write(tmp.join("event3"), "").unwrap();
+        assert!(w.changed());
+        assert!(!w.changed());
+
+        // by-path shows up later and is then watched too
+        fs::create_dir(tmp.join(BY_PATH_SUBDIR)).unwrap();
+        assert!(w.changed());
+        fs::write(tmp.join(BY_PATH_SUBDIR).join("platform-pek-event"), "").unwrap();
+        assert!(w.changed());
+    }
+}
@@ -0,0 +1,190 @@
+//! Idle tracking: enter saving mode after a period without keyboard, trackball or
+//! pointer input, with separate timeouts on battery and on AC.
+
+use std::time::{Duration, Instant};
+
+use crate::input::{DeviceInfo, EV_ABS, EV_KEY, EV_REL};
+use crate::wake::{MotionAccumulator, REL_X, REL_Y};
+
+const KEY_A: u16 = 30;
+const KEY_SPACE: u16 = 57;
+const BTN_LEFT: u16 = 0x110;
+
+/// Whether a device is something the user types or points with. Buttons-only
+/// devices (power key, lid switch, jack detection) don't count.
+pub fn is_activity_source(info: &DeviceInfo) -> bool {
+    info.supports_event(EV_REL)
+        || info.supports_event(EV_ABS)
+        || [KEY_A, KEY_SPACE, BTN_LEFT]
+            .iter()
+            .any(|k| info.supports_key(*k))
+}
+
+/// Idle timeouts; `None` disables automatic saving on that power source
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IdleConfig {
+    pub battery_timeout: Option<Duration>,
+    pub ac_timeout: Option<Duration>,
+    /// Trackball motion per second below which it is treated as jitter (0 = any)
+    pub motion_threshold: u32,
+}
+
+impl IdleConfig {
+    pub fn enabled(&self) -> bool {
+        self.battery_timeout.is_some() || self.ac_timeout.is_some()
+    }
+
+    pub fn timeout(&self, on_ac: bool) -> Option<Duration> {
+        if on_ac {
+            self.ac_timeout
+        } else {
+            self.battery_timeout
+        }
+    }
+}
+
+/// Tracks the time of the last real input. Time is injected so tests don't sleep.
+#[derive(Clone, Debug)]
+pub struct IdleTracker {
+    pub config: IdleConfig,
+    motion: MotionAccumulator,
+    last_activity: Instant,
+    /// Fired for the current idle period; cleared by the next activity
+    fired: bool,
+}
+
+impl IdleTracker {
+    pub fn new(config: IdleConfig, now: Instant) -> Self {
+        let motion = MotionAccumulator::new(config.motion_threshold);
+        IdleTracker {
+            config,
+            motion,
+            last_activity: now,
+            fired: false,
+        }
+    }
+
+    /// Start a fresh idle period, e.g. after leaving saving mode
+    pub fn reset(&mut self, now: Instant) {
+        self.last_activity = now;
+        self.fired = false;
+        self.motion.reset();
+    }
+
+    /// Feed one input event; returns true if it counted as activity
+    pub fn on_event(&mut self, etype: u16, code: u16, value: i32, now: Instant) -> bool {
+        let active = match etype {
+            EV_KEY => value != 2,
+            EV_REL if code == REL_X || code == REL_Y => self.motion.add(value, now),
+            EV_REL | EV_ABS => true,
+            _ => false,
+        };
+        if active {
+            self.reset(now);
+        }
+        active
+    }
+
+    /// When the idle timeout for the given power source runs out
+    pub fn deadline(&self, on_ac: bool) -> Option<Instant> {
+        if self.fired {
+            return None;
+        }
+        self.config.timeout(on_ac).map(|t| self.last_activity + t)
+    }
+
+    /// Returns true once per idle period when the timeout has passed
+    pub fn poll(&mut self, now: Instant, on_ac: bool) -> bool {
+        match self.deadline(on_ac) {
+            Some(d) if now >= d => {
+                self.fired = true;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(n: u64) -> Duration {
+        Duration::from_secs(n)
+    }
+
+    fn config() -> IdleConfig {
+        IdleConfig {
+            battery_timeout: Some(secs(60)),
+            ac_timeout: Some(secs(300)),
+            motion_threshold: 5,
+        }
+    }
+
+    #[test]
+    fn test_activity_sources() {
+        let mut info = DeviceInfo::default();
+        assert!(!is_activity_source(&info));
+        // power key only
+        info.key_bits = vec![0u8; 96];
+        info.key_bits[116 / 8] |= 1 << (116 % 8);
+        assert!(!is_activity_source(&info));
+        info.key_bits[KEY_A as usize / 8] |= 1 << (KEY_A % 8);
+        assert!(is_activity_source(&info));
+        let trackball = DeviceInfo {
+            ev_bits: vec![1 << EV_REL],
+            ..Default::default()
+        };
+        assert!(is_activity_source(&trackball));
+    }
+
+    #[test]
+    fn test_timeouts_per_power_source() {
+        let t0 = Instant::now();
+        let mut idle = IdleTracker::new(config(), t0);
+        assert_eq!(idle.deadline(false), Some(t0 + secs(60)));
+        assert_eq!(idle.deadline(true), Some(t0 + secs(300)));
+        assert!(!idle.poll(t0 + secs(59), false));
+        assert!(!idle.poll(t0 + secs(61), true));
+        assert!(idle.poll(t0 + secs(61), false));
+        // fires once per idle period
+        assert!(!idle.poll(t0 + secs(120), false));
+        assert_eq!(idle.deadline(false), None);
+
+        // a key press starts a new period
+        assert!(idle.on_event(EV_KEY, KEY_A, 1, t0 + secs(200)));
+        assert_eq!(idle.deadline(false), Some(t0 + secs(260)));
+        // auto-repeat alone doesn't
+        assert!(!idle.on_event(EV_KEY, KEY_A, 2, t0 + secs(250)));
+    }
+
+    #[test]
+    fn test_trackball_jitter_ignored() {
+        let t0 = Instant::now();
+        let mut idle = IdleTracker::new(config(), t0);
+        assert!(!idle.on_event(EV_REL, REL_X, 1, t0 + secs(10)));
+        assert!(!idle.on_event(EV_REL, REL_Y, -1, t0 + secs(20)));
+        assert_eq!(idle.deadline(false), Some(t0 + secs(60)));
+        let t = t0 + secs(30);
+        assert!(!idle.on_event(EV_REL, REL_X, 4, t));
+        assert!(idle.on_event(EV_REL, REL_X, 4, t));
+        assert_eq!(idle.deadline(false), Some(t + secs(60)));
+    }
+
+    #[test]
+    fn test_disabled_source() {
+        let t0 = Instant::now();
+        let mut idle = IdleTracker::new(
+            IdleConfig {
+                battery_timeout: Some(secs(60)),
+                ..Default::default()
+            },
+            t0,
+        );
+        assert!(idle.config.enabled());
+        assert_eq!(idle.deadline(true), None);
+        assert!(!idle.poll(t0 + secs(1000), true));
+        // any motion counts without a threshold
+        assert!(idle.on_event(EV_REL, REL_X, 1, t0));
+    }
+}
@@ -0,0 +1,468 @@
+//! evdev input device helpers: opening and grabbing devices, reading raw events,
+//! tracking which keys are held and translating symbolic key names.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use log::{debug, warn};
+
+use crate::error::Error;
+use crate::evdev::{self, Decoder, READ_BATCH};
+
+// EVIOCGRAB ioctl to grab exclusive access to input device
+const EVIOCGRAB: u64 = 0x40044590;
+// EVIOCSCLOCKID ioctl to pick the clock used for event timestamps
+const EVIOCSCLOCKID: u64 = 0x400445a0;
+
+// Highest key code (KEY_MAX) and the size of a key bitmap covering it
+const KEY_MAX: usize = 0x2ff;
+const KEY_BITS_LEN: usize = KEY_MAX / 8 + 1;
+// Same for event types (EV_MAX)
+const EV_MAX: usize = 0x1f;
+const EV_BITS_LEN: usize = EV_MAX / 8 + 1;
+
+/// `_IOC(_IOC_READ, 'E', nr, len)`, the encoding of the variable-length evdev getters
+const fn eviocg(nr: u64, len: usize) -> u64 {
+    (2 << 30) | ((len as u64) << 16) | ((b'E' as u64) << 8) | nr
+}
+
+fn eviocgname(len: usize) -> u64 {
+    eviocg(0x06, len)
+}
+
+fn eviocgphys(len: usize) -> u64 {
+    eviocg(0x07, len)
+}
+
+fn eviocgbit(ev: u16, len: usize) -> u64 {
+    eviocg(0x20 + ev as u64, len)
+}
+
+fn eviocgkey(len: usize) -> u64 {
+    eviocg(0x18, len)
+}
+
+pub const EV_SYN: u16 = 0;
+pub const EV_KEY: u16 = 1;
+pub const EV_REL: u16 = 2;
+pub const EV_ABS: u16 = 3;
+
+/// Symbolic names for the key codes from `linux/input-event-codes.h` that are
+/// useful for bindings on the uConsole
+const KEY_NAMES: &[(&str, u16)] = &[
+    ("KEY_ESC", 1),
+    ("KEY_1", 2),
+    ("KEY_2", 3),
+    ("KEY_3", 4),
+    ("KEY_4", 5),
+    ("KEY_5", 6),
+    ("KEY_6", 7),
+    ("KEY_7", 8),
+    ("KEY_8", 9),
+    ("KEY_9", 10),
+    ("KEY_0", 11),
+    ("KEY_MINUS", 12),
+    ("KEY_EQUAL", 13),
+    ("KEY_BACKSPACE", 14),
+    ("KEY_TAB", 15),
+    ("KEY_Q", 16),
+    ("KEY_W", 17),
+    ("KEY_E", 18),
+    ("KEY_R", 19),
+    ("KEY_T", 20),
+    ("KEY_Y", 21),
+    ("KEY_U", 22),
+    ("KEY_I", 23),
+    ("KEY_O", 24),
+    ("KEY_P", 25),
+    ("KEY_LEFTBRACE", 26),
+    ("KEY_RIGHTBRACE", 27),
+    ("KEY_ENTER", 28),
+    ("KEY_LEFTCTRL", 29),
+    ("KEY_A", 30),
+    ("KEY_S", 31),
+    ("KEY_D", 32),
+    ("KEY_F", 33),
+    ("KEY_G", 34),
+    ("KEY_H", 35),
+    ("KEY_J", 36),
+    ("KEY_K", 37),
+    ("KEY_L", 38),
+    ("KEY_SEMICOLON", 39),
+    ("KEY_APOSTROPHE", 40),
+    ("KEY_GRAVE", 41),
+    ("KEY_LEFTSHIFT", 42),
+    ("KEY_BACKSLASH", 43),
+    ("KEY_Z", 44),
+    ("KEY_X", 45),
+    ("KEY_C", 46),
+    ("KEY_V", 47),
+    ("KEY_B", 48),
+    ("KEY_N", 49),
+    ("KEY_M", 50),
+    ("KEY_COMMA", 51),
+    ("KEY_DOT", 52),
+    ("KEY_SLASH", 53),
+    ("KEY_RIGHTSHIFT", 54),
+    ("KEY_LEFTALT", 56),
+    ("KEY_SPACE", 57),
+    ("KEY_CAPSLOCK", 58),
+    ("KEY_F1", 59),
+    ("KEY_F2", 60),
+    ("KEY_F3", 61),
+    ("KEY_F4", 62),
+    ("KEY_F5", 63),
+    ("KEY_F6", 64),
+    ("KEY_F7", 65),
+    ("KEY_F8", 66),
+    ("KEY_F9", 67),
+    ("KEY_F10", 68),
+    ("KEY_F11", 87),
+    ("KEY_F12", 88),
+    ("KEY_RIGHTCTRL", 97),
+    ("KEY_SYSRQ", 99),
+    ("KEY_RIGHTALT", 100),
+    ("KEY_HOME", 102),
+    ("KEY_UP", 103),
+    ("KEY_PAGEUP", 104),
+    ("KEY_LEFT", 105),
+    ("KEY_RIGHT", 106),
+    ("KEY_END", 107),
+    ("KEY_DOWN", 108),
+    ("KEY_PAGEDOWN", 109),
+    ("KEY_INSERT", 110),
+    ("KEY_DELETE", 111),
+    ("KEY_MUTE", 113),
+    ("KEY_VOLUMEDOWN", 114),
+    ("KEY_VOLUMEUP", 115),
+    ("KEY_POWER", 116),
+    ("KEY_PAUSE", 119),
+    ("KEY_LEFTMETA", 125),
+    ("KEY_RIGHTMETA", 126),
+    ("KEY_COMPOSE", 127),
+    ("KEY_MENU", 139),
+    ("KEY_SLEEP", 142),
+    ("KEY_WAKEUP", 143),
+    ("KEY_SCREENLOCK", 152),
+    ("KEY_SUSPEND", 205),
+    ("KEY_BRIGHTNESSDOWN", 224),
+    ("KEY_BRIGHTNESSUP", 225),
+    ("KEY_BATTERY", 236),
+    ("KEY_BLUETOOTH", 237),
+    ("KEY_WLAN", 238),
+    ("KEY_RFKILL", 247),
+    ("BTN_LEFT", 272),
+    ("BTN_RIGHT", 273),
+    ("BTN_MIDDLE", 274),
+    ("KEY_POWER2", 356),
+    ("KEY_FN", 464),
+];
+
+/// Resolve a symbolic key name (`KEY_POWER`, `power`, `BTN_LEFT`) or a decimal code
+pub fn keycode_from_name(name: &str) -> Option<u16> {
+    let name = name.trim();
+    if let Ok(code) = name.parse::<u16>() {
+        return Some(code);
+    }
+    let upper = name.to_ascii_uppercase();
+    KEY_NAMES
+        .iter()
+        .find(|(n, _)| *n == upper || n.strip_prefix("KEY_") == Some(upper.as_str()))
+        .map(|(_, c)| *c)
+}
+
+/// Symbolic name of a key code, if it is in the table
+pub fn key_name(code: u16) -> Option<&'static str> {
+    KEY_NAMES.iter().find(|(_, c)| *c == code).map(|(n, _)| *n)
+}
+
+/// A decoded `struct input_event` (see [`crate::evdev`] for the wire layouts)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InputEvent {
+    pub sec: i64,
+    pub usec: i64,
+    pub etype: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+/// Identification of an evdev node as reported by the kernel
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeviceInfo {
+    /// EVIOCGNAME, e.g. `axp20x-pek`
+    pub name: String,
+    /// EVIOCGPHYS, e.g. `gpio-keys/input0` (often empty for platform devices)
+    pub phys: String,
+    /// EVIOCGBIT(EV_KEY) bitmap of the key codes the device can report
+    pub key_bits: Vec<u8>,
+    /// EVIOCGBIT(0) bitmap of the event types the device can report
+    pub ev_bits: Vec<u8>,
+}
+
+impl DeviceInfo {
+    pub fn supports_key(&self, code: u16) -> bool {
+        let byte = code as usize / 8;
+        byte < self.key_bits.len() && self.key_bits[byte] & (1 << (code % 8)) != 0
+    }
+
+    pub fn supports_event(&self, etype: u16) -> bool {
+        let byte = etype as usize / 8;
+        byte < self.ev_bits.len() && self.ev_bits[byte] & (1 << (etype % 8)) != 0
+    }
+
+    /// Every key code set in `key_bits`
+    pub fn supported_keys(&self) -> Vec<u16> {
+        (0..(self.key_bits.len() * 8) as u16)
+            .filter(|c| self.supports_key(*c))
+            .collect()
+    }
+}
+
+/// An opened evdev node
+#[derive(Debug)]
+pub struct InputDevice {
+    pub path: PathBuf,
+    file: File,
+    decoder: Decoder,
+    /// Event timestamps are on CLOCK_MONOTONIC (EVIOCSCLOCKID succeeded)
+    monotonic: bool,
+}
+
+impl InputDevice {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path)
+            .map_err(|e| Error::Io(format!("failed to open {}: {}", path.display(), e)))?;
+        let clock: libc::c_int = libc::CLOCK_MONOTONIC;
+        let monotonic =
+            unsafe { libc::ioctl(file.as_raw_fd(), EVIOCSCLOCKID as _, &clock as *const _) } == 0;
+        if !monotonic {
+            debug!(
+                "EVIOCSCLOCKID on {} failed, using read time for events",
+                path.display()
+            );
+        }
+        Ok(InputDevice {
+            path: path.to_path_buf(),
+            file,
+            decoder: Decoder::default(),
+            monotonic,
+        })
+    }
+
+    /// Grab (or release) exclusive access so other readers stop seeing its events
+    pub fn grab(&self, on: bool) -> Result<(), Error> {
+        let ret = unsafe { libc::ioctl(self.file.as_raw_fd(), EVIOCGRAB as _, on as libc::c_int) };
+        if ret != 0 {
+            return Err(Error::Io(format!(
+                "EVIOCGRAB on {} failed: {}",
+                self.path.display(),
+                std::io::Error::last_os_error()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Run a variable-length getter ioctl into a buffer of `len` bytes
+    fn ioctl_read(&self, request: u64, len: usize) -> Result<Vec<u8>, Error> {
+        let mut buf = vec![0u8; len];
+        let ret = unsafe {
+            libc::ioctl(
+                self.file.as_raw_fd(),
+                request as _,
+                buf.as_mut_ptr() as *mut libc::c_void,
+            )
+        };
+        if ret < 0 {
+            return Err(Error::Io(format!(
+                "ioctl on {} failed: {}",
+                self.path.display(),
+                std::io::Error::last_os_error()
+            )));
+        }
+        buf.truncate(ret as usize);
+        Ok(buf)
+    }
+
+    fn ioctl_string(&self, request: u64) -> String {
+        self.ioctl_read(request, 256)
+            .map(|b| {
+                let end = b.iter().position(|c| *c == 0).unwrap_or(b.len());
+                String::from_utf8_lossy(&b[..end]).into_owned()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Query name, phys and supported keys; missing properties are left empty
+    pub fn info(&self) -> DeviceInfo {
+        DeviceInfo {
+            name: self.ioctl_string(eviocgname(256)),
+            phys: self.ioctl_string(eviocgphys(256)),
+            key_bits: self
+                .ioctl_read(eviocgbit(EV_KEY, KEY_BITS_LEN), KEY_BITS_LEN)
+                .unwrap_or_default(),
+            ev_bits: self
+                .ioctl_read(eviocgbit(0, EV_BITS_LEN), EV_BITS_LEN)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Read the queued events in one batch, blocking if none is queued. After the
+    /// kernel dropped events, synthetic key events bring the held keys back in
+    /// sync. A device that was unplugged reports `Error::NotFound`.
+    pub fn read_events(&mut self) -> Result<Vec<InputEvent>, Error> {
+        let mut buf = vec![0u8; READ_BATCH * self.decoder.layout.size()];
+        let n = self.file.read(&mut buf).map_err(|e| {
+            if e.raw_os_error() == Some(libc::ENODEV) {
+                Error::NotFound(format!("{} was removed", self.path.display()))
+            } else {
+                Error::from(e)
+            }
+        })?;
+        if n == 0 {
+            return Err(Error::NotFound(format!(
+                "{} was removed",
+                self.path.display()
+            )));
+        }
+        let mut events = self.decoder.feed(&buf[..n]);
+        if self.decoder.needs_resync() {
+            let bits = self.ioctl_read(eviocgkey(KEY_BITS_LEN), KEY_BITS_LEN)?;
+            let (sec, usec) = evdev::monotonic_now();
+            let fixed = self.decoder.resync(&bits, sec, usec);
+            warn!(
+                "{} dropped events, resynced key state ({} key changes)",
+                self.path.display(),
+                fixed.len().saturating_sub(1)
+            );
+            events.extend(fixed);
+        }
+        Ok(events)
+    }
+
+    /// When `event` happened, from its kernel timestamp if that is monotonic
+    pub fn event_time(&self, event: &InputEvent) -> Instant {
+        if self.monotonic {
+            evdev::monotonic_instant(event.sec, event.usec)
+        } else {
+            Instant::now()
+        }
+    }
+}
+
+impl AsFd for InputDevice {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+/// Keys currently held down, merged across every watched device. Each key is
+/// tracked per device slot, so a device going away only drops its own keys.
+#[derive(Clone, Debug, Default)]
+pub struct KeyState {
+    pressed: HashSet<(usize, u16)>,
+}
+
+impl KeyState {
+    /// Track key-down/key-up on device slot `device`; auto-repeat and non-key
+    /// events leave the state alone
+    pub fn update(&mut self, device: usize, etype: u16, code: u16, value: i32) {
+        if etype != EV_KEY {
+            return;
+        }
+        match value {
+            1 => {
+                self.pressed.insert((device, code));
+            }
+            0 => {
+                self.pressed.remove(&(device, code));
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether `code` is held on any device
+    pub fn is_pressed(&self, code: u16) -> bool {
+        self.pressed.iter().any(|(_, k)| *k == code)
+    }
+
+    /// Forget the keys held on `device`, e.g. after it was unplugged
+    pub fn clear_device(&mut self, device: usize) {
+        self.pressed.retain(|(d, _)| *d != device);
+    }
+
+    pub fn clear(&mut self) {
+        self.pressed.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keycode_names() {
+        assert_eq!(keycode_from_name("KEY_POWER"), Some(116));
+        assert_eq!(keycode_from_name("power"), Some(116));
+        assert_eq!(keycode_from_name(" key_f1 "), Some(59));
+        assert_eq!(keycode_from_name("BTN_LEFT"), Some(272));
+        assert_eq!(keycode_from_name("142"), Some(142));
+        assert_eq!(keycode_from_name("KEY_NOPE"), None);
+        assert_eq!(key_name(142), Some("KEY_SLEEP"));
+        assert_eq!(key_name(9999), None);
+    }
+
+    #[test]
+    fn test_ioctl_encoding_matches_kernel_headers() {
+        // values of EVIOCGNAME(256) / EVIOCGPHYS(256) / EVIOCGBIT(EV_KEY, 96) on Linux
+        assert_eq!(eviocgname(256), 0x81004506);
+        assert_eq!(eviocgphys(256), 0x81004507);
+        assert_eq!(eviocgbit(EV_KEY, KEY_BITS_LEN), 0x80604521);
+        assert_eq!(eviocgkey(KEY_BITS_LEN), 0x80604518);
+    }
+
+    #[test]
+    fn test_device_info_supports_key() {
+        let mut key_bits = vec![0u8; KEY_BITS_LEN];
+        key_bits[116 / 8] |= 1 << (116 % 8);
+        let info = DeviceInfo {
+            key_bits,
+            ..Default::default()
+        };
+        assert!(info.supports_key(116));
+        assert!(!info.supports_key(117));
+        assert!(!info.supports_key(0x3ff));
+        assert_eq!(info.supported_keys(), vec![116]);
+    }
+
+    #[test]
+    fn test_key_state_tracks_down_up() {
+        let mut ks = KeyState::default();
+        ks.update(0, EV_KEY, 29, 1);
+        ks.update(0, EV_KEY, 56, 1);
+        ks.update(0, EV_KEY, 56, 2);
+        ks.update(0, EV_SYN, 0, 0);
+        assert!(ks.is_pressed(29));
+        assert!(ks.is_pressed(56));
+        ks.update(0, EV_KEY, 29, 0);
+        assert!(!ks.is_pressed(29));
+        ks.clear();
+        assert!(!ks.is_pressed(56));
+
+        // a key held on two devices stays held until both let go
+        ks.update(0, EV_KEY, 464, 1);
+        ks.update(3, EV_KEY, 464, 1);
+        ks.update(3, EV_KEY, 59, 1);
+        ks.update(0, EV_KEY, 464, 0);
+        assert!(ks.is_pressed(464));
+        // unplugging one only drops what was held on it
+        ks.update(0, EV_KEY, 29, 1);
+        ks.clear_device(3);
+        assert!(!ks.is_pressed(464));
+        assert!(!ks.is_pressed(59));
+        assert!(ks.is_pressed(29));
+    }
+}
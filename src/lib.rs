@@ -1,16 +1,37 @@
 //! Console Sleep Service Library
 //! Pure Rust implementation with zero external dependencies
 
+pub mod action;
 pub mod args;
+pub mod chord;
 pub mod config;
+pub mod control;
+pub mod dim;
 pub mod error;
+pub mod evdev;
+pub mod feedback;
+pub mod gesture;
 pub mod hardware;
+pub mod hotplug;
+pub mod idle;
+pub mod input;
+pub mod low_battery;
 pub mod power_mode;
+pub mod profile;
+pub mod restore;
+pub mod suspend;
+#[cfg(test)]
+mod test_util;
+pub mod thermal;
+pub mod timer;
+pub mod uinput;
+pub mod wake;
 
+pub use action::Action;
 pub use config::Config;
 pub use error::Error;
-pub use hardware::cpu::CpuFreqConfig;
-pub use hardware::rf::{BTConfig, WifiConfig};
+pub use hardware::cpu::{CpuFreqConfig, CpuPolicies};
+pub use hardware::rf::{RfConfig, RfType};
 pub use hardware::*;
 pub use power_mode::PowerMode;
 pub use power_mode::{enter_saving_mode, exit_saving_mode};
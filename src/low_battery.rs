@@ -0,0 +1,250 @@
+//! Low battery handling: force saving mode below one charge level and power off
+//! cleanly below another, after a grace period in which plugging in the charger
+//! still saves the session.
+
+use std::time::{Duration, Instant};
+
+use crate::hardware::battery::BatteryStatus;
+
+pub const DEFAULT_CRITICAL_GRACE: Duration = Duration::from_secs(60);
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(60);
+/// Percent above the low threshold the charge has to climb back to, without the
+/// charger, before a later drop counts as low again
+const REARM_MARGIN: u8 = 5;
+
+/// Battery thresholds in percent; `None` disables that stage
+#[derive(Clone, Debug, PartialEq)]
+pub struct LowBatteryConfig {
+    pub low_percent: Option<u8>,
+    pub critical_percent: Option<u8>,
+    /// Time between the critical warning and the poweroff
+    pub critical_grace: Duration,
+    pub poll_interval: Duration,
+}
+
+impl Default for LowBatteryConfig {
+    fn default() -> Self {
+        LowBatteryConfig {
+            low_percent: None,
+            critical_percent: None,
+            critical_grace: DEFAULT_CRITICAL_GRACE,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+}
+
+impl LowBatteryConfig {
+    pub fn enabled(&self) -> bool {
+        self.low_percent.is_some() || self.critical_percent.is_some()
+    }
+}
+
+/// What the daemon should do after a battery reading
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BatteryEvent {
+    /// Dropped to the low threshold: enter saving mode
+    Low(u8),
+    /// Dropped to the critical threshold: warn, poweroff follows after the grace
+    Critical(u8),
+    /// Still critical after the grace period
+    Shutdown(u8),
+    /// Charger plugged in or charge back above critical during the grace period
+    Recovered,
+}
+
+/// Turns battery readings into [`BatteryEvent`]s, each firing once per discharge.
+/// Time is injected so tests don't sleep.
+#[derive(Clone, Debug)]
+pub struct LowBatteryMonitor {
+    pub config: LowBatteryConfig,
+    low_fired: bool,
+    critical_since: Option<Instant>,
+    shutdown_fired: bool,
+}
+
+impl LowBatteryMonitor {
+    pub fn new(config: LowBatteryConfig) -> Self {
+        LowBatteryMonitor {
+            config,
+            low_fired: false,
+            critical_since: None,
+            shutdown_fired: false,
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        status: &BatteryStatus,
+        on_ac: bool,
+        now: Instant,
+    ) -> Option<BatteryEvent> {
+        if on_ac || status.charging() {
+            self.low_fired = false;
+            self.shutdown_fired = false;
+            return self.critical_since.take().map(|_| BatteryEvent::Recovered);
+        }
+        // a failed read changes nothing, least of all a pending poweroff
+        let capacity = status.capacity?;
+
+        if let Some(critical) = self.config.critical_percent
+            && capacity <= critical
+        {
+            // critical implies low; don't announce that afterwards
+            self.low_fired = true;
+            return match self.critical_since {
+                None => {
+                    self.critical_since = Some(now);
+                    Some(BatteryEvent::Critical(capacity))
+                }
+                Some(since)
+                    if !self.shutdown_fired
+                        && now.saturating_duration_since(since) >= self.config.critical_grace =>
+                {
+                    self.shutdown_fired = true;
+                    Some(BatteryEvent::Shutdown(capacity))
+                }
+                Some(_) => None,
+            };
+        }
+        let recovered = self.critical_since.take().map(|_| BatteryEvent::Recovered);
+
+        match self.config.low_percent {
+            Some(low) if capacity <= low => {
+                if !self.low_fired {
+                    self.low_fired = true;
+                    return Some(BatteryEvent::Low(capacity));
+                }
+            }
+            Some(low) if capacity > low.saturating_add(REARM_MARGIN) => self.low_fired = false,
+            Some(_) => {}
+            None => self.low_fired = false,
+        }
+        recovered
+    }
+
+    /// When to read the battery next: sooner while a poweroff is pending
+    pub fn next_poll(&self, now: Instant) -> Instant {
+        let poll = now + self.config.poll_interval;
+        match self.critical_since {
+            Some(since) if !self.shutdown_fired => poll.min(since + self.config.critical_grace),
+            _ => poll,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::battery::ChargeStatus;
+
+    fn discharging(capacity: u8) -> BatteryStatus {
+        BatteryStatus {
+            capacity: Some(capacity),
+            status: ChargeStatus::Discharging,
+            ..Default::default()
+        }
+    }
+
+    fn monitor() -> LowBatteryMonitor {
+        LowBatteryMonitor::new(LowBatteryConfig {
+            low_percent: Some(15),
+            critical_percent: Some(5),
+            critical_grace: Duration::from_secs(30),
+            poll_interval: Duration::from_secs(60),
+        })
+    }
+
+    #[test]
+    fn test_low_fires_once_per_discharge() {
+        let mut m = monitor();
+        let t0 = Instant::now();
+        assert_eq!(m.update(&discharging(40), false, t0), None);
+        assert_eq!(
+            m.update(&discharging(15), false, t0),
+            Some(BatteryEvent::Low(15))
+        );
+        assert_eq!(m.update(&discharging(14), false, t0), None);
+
+        // wobbling around the threshold doesn't
+        assert_eq!(m.update(&discharging(16), false, t0), None);
+        assert_eq!(m.update(&discharging(15), false, t0), None);
+        assert_eq!(m.update(&discharging(20), false, t0), None);
+        assert_eq!(m.update(&discharging(15), false, t0), None);
+        // climbing clear of it does
+        assert_eq!(m.update(&discharging(21), false, t0), None);
+        assert_eq!(
+            m.update(&discharging(15), false, t0),
+            Some(BatteryEvent::Low(15))
+        );
+
+        // so does charging
+        assert_eq!(m.update(&discharging(14), true, t0), None);
+        assert_eq!(
+            m.update(&discharging(13), false, t0),
+            Some(BatteryEvent::Low(13))
+        );
+    }
+
+    #[test]
+    fn test_critical_grace_then_shutdown() {
+        let mut m = monitor();
+        let t0 = Instant::now();
+        assert_eq!(
+            m.update(&discharging(5), false, t0),
+            Some(BatteryEvent::Critical(5))
+        );
+        assert_eq!(m.next_poll(t0), t0 + Duration::from_secs(30));
+        let t1 = t0 + Duration::from_secs(10);
+        assert_eq!(m.update(&discharging(4), false, t1), None);
+        let t2 = t0 + Duration::from_secs(30);
+        assert_eq!(
+            m.update(&discharging(4), false, t2),
+            Some(BatteryEvent::Shutdown(4))
+        );
+        assert_eq!(m.update(&discharging(3), false, t2), None);
+        assert_eq!(m.next_poll(t2), t2 + Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_charger_during_grace_cancels_shutdown() {
+        let mut m = monitor();
+        let t0 = Instant::now();
+        m.update(&discharging(3), false, t0);
+        let charging = BatteryStatus {
+            status: ChargeStatus::Charging,
+            ..discharging(3)
+        };
+        let t1 = t0 + Duration::from_secs(5);
+        assert_eq!(
+            m.update(&charging, false, t1),
+            Some(BatteryEvent::Recovered)
+        );
+        let t2 = t0 + Duration::from_secs(60);
+        assert_eq!(m.update(&charging, false, t2), None);
+        // unplugged again: a fresh grace period
+        assert_eq!(
+            m.update(&discharging(3), false, t2),
+            Some(BatteryEvent::Critical(3))
+        );
+    }
+
+    #[test]
+    fn test_unknown_capacity_is_ignored() {
+        let mut m = monitor();
+        let status = BatteryStatus {
+            status: ChargeStatus::Discharging,
+            ..Default::default()
+        };
+        let t0 = Instant::now();
+        assert_eq!(m.update(&status, false, t0), None);
+
+        // nor does it cancel a pending poweroff
+        m.update(&discharging(3), false, t0);
+        let t1 = t0 + Duration::from_secs(30);
+        assert_eq!(m.update(&status, false, t1), None);
+        assert_eq!(
+            m.update(&discharging(3), false, t1),
+            Some(BatteryEvent::Shutdown(3))
+        );
+    }
+}
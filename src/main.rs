@@ -3,104 +3,702 @@
 //! Power-saving mode: display off, WiFi off(optional), reduced CPU frequency
 
 use nix::sys::epoll::EpollTimeout;
+use std::collections::BTreeMap;
 use std::env;
-use std::fs::File;
-use std::io::Read;
-use std::os::fd::AsRawFd;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread::{sleep, spawn};
 use std::time::{Duration, Instant};
 
 use nix::sys::epoll::{Epoll, EpollCreateFlags, EpollEvent, EpollFlags};
+use nix::sys::eventfd::{EfdFlags, EventFd};
 
-use log::{Level, LevelFilter, debug, error, info, warn};
+use log::{Level, LevelFilter, debug, error, info, log, warn};
 use uconsole_sleep::hardware::power_key;
 
-use uconsole_sleep::BTConfig;
+use uconsole_sleep::Action;
 use uconsole_sleep::CpuFreqConfig;
-use uconsole_sleep::WifiConfig;
+use uconsole_sleep::Error;
 use uconsole_sleep::args::parse_cli_args;
 use uconsole_sleep::config::Config;
-use uconsole_sleep::power_mode::{PowerMode, enter_saving_mode, exit_saving_mode};
-
-// EVIOCGRAB ioctl to grab exclusive access to input device
-const EVIOCGRAB: u64 = 0x40044590;
-
-// input_event classification constants
-const EV_KEY: u16 = 1;
-const KEY_POWER: u16 = 116;
-
-/// Outcome of classifying a single input_event for the power key.
-#[derive(Clone, Copy, PartialEq, Debug)]
-pub enum KeyDecision {
-    /// Power key was released after a press shorter than the hold threshold.
-    ShortPress,
-    /// Power key was released after a press at/longer than the hold threshold.
-    LongPress,
-    /// Event is not actionable yet (key-down, auto-repeat, non-power key, orphan key-up).
-    Continue,
+use uconsole_sleep::control::ControlFifo;
+use uconsole_sleep::dim::Dimmer;
+use uconsole_sleep::feedback::Feedback;
+use uconsole_sleep::gesture::{
+    Bindings, DEFAULT_TAP_WINDOW, Gesture, GestureRecognizer, KEY_POWER,
+};
+use uconsole_sleep::hardware::backlight;
+use uconsole_sleep::hardware::battery;
+use uconsole_sleep::hardware::cpu::{CpuPolicies, FreqCap};
+use uconsole_sleep::hardware::cpu_hotplug::CpuHotplug;
+use uconsole_sleep::hardware::devfreq::DevfreqConfig;
+use uconsole_sleep::hardware::internal_kb;
+use uconsole_sleep::hardware::power_key::PowerKeySelector;
+use uconsole_sleep::hardware::rf::{self, RfConfig, RfType};
+use uconsole_sleep::hardware::rfkill::{RfkillChange, RfkillMonitor};
+use uconsole_sleep::hotplug::{Backoff, InputDirWatcher};
+use uconsole_sleep::idle::{IdleTracker, is_activity_source};
+use uconsole_sleep::input::{EV_KEY, EV_SYN, InputDevice, KeyState};
+use uconsole_sleep::low_battery::{BatteryEvent, LowBatteryMonitor};
+use uconsole_sleep::power_mode::{DisplayPaths, DisplayState, PowerMode, SavingConfig, display_on};
+use uconsole_sleep::profile::{
+    BUILTIN_PROFILE, DisplayAction, NORMAL_PROFILE, ProfileSpec, Profiles, RfAction,
+};
+use uconsole_sleep::suspend::Suspender;
+use uconsole_sleep::thermal::ThermalMonitor;
+use uconsole_sleep::timer::DeadlineTimer;
+use uconsole_sleep::uinput::{VIRTUAL_DEVICE_NAME, VirtualDevice};
+use uconsole_sleep::wake::WakeDetector;
+
+// epoll tokens identifying the fd that became ready; input devices use
+// TOKEN_DEVICE_BASE + index into the device list
+const TOKEN_TIMER: u64 = 0;
+const TOKEN_MODE: u64 = 1;
+const TOKEN_HOTPLUG: u64 = 2;
+const TOKEN_RECONNECT: u64 = 3;
+const TOKEN_IDLE: u64 = 4;
+const TOKEN_DIM: u64 = 5;
+const TOKEN_BATTERY: u64 = 6;
+const TOKEN_CHARGER: u64 = 7;
+const TOKEN_CONTROL: u64 = 8;
+const TOKEN_SUSPEND: u64 = 9;
+const TOKEN_THERMAL: u64 = 10;
+const TOKEN_RFKILL: u64 = 11;
+const TOKEN_DEVICE_BASE: u64 = 16;
+
+/// How often the charger is checked when AC and battery saving settings differ
+const CHARGER_POLL: Duration = Duration::from_secs(5);
+
+/// Power key events this soon after resuming are the wake-up press, not a gesture
+const RESUME_SETTLE: Duration = Duration::from_secs(1);
+
+// Use PowerMode and enter/exit functions from the library `power_mode` module.
+
+/// Everything a background thread needs to switch power modes.
+#[derive(Clone)]
+struct ModeContext {
+    power_mode: Arc<Mutex<PowerMode>>,
+    /// Lock-free copy of `power_mode` for the event loop, which must not wait for a
+    /// switch to finish (a fade-out takes a while). Set as soon as entering starts.
+    saving_now: Arc<AtomicBool>,
+    /// Settings of the active saving mode, or the one a plain toggle enters next
+    saving: Arc<Mutex<SavingConfig>>,
+    /// Copy of `saving.rf`, which the event loop reads without waiting for a
+    /// switch in progress to let go of `saving`
+    saving_rf: Arc<Mutex<Vec<RfConfig>>>,
+    /// The built-in `saving` profile for the current power source; swapped when
+    /// the charger comes or goes
+    builtin: Arc<Mutex<SavingConfig>>,
+    /// Named profiles, set up at startup
+    profile_configs: Arc<BTreeMap<String, SavingConfig>>,
+    profiles: Arc<Profiles>,
+    dry_run: bool,
+    /// Signalled after every completed mode switch so the event loop can react
+    mode_changed: Arc<EventFd>,
+    /// uinput device for `key:` actions and pass-through, if one is needed
+    virtual_keys: Option<Arc<VirtualDevice>>,
+    /// Backlight fades around the display being switched off and on
+    dimmer: Arc<Dimmer>,
+    /// Signalled by the `sleep` action; the event loop suspends, as it owns the
+    /// input devices that need re-grabbing afterwards
+    suspend_request: Arc<EventFd>,
+}
+
+impl ModeContext {
+    /// Current mode without waiting for a switch in progress
+    fn mode(&self) -> PowerMode {
+        if self.saving_now.load(Ordering::Relaxed) {
+            PowerMode::Saving
+        } else {
+            PowerMode::Normal
+        }
+    }
+
+    /// Make `next` the saving settings, keeping `saving_rf` in step
+    fn set_saving(&self, saving: &mut SavingConfig, next: SavingConfig) {
+        *self.saving_rf.lock().unwrap() = next.rf.clone();
+        *saving = next;
+    }
+
+    /// Radio settings of the saving mode, without waiting for a switch in progress
+    fn rf(&self, kind: RfType) -> Option<RfConfig> {
+        let rf = self.saving_rf.lock().unwrap();
+        rf.iter().find(|r| r.kind == kind).cloned()
+    }
+
+    /// Saving settings of profile `name`; `None` for `normal` and unknown names
+    fn profile(&self, name: &str) -> Option<SavingConfig> {
+        if name == BUILTIN_PROFILE {
+            return Some(self.builtin.lock().unwrap().clone());
+        }
+        self.profile_configs.get(name).cloned()
+    }
+
+    /// Profile entered by `toggle`, `enter_saving` and the idle timeout
+    fn default_profile(&self) -> SavingConfig {
+        self.profiles
+            .default
+            .as_deref()
+            .and_then(|n| self.profile(n))
+            .unwrap_or_else(|| self.builtin.lock().unwrap().clone())
+    }
+}
+
+/// Why an input device is being watched
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DeviceRole {
+    PowerKey,
+    /// From `INPUT_DEVICES`, watched for chords
+    Extra,
+    /// Internal keyboard/trackball, watched for chords and/or wake-up
+    InternalKb,
+    /// Any other keyboard or pointer, watched only to reset the idle timer
+    Activity,
 }
 
-/// Result of classifying an event: the decision plus the timestamp state to carry forward.
-#[derive(Clone, Copy, PartialEq, Debug)]
-pub struct KeyEventResult {
-    pub decision: KeyDecision,
-    /// New value for `last_key_down_timestamp` after this event.
-    pub last_key_down: Option<Instant>,
+/// Record the display state, then fade the backlight to black ahead of switching
+/// the display off. The recorded brightness is the one from before any dim stage;
+/// after a fade the display comes back on dark and the dimmer fades it in.
+fn fade_out(ctx: &ModeContext) -> DisplayState {
+    let mut display = DisplayState::capture(&DisplayPaths::find());
+    if let Some(b) = ctx.dimmer.original() {
+        display.brightness = Some(b);
+    }
+    ctx.dimmer.fade_out();
+    display.faded_out = ctx.dimmer.faded_out();
+    display
 }
 
-/// Pure classification of a raw input_event into a power-key decision.
-///
-/// `now` is passed in (rather than read from the clock) so the logic is deterministic and
-/// unit-testable. This mirrors the inline logic that previously lived in the event loop:
-/// a key-down (value 1) records the press time; a key-up (value 0) resolves to a short or
-/// long press based on the elapsed time since the last key-down.
-fn classify_key_event(
-    last_key_down: Option<Instant>,
-    etype: u16,
-    code: u16,
-    value: i32,
-    hold_trigger: Duration,
-    now: Instant,
-) -> KeyEventResult {
-    // Ignore everything that isn't the power key.
-    if etype != EV_KEY || code != KEY_POWER {
-        return KeyEventResult {
-            decision: KeyDecision::Continue,
-            last_key_down,
+/// Move from the current state to `next` (`None` is normal mode), with `mode` held
+/// locked by the caller. Returns false if nothing changed.
+fn apply_profile(ctx: &ModeContext, mode: &mut PowerMode, next: Option<SavingConfig>) -> bool {
+    let mut saving = ctx.saving.lock().unwrap();
+    match (&*mode, next) {
+        (PowerMode::Normal, None) => {
+            debug!("Already in Normal mode");
+            return false;
+        }
+        (PowerMode::Normal, Some(next)) => {
+            ctx.saving_now.store(true, Ordering::Relaxed);
+            let display = (next.display == DisplayAction::Off).then(|| fade_out(ctx));
+            next.enter(display, ctx.dry_run);
+            if next.display == DisplayAction::Dim {
+                ctx.dimmer.dim();
+            }
+            ctx.set_saving(&mut saving, next);
+            *mode = PowerMode::Saving;
+        }
+        (PowerMode::Saving, None) => {
+            saving.exit(ctx.dry_run);
+            ctx.dimmer.restore();
+            *mode = PowerMode::Normal;
+            ctx.saving_now.store(false, Ordering::Relaxed);
+        }
+        (PowerMode::Saving, Some(next)) => {
+            if next.name == saving.name {
+                debug!("Already in {} mode", next.name);
+                return false;
+            }
+            let display = (next.display == DisplayAction::Off
+                && saving.display != DisplayAction::Off)
+                .then(|| fade_out(ctx));
+            saving.switch_to(&next, display, ctx.dry_run);
+            match next.display {
+                DisplayAction::Dim => ctx.dimmer.dim(),
+                DisplayAction::On => ctx.dimmer.restore(),
+                DisplayAction::Off => {}
+            }
+            ctx.set_saving(&mut saving, next);
+        }
+    }
+    true
+}
+
+fn signal_mode_change(ctx: &ModeContext) {
+    if let Err(e) = ctx.mode_changed.write(1) {
+        warn!("Failed to signal mode change: {}", e);
+    }
+}
+
+/// Switch power mode on a background thread; a `target` of `None` toggles.
+/// Saving mode means the default profile.
+fn switch_power_mode(ctx: &ModeContext, target: Option<PowerMode>) {
+    let ctx = ctx.clone();
+    spawn(move || {
+        let mut mode = ctx.power_mode.lock().unwrap();
+        let target = target.unwrap_or(match *mode {
+            PowerMode::Normal => PowerMode::Saving,
+            PowerMode::Saving => PowerMode::Normal,
+        });
+        if *mode == target {
+            debug!("Already in {:?} mode", target);
+            return;
+        }
+        let next = (target == PowerMode::Saving).then(|| ctx.default_profile());
+        if apply_profile(&ctx, &mut mode, next) {
+            signal_mode_change(&ctx);
+        }
+    });
+}
+
+/// Switch to profile `name` on a background thread, or to the next one in the
+/// cycle if `name` is `None`
+fn switch_profile(ctx: &ModeContext, name: Option<String>) {
+    let ctx = ctx.clone();
+    spawn(move || {
+        let mut mode = ctx.power_mode.lock().unwrap();
+        let name = name.unwrap_or_else(|| {
+            let current = match *mode {
+                PowerMode::Normal => NORMAL_PROFILE.to_string(),
+                PowerMode::Saving => ctx.saving.lock().unwrap().name.clone(),
+            };
+            ctx.profiles.next_in_cycle(&current)
+        });
+        let next = if name == NORMAL_PROFILE {
+            None
+        } else {
+            match ctx.profile(&name) {
+                Some(p) => Some(p),
+                None => {
+                    warn!("Unknown power profile '{}'", name);
+                    return;
+                }
+            }
         };
+        info!("Switching to power profile '{}'", name);
+        if apply_profile(&ctx, &mut mode, next) {
+            signal_mode_change(&ctx);
+        }
+    });
+}
+
+/// Use `next` for the built-in saving profile from now on, on a background thread;
+/// if it is the active one it is moved over to the new settings right away.
+fn switch_saving_config(ctx: &ModeContext, next: SavingConfig) {
+    let ctx = ctx.clone();
+    spawn(move || {
+        let mode = ctx.power_mode.lock().unwrap();
+        *ctx.builtin.lock().unwrap() = next.clone();
+        let mut saving = ctx.saving.lock().unwrap();
+        if saving.name != BUILTIN_PROFILE {
+            return;
+        }
+        if *mode == PowerMode::Saving {
+            saving.switch_to(&next, None, ctx.dry_run);
+        }
+        ctx.set_saving(&mut saving, next);
+    });
+}
+
+/// Look up and run the action bound to `gesture` in the current power mode. A bound
+/// hold gets a feedback pulse first so the user knows the key can be released.
+fn dispatch_gesture(
+    gesture: Gesture,
+    bindings: &Bindings,
+    hold_feedback: &Feedback,
+    ctx: &ModeContext,
+) {
+    let mode = ctx.mode();
+    match bindings.lookup(&mode, gesture) {
+        Some(Action::None) | None => {
+            info!("Power key {} in {:?} mode (no action bound)", gesture, mode)
+        }
+        Some(action) => {
+            info!(
+                "Power key {} in {:?} mode: running '{}'",
+                gesture, mode, action
+            );
+            if gesture == Gesture::Hold {
+                // the action runs once the pulse has put the backlight back, so a
+                // mode switch neither gets undone by it nor records its flipped state
+                let (action, ctx) = (action.clone(), ctx.clone());
+                hold_feedback.pulse_then(ctx.dry_run, move || run_action(&action, &ctx));
+            } else {
+                run_action(action, ctx);
+            }
+        }
     }
-    match value {
-        // key-down: remember when the press started
-        1 => KeyEventResult {
-            decision: KeyDecision::Continue,
-            last_key_down: Some(now),
+}
+
+/// Carry out a bound action. System actions run on a background thread so a slow
+/// command never stalls the event loop.
+fn run_action(action: &Action, ctx: &ModeContext) {
+    match action {
+        Action::None => {}
+        Action::ToggleSaving => switch_power_mode(ctx, None),
+        Action::EnterSaving => switch_power_mode(ctx, Some(PowerMode::Saving)),
+        Action::ExitSaving => switch_power_mode(ctx, Some(PowerMode::Normal)),
+        Action::Profile(name) => switch_profile(ctx, Some(name.clone())),
+        Action::CycleProfile => switch_profile(ctx, None),
+        Action::Sleep => {
+            if let Err(e) = ctx.suspend_request.write(1) {
+                warn!("Failed to request suspend: {}", e);
+            }
+        }
+        Action::ToggleWifi | Action::ToggleBt => {
+            let kind = if *action == Action::ToggleWifi {
+                RfType::Wlan
+            } else {
+                RfType::Bluetooth
+            };
+            // off the event loop, as a mode switch may hold `saving` for a while
+            let ctx = ctx.clone();
+            spawn(move || {
+                if let Some(rf) = ctx.saving.lock().unwrap().rf(kind) {
+                    rf.toggle(ctx.dry_run);
+                }
+            });
+        }
+        Action::EmitKey(code) => match &ctx.virtual_keys {
+            Some(_) if ctx.dry_run => debug!("DRY-RUN: would emit {}", action),
+            Some(v) => {
+                if let Err(e) = v.tap_key(*code) {
+                    error!("Failed to emit {}: {}", action, e);
+                }
+            }
+            None => warn!("No virtual input device, cannot emit {}", action),
         },
-        // key-up: resolve short vs. long press and clear the press time
-        0 => {
-            let decision = match last_key_down {
-                Some(down_ts) if now.duration_since(down_ts) < hold_trigger => {
-                    KeyDecision::ShortPress
+        other => {
+            let action = other.clone();
+            let dry_run = ctx.dry_run;
+            spawn(move || {
+                if let Err(e) = action.execute(dry_run) {
+                    error!("Action '{}' failed: {}", action, e);
                 }
-                Some(_) => KeyDecision::LongPress,
-                // No preceding key-down (e.g. missed event): don't trigger a toggle.
-                None => KeyDecision::Continue,
-            };
-            KeyEventResult {
-                decision,
-                last_key_down: None,
+            });
+        }
+    }
+}
+
+/// Saving mode settings from `cfg`; the CLI `--toggle-wifi`/`--toggle-bt` flags
+/// win over the config on both power sources.
+fn saving_config(
+    cfg: &Config,
+    toggle_wifi_flag: Option<bool>,
+    toggle_bt_flag: Option<bool>,
+    freq_cap: &FreqCap,
+) -> SavingConfig {
+    let cpu = match cfg.policy_path.clone() {
+        Some(path) => CpuPolicies::single(CpuFreqConfig::with_policy_path(
+            path,
+            cfg.saving_cpu_freq.clone(),
+        )),
+        None => CpuPolicies::new(cfg.saving_cpu_freq.clone(), &cfg.saving_cpu_freq_policies),
+    }
+    .with_saving_governor(cfg.saving_cpu_governor.clone())
+    .with_governor_tunables(cfg.saving_governor_tunables.clone())
+    .with_hotplug(cfg.saving_cpu_offline.clone().map(CpuHotplug::new))
+    .with_freq_cap(freq_cap.clone());
+    SavingConfig {
+        name: BUILTIN_PROFILE.to_string(),
+        cpu,
+        devfreq: DevfreqConfig::new(
+            cfg.saving_devfreq_max,
+            cfg.saving_devfreq_governor.clone(),
+            &cfg.devfreq_devices,
+        ),
+        rf: RfType::ALL
+            .iter()
+            .map(|&kind| {
+                let setting = cfg.rf_setting(kind);
+                let flag = match kind {
+                    RfType::Wlan => toggle_wifi_flag,
+                    RfType::Bluetooth => toggle_bt_flag,
+                    _ => None,
+                };
+                RfConfig::new(kind, flag.unwrap_or(setting.toggle), setting.select)
+                    .with_rfkill_dev(cfg.rfkill_dev.clone())
+            })
+            .collect(),
+        display: DisplayAction::Off,
+    }
+}
+
+/// Saving settings of a named profile; CPU policy, devfreq devices and rfkill
+/// selection come from `cfg`. The profile's CPU range applies to every policy
+/// without a range of its own.
+fn profile_config(
+    cfg: &Config,
+    name: &str,
+    spec: &ProfileSpec,
+    freq_cap: &FreqCap,
+) -> SavingConfig {
+    let cpu = match cfg.policy_path.clone() {
+        Some(path) => {
+            CpuPolicies::single(CpuFreqConfig::with_policy_path(path, spec.cpu_freq.clone()))
+        }
+        None => CpuPolicies::new(spec.cpu_freq.clone(), &spec.cpu_freq_policies),
+    }
+    .with_saving_governor(spec.governor.clone())
+    .with_governor_tunables(spec.governor_tunables.clone())
+    .with_hotplug(spec.cpu_offline.clone().map(CpuHotplug::new))
+    .with_freq_cap(freq_cap.clone());
+    SavingConfig {
+        name: name.to_string(),
+        cpu,
+        devfreq: DevfreqConfig::new(
+            spec.devfreq_max,
+            spec.devfreq_governor.clone(),
+            &cfg.devfreq_devices,
+        ),
+        rf: RfType::ALL
+            .iter()
+            .map(|&kind| {
+                RfConfig::new(
+                    kind,
+                    spec.rf(kind) == RfAction::Block,
+                    cfg.rf_setting(kind).select,
+                )
+                .with_rfkill_dev(cfg.rfkill_dev.clone())
+            })
+            .collect(),
+        display: spec.display,
+    }
+}
+
+/// Input devices to watch besides the power key: `INPUT_DEVICES` plus, if chords or
+/// wake-up need them, the evdev nodes of the internal keyboard/trackball.
+fn extra_input_devices(cfg: &Config) -> Vec<(PathBuf, DeviceRole)> {
+    let mut paths: Vec<(PathBuf, DeviceRole)> = cfg
+        .input_devices
+        .iter()
+        .map(|p| (p.clone(), DeviceRole::Extra))
+        .collect();
+    if cfg.watch_internal_kb || cfg.wake.enabled() {
+        let nodes = internal_kb_nodes(cfg, Level::Warn);
+        // Listed explicitly in INPUT_DEVICES as well: the internal role wins
+        paths.retain(|(p, _)| !nodes.contains(p));
+        paths.extend(nodes.into_iter().map(|p| (p, DeviceRole::InternalKb)));
+    }
+    paths.dedup_by(|a, b| a.0 == b.0);
+    paths
+}
+
+/// evdev nodes of the internal keyboard/trackball (`INTERNAL_KB_IDS`); it not being
+/// there is logged at `level`
+fn internal_kb_nodes(cfg: &Config, level: Level) -> Vec<PathBuf> {
+    let ids: Vec<&str> = if cfg.internal_kb_ids.is_empty() {
+        internal_kb::UCONSOLE_KB_IDS.to_vec()
+    } else {
+        cfg.internal_kb_ids.iter().map(String::as_str).collect()
+    };
+    match internal_kb::find_internal_kb(&ids) {
+        Ok(Some(usb)) => internal_kb::find_event_nodes(&usb).unwrap_or_else(|e| {
+            log!(level, "Failed to list internal keyboard inputs: {}", e);
+            Vec::new()
+        }),
+        Ok(None) => {
+            log!(level, "Internal keyboard {:?} not found", ids);
+            Vec::new()
+        }
+        Err(e) => {
+            log!(level, "Failed to find internal keyboard: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Suspend through `suspender` and put things back afterwards: the display as it
+/// was, and the grabs, which some drivers drop across a suspend
+fn suspend_and_resume(
+    suspender: &Suspender,
+    ctx: &ModeContext,
+    devices: &[Option<(InputDevice, DeviceRole)>],
+    wake_devices: &[PathBuf],
+    wake_grabbed: bool,
+) {
+    let display_paths = DisplayPaths::find();
+    let display = DisplayState::capture(&display_paths);
+    debug!("Display state before suspend: {:?}", display);
+    {
+        // No mode switch may run halfway across the sleep
+        let _mode = ctx.power_mode.lock().unwrap();
+        match suspender.suspend(wake_devices, ctx.dry_run) {
+            Ok(()) => info!("Resumed from suspend"),
+            Err(e) => error!("Suspend failed: {}", e),
+        }
+        if let Err(e) = display_on(&display_paths, Some(&display), ctx.dry_run) {
+            warn!("Failed to restore display after resume: {}", e);
+        }
+    }
+    for (d, role) in devices.iter().flatten() {
+        let grabbed = match role {
+            DeviceRole::PowerKey => true,
+            DeviceRole::InternalKb => wake_grabbed,
+            _ => false,
+        };
+        if !grabbed {
+            continue;
+        }
+        let _ = d.grab(false);
+        if let Err(e) = d.grab(true) {
+            warn!("Failed to re-grab {} after resume: {}", d.path.display(), e);
+        }
+    }
+}
+
+/// Find, open and grab the power key device. `Ok(None)` means no matching node
+/// exists (yet); an error means one exists but could not be opened.
+fn open_power_key(selector: &PowerKeySelector) -> Result<Option<InputDevice>, Error> {
+    let Some(path) = power_key::find_power_key_by(selector)? else {
+        return Ok(None);
+    };
+    let dev = InputDevice::open(&path)?;
+    info!("Using device {}", path.display());
+    // Grab exclusive access to prevent LXDE from receiving power key events
+    if let Err(e) = dev.grab(true) {
+        warn!("Failed to grab exclusive access to power key device: {}", e);
+        warn!("LXDE may still receive power key events");
+    } else {
+        info!("Successfully grabbed exclusive access to power key device");
+    }
+    Ok(Some(dev))
+}
+
+/// Arm the reconnect timer with the next backoff delay
+fn schedule_reconnect(retry_timer: &DeadlineTimer, backoff: &mut Backoff) {
+    let delay = backoff.next_delay();
+    debug!("Next power key reconnect attempt in {:?}", delay);
+    if let Err(e) = retry_timer.arm(Some(Instant::now() + delay)) {
+        warn!("Failed to arm reconnect timer: {}", e);
+    }
+}
+
+/// One attempt at bringing the power key back into the (empty) slot 0
+fn reconnect_power_key(
+    selector: &PowerKeySelector,
+    epoll: &Epoll,
+    slot: &mut Option<(InputDevice, DeviceRole)>,
+    backoff: &mut Backoff,
+    retry_timer: &DeadlineTimer,
+) {
+    match open_power_key(selector) {
+        Ok(Some(dev)) => {
+            let event = EpollEvent::new(EpollFlags::EPOLLIN, TOKEN_DEVICE_BASE);
+            if let Err(e) = epoll.add(&dev, event) {
+                warn!("Failed to add power key device to epoll: {}", e);
+                schedule_reconnect(retry_timer, backoff);
+                return;
             }
+            info!("Power key device {} is back", dev.path.display());
+            *slot = Some((dev, DeviceRole::PowerKey));
+            backoff.reset();
+            let _ = retry_timer.arm(None);
+        }
+        Ok(None) => {
+            debug!("Power key device ({}) still missing", selector);
+            schedule_reconnect(retry_timer, backoff);
+        }
+        Err(e) => {
+            warn!("Power key device not usable yet: {}", e);
+            schedule_reconnect(retry_timer, backoff);
         }
-        // auto-repeat (value 2) or anything else: no state change
-        _ => KeyEventResult {
-            decision: KeyDecision::Continue,
-            last_key_down,
-        },
     }
 }
 
-// Use PowerMode and enter/exit functions from the library `power_mode` module.
+/// Open every other keyboard, trackball or pointer so any input resets the idle
+/// timer. Nodes that are already open (by their resolved path) are skipped.
+fn activity_devices(open: &[Option<(InputDevice, DeviceRole)>]) -> Vec<(InputDevice, DeviceRole)> {
+    let open: Vec<PathBuf> = open
+        .iter()
+        .flatten()
+        .filter_map(|(d, _)| fs::canonicalize(&d.path).ok())
+        .collect();
+    let nodes = match power_key::list_event_nodes(Path::new("/dev/input")) {
+        Ok(n) => n,
+        Err(e) => {
+            warn!("Failed to list input devices: {}", e);
+            return Vec::new();
+        }
+    };
+    let mut found = Vec::new();
+    for node in nodes {
+        if fs::canonicalize(&node).is_ok_and(|p| open.contains(&p)) {
+            continue;
+        }
+        let Ok(d) = InputDevice::open(&node) else {
+            continue;
+        };
+        let info = d.info();
+        if info.name == VIRTUAL_DEVICE_NAME || !is_activity_source(&info) {
+            continue;
+        }
+        info!("Watching {} ({}) for activity", node.display(), info.name);
+        found.push((d, DeviceRole::Activity));
+    }
+    found
+}
+
+/// Watch `d` in a slot emptied by an unplugged device where possible (never the
+/// power key's). False if it couldn't be added to epoll.
+fn watch_device(
+    epoll: &Epoll,
+    devices: &mut Vec<Option<(InputDevice, DeviceRole)>>,
+    d: InputDevice,
+    role: DeviceRole,
+) -> bool {
+    let index = devices
+        .iter()
+        .skip(1)
+        .position(Option::is_none)
+        .map_or(devices.len(), |i| i + 1);
+    let event = EpollEvent::new(EpollFlags::EPOLLIN, TOKEN_DEVICE_BASE + index as u64);
+    if let Err(e) = epoll.add(&d, event) {
+        warn!("Failed to add {} to epoll: {}", d.path.display(), e);
+        return false;
+    }
+    if index == devices.len() {
+        devices.push(Some((d, role)));
+    } else {
+        devices[index] = Some((d, role));
+    }
+    true
+}
+
+/// Watch activity devices plugged in since the last scan
+fn watch_new_activity_devices(epoll: &Epoll, devices: &mut Vec<Option<(InputDevice, DeviceRole)>>) {
+    for (d, role) in activity_devices(devices) {
+        watch_device(epoll, devices, d, role);
+    }
+}
+
+/// Watch internal keyboard/trackball nodes that came back since the last scan (e.g.
+/// after a USB reset), grabbed if the others are
+fn watch_new_internal_kb(
+    cfg: &Config,
+    epoll: &Epoll,
+    devices: &mut Vec<Option<(InputDevice, DeviceRole)>>,
+    grabbed: bool,
+) {
+    let open: Vec<PathBuf> = devices
+        .iter()
+        .flatten()
+        .filter_map(|(d, _)| fs::canonicalize(&d.path).ok())
+        .collect();
+    for path in internal_kb_nodes(cfg, Level::Debug) {
+        if fs::canonicalize(&path).is_ok_and(|p| open.contains(&p)) {
+            continue;
+        }
+        let d = match InputDevice::open(&path) {
+            Ok(d) => d,
+            Err(e) => {
+                warn!("Skipping input device: {}", e);
+                continue;
+            }
+        };
+        if grabbed && let Err(e) = d.grab(true) {
+            warn!("Failed to grab {}: {}", d.path.display(), e);
+        }
+        info!(
+            "Watching {:?} input device {}",
+            DeviceRole::InternalKb,
+            path.display()
+        );
+        watch_device(epoll, devices, d, DeviceRole::InternalKb);
+    }
+}
 
 fn resolve_log_level(
     rust_log_env: Option<String>,
@@ -124,7 +722,12 @@ fn resolve_log_level(
 
 fn main() {
     // parse basic CLI flags
-    let (dry_run, verbosity, toggle_wifi_flag, toggle_bt_flag, cli_config_path) = parse_cli_args();
+    let cli = parse_cli_args();
+    let dry_run = cli.dry_run;
+    let verbosity = cli.verbosity;
+    let toggle_wifi_flag = cli.toggle_wifi;
+    let toggle_bt_flag = cli.toggle_bt;
+    let cli_config_path = cli.config_path.clone();
 
     // Read configuration (env vars + config file)
     let cfg = Config::load(cli_config_path.clone());
@@ -142,6 +745,9 @@ fn main() {
     }
     let _ = builder.try_init();
     info!("Starting sleep-remap-powerkey (power-saving mode toggle)");
+    for w in &cfg.warnings {
+        warn!("{}", w);
+    }
 
     let hold_trigger = Duration::from_secs_f32(
         cfg.hold_trigger_sec
@@ -156,29 +762,27 @@ fn main() {
     // Track current power mode (shared between threads)
     let power_mode = Arc::new(Mutex::new(PowerMode::Normal));
 
-    // Setup CPU frequency configuration, prefer CLI flag override if provided
-    let saving_cpu_freq = cfg
-        .saving_cpu_freq
-        .clone()
-        .or_else(|| env::var("SAVING_CPU_FREQ").ok());
-    let cpu_config = if let Some(path) = cfg.policy_path.clone() {
-        CpuFreqConfig::with_policy_path(path, saving_cpu_freq.clone())
-    } else {
-        CpuFreqConfig::new(saving_cpu_freq.clone())
-    };
-    // Determine wifi and bt config: CLI flags override config file; use clones to avoid moving original variables used for logging
-    let final_toggle_wifi = match toggle_wifi_flag {
-        Some(v) => v,
-        None => cfg.toggle_wifi,
-    };
-    let final_toggle_bt = match toggle_bt_flag {
-        Some(v) => v,
-        None => cfg.toggle_bt,
-    };
-    let final_wifi_rfkill = cfg.wifi_rfkill_path.clone();
-    let final_bt_rfkill = cfg.bt_rfkill_path.clone();
-    let wifi_config = WifiConfig::new(final_toggle_wifi, final_wifi_rfkill.clone());
-    let bt_config = BTConfig::new(final_toggle_bt, final_bt_rfkill.clone());
+    // Saving mode settings for AC and battery. Both are set up while still in
+    // normal mode, so the CPU defaults they restore are the real ones.
+    // The thermal cap is shared by all of them, so no mode change lifts it
+    let mut on_ac = battery::on_ac_power().unwrap_or(false);
+    let freq_cap = FreqCap::default();
+    let saving_ac = saving_config(
+        &cfg.for_power_source(true),
+        toggle_wifi_flag,
+        toggle_bt_flag,
+        &freq_cap,
+    );
+    let saving_battery = saving_config(
+        &cfg.for_power_source(false),
+        toggle_wifi_flag,
+        toggle_bt_flag,
+        &freq_cap,
+    );
+    let source_cfg = cfg.for_power_source(on_ac);
+    let saving_cpu_freq = source_cfg.saving_cpu_freq.clone();
+    let builtin = if on_ac { &saving_ac } else { &saving_battery }.clone();
+    let cpu_config = builtin.cpu.clone();
 
     // Print all parameters for startup debugging (capture a string for options to avoid moves)
     let opt_to_str = |p: &Option<PathBuf>| match p {
@@ -186,12 +790,8 @@ fn main() {
         None => "None".to_string(),
     };
     let cli_policy_str = "None".to_string();
-    let wifi_rfkill_cli_str = "None".to_string();
-    let bt_rfkill_cli_str = "None".to_string();
     let cli_config_str = opt_to_str(&cli_config_path);
     let cfg_policy_str = opt_to_str(&cfg.policy_path);
-    let cfg_wifi_rfkill_str = opt_to_str(&cfg.wifi_rfkill_path);
-    let cfg_bt_rfkill_str = opt_to_str(&cfg.bt_rfkill_path);
 
     debug!("cli.dry_run={}", dry_run);
     // CLI --dry-run and config/env DRY_RUN are OR'd: either source enables dry-run.
@@ -201,72 +801,244 @@ fn main() {
     debug!("cli.config_path={}", cli_config_str);
     debug!("cli.toggle_wifi={:?}", toggle_wifi_flag);
     debug!("cli.toggle_bt={:?}", toggle_bt_flag);
-    debug!("cli.wifi_rfkill={}", wifi_rfkill_cli_str);
-    debug!("cli.bt_rfkill={}", bt_rfkill_cli_str);
 
     debug!("cfg.dry_run={}", cfg.dry_run);
     debug!("final.dry_run={}", dry_run);
     debug!("cfg.policy_path={}", cfg_policy_str);
     debug!("cfg.saving_cpu_freq={:?}", cfg.saving_cpu_freq);
     debug!("cfg.hold_trigger_sec={:?}", cfg.hold_trigger_sec);
-    debug!("cfg.toggle_wifi={}", cfg.toggle_wifi);
-    debug!("cfg.toggle_bt={}", cfg.toggle_bt);
-    debug!("cfg.wifi_rfkill={}", cfg_wifi_rfkill_str);
-    debug!("cfg.bt_rfkill={}", cfg_bt_rfkill_str);
+    debug!("cfg.rf={:?}", cfg.rf);
 
     debug!("derived.hold_trigger_s={:.3}", hold_trigger.as_secs_f32());
     debug!("derived.saving_cpu_freq={:?}", saving_cpu_freq);
     debug!(
-        "derived.cpu_policy_path={}",
-        cpu_config.policy_path.display()
-    );
-    debug!("derived.cpu_saving_min={:?}", cpu_config.saving_min);
-    debug!("derived.cpu_saving_max={:?}", cpu_config.saving_max);
-    debug!("derived.cpu_default_min={:?}", cpu_config.default_min);
-    debug!("derived.cpu_default_max={:?}", cpu_config.default_max);
-    debug!("derived.final_toggle_wifi={}", final_toggle_wifi);
-    debug!(
-        "derived.final_wifi_rfkill={}",
-        opt_to_str(&final_wifi_rfkill)
+        "cfg.saving_cpu_freq_policies={:?}",
+        cfg.saving_cpu_freq_policies
     );
+    for policy in &cpu_config.policies {
+        debug!(
+            "derived.cpu_policy={} saving={:?}/{:?} default={:?}/{:?}",
+            policy.policy_path.display(),
+            policy.saving_min,
+            policy.saving_max,
+            policy.default_min,
+            policy.default_max
+        );
+    }
+    debug!("cfg.saving_cpu_offline={:?}", cfg.saving_cpu_offline);
+    for dev in &builtin.devfreq.devices {
+        debug!(
+            "derived.devfreq={} saving_max={:?} governor={:?}",
+            dev.path.display(),
+            dev.saving_max,
+            dev.saving_governor
+        );
+    }
+    for rf in builtin.rf.iter().filter(|r| r.enabled) {
+        let devices: Vec<String> = rf
+            .devices()
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect();
+        debug!(
+            "derived.rf.{}={} ({})",
+            rf.kind,
+            rf.select,
+            devices.join(" ")
+        );
+    }
 
-    let dev = match power_key::find_power_key() {
-        Ok(Some(p)) => p,
-        Ok(None) => {
-            error!("Power key device not found, exiting");
+    // Long press action: CLI overrides config; default is to only log the press
+    let long_press_action = cli
+        .long_press_action
+        .clone()
+        .or_else(|| cfg.long_press_action.clone())
+        .unwrap_or(Action::None);
+    debug!("cli.long_press_action={:?}", cli.long_press_action);
+    debug!("cfg.long_press_action={:?}", cfg.long_press_action);
+    debug!("derived.long_press_action={}", long_press_action);
+
+    // Gesture bindings: defaults < LONG_PRESS_ACTION < BIND_* keys < CLI long press
+    let tap_window = cfg
+        .tap_window_sec
+        .map(Duration::from_secs_f32)
+        .unwrap_or(DEFAULT_TAP_WINDOW);
+    let mut bindings = Bindings::with_defaults();
+    if let Some(a) = cfg.long_press_action.clone() {
+        bindings.bind(None, Gesture::Hold, a);
+    }
+    bindings.extend(&cfg.bindings);
+    if let Some(a) = cli.long_press_action.clone() {
+        bindings.bind(None, Gesture::Hold, a);
+    }
+    let mut recognizer = GestureRecognizer::new(hold_trigger, tap_window);
+    debug!("derived.tap_window_s={:.3}", tap_window.as_secs_f32());
+    debug!("derived.bindings={:?}", bindings);
+    debug!("cfg.hold_feedback={:?}", cfg.hold_feedback);
+    let hold_feedback = cfg.hold_feedback.clone();
+
+    let mode_changed = match EventFd::from_flags(EfdFlags::EFD_NONBLOCK | EfdFlags::EFD_CLOEXEC) {
+        Ok(fd) => Arc::new(fd),
+        Err(e) => {
+            error!("Failed to create mode change eventfd: {}", e);
             return;
         }
+    };
+    let suspend_request = match EventFd::from_flags(EfdFlags::EFD_NONBLOCK | EfdFlags::EFD_CLOEXEC)
+    {
+        Ok(fd) => Arc::new(fd),
         Err(e) => {
-            error!("Failed to find power key: {}", e);
+            error!("Failed to create suspend request eventfd: {}", e);
             return;
         }
     };
+    // Power key device and trigger key: CLI overrides config
+    let power_key_selector = cli
+        .power_key_device
+        .clone()
+        .or_else(|| cfg.power_key_device.clone())
+        .unwrap_or_default();
+    recognizer.key = cli
+        .power_key_code
+        .or(cfg.power_key_code)
+        .unwrap_or(KEY_POWER);
+    debug!("derived.power_key_device={}", power_key_selector);
+    debug!("derived.power_key_code={}", recognizer.key);
 
-    info!("Using device {}", dev.display());
-
-    let mut file = match File::open(&dev) {
-        Ok(f) => f,
+    // A missing power key is not fatal: wait for it to show up instead
+    let mut reconnect_backoff = Backoff::default();
+    let power_slot = match open_power_key(&power_key_selector) {
+        Ok(Some(d)) => Some((d, DeviceRole::PowerKey)),
+        Ok(None) => {
+            warn!(
+                "Power key device ({}) not found, waiting for it to appear",
+                power_key_selector
+            );
+            None
+        }
         Err(e) => {
-            error!("Failed to open device {}: {}", dev.display(), e);
-            return;
+            warn!("Power key device not usable yet: {}", e);
+            None
         }
     };
+    let power_path = power_slot.as_ref().map(|(d, _)| d.path.clone());
 
-    // Grab exclusive access to prevent LXDE from receiving power key events
-    let fd = file.as_raw_fd();
-    unsafe {
-        let ret = libc::ioctl(fd, EVIOCGRAB as _, 1);
-        if ret != 0 {
-            warn!("Failed to grab exclusive access to power key device");
-            warn!("LXDE may still receive power key events");
-        } else {
-            info!("Successfully grabbed exclusive access to power key device");
+    // Index 0 is always the power key slot; the rest are watched for chords and
+    // wake-up. A slot is emptied when its device is unplugged.
+    let mut devices = vec![power_slot];
+    for (path, role) in extra_input_devices(&cfg) {
+        if Some(&path) == power_path.as_ref() {
+            continue;
+        }
+        match InputDevice::open(&path) {
+            Ok(d) => {
+                info!("Watching {:?} input device {}", role, path.display());
+                devices.push(Some((d, role)));
+            }
+            Err(e) => warn!("Skipping input device: {}", e),
         }
     }
+    if cfg.idle.enabled() {
+        devices.extend(activity_devices(&devices).into_iter().map(Some));
+    }
+    let mut chords = cfg.chords.clone();
+    let mut key_state = KeyState::default();
+    debug!("cfg.chords={:?}", chords);
+    let mut wake = WakeDetector::new(cfg.wake.clone());
+    let mut wake_grabbed = false;
+    debug!("cfg.wake={:?}", cfg.wake);
 
-    // input_event struct is 24 bytes (2x i64 + u16 + u16 + i32)
-    let mut buf = [0u8; 24];
-    let mut last_key_down_timestamp: Option<Instant> = None;
+    // Virtual keyboard for `key:` actions and pass-through of unconsumed events
+    let mut emit_keys: Vec<u16> = bindings
+        .actions()
+        .chain(chords.actions())
+        .filter_map(|a| match a {
+            Action::EmitKey(code) => Some(*code),
+            _ => None,
+        })
+        .collect();
+    if cfg.uinput_passthrough {
+        match &devices[0] {
+            Some((d, _)) => emit_keys.extend(d.info().supported_keys()),
+            None => warn!("Power key device missing, pass-through limited to mapped keys"),
+        }
+    }
+    let virtual_keys = if emit_keys.is_empty() {
+        None
+    } else {
+        match VirtualDevice::create(&emit_keys) {
+            Ok(v) => {
+                info!("Created virtual input device for {} keys", v.keys.len());
+                Some(Arc::new(v))
+            }
+            Err(e) => {
+                warn!("Failed to create uinput device: {}", e);
+                None
+            }
+        }
+    };
+    debug!("cfg.uinput_passthrough={}", cfg.uinput_passthrough);
+
+    let mut idle = IdleTracker::new(cfg.idle.clone(), Instant::now());
+    debug!("cfg.idle={:?}", cfg.idle);
+    debug!("derived.on_ac={}", on_ac);
+
+    let supplies = battery::find_supplies().unwrap_or_else(|e| {
+        warn!("Failed to look up power supplies: {}", e);
+        Default::default()
+    });
+    let mut low_battery = LowBatteryMonitor::new(cfg.battery.clone());
+    if cfg.battery.enabled() && supplies.battery.is_none() {
+        warn!("Battery thresholds configured but no battery found");
+    }
+    debug!("cfg.battery={:?}", cfg.battery);
+    debug!("derived.power_supplies={:?}", supplies);
+
+    let backlight_path = backlight::find_backlight().unwrap_or_else(|e| {
+        warn!("Failed to look up backlight: {}", e);
+        None
+    });
+    let dimmer = Arc::new(Dimmer::new(cfg.dim.clone(), backlight_path, dry_run));
+    debug!("cfg.dim={:?}", cfg.dim);
+    debug!("derived.dim_enabled={}", dimmer.enabled());
+
+    // Named profiles, also set up while in normal mode for the CPU defaults
+    let profile_configs: BTreeMap<String, SavingConfig> = cfg
+        .profiles
+        .specs
+        .iter()
+        .map(|(name, spec)| (name.clone(), profile_config(&cfg, name, spec, &freq_cap)))
+        .collect();
+    for name in cfg.profiles.unknown_names() {
+        warn!(
+            "Unknown power profile '{}' in PROFILE_CYCLE/PROFILE_DEFAULT",
+            name
+        );
+    }
+    if cfg.dim.percent.is_none()
+        && profile_configs
+            .values()
+            .any(|p| p.display == DisplayAction::Dim)
+    {
+        warn!("A profile dims the display but DIM_PERCENT is not set");
+    }
+    debug!("cfg.profiles={:?}", cfg.profiles);
+    debug!("derived.profile_cycle={:?}", cfg.profiles.cycle_order());
+
+    let ctx = ModeContext {
+        power_mode: Arc::clone(&power_mode),
+        saving_now: Arc::new(AtomicBool::new(false)),
+        saving: Arc::new(Mutex::new(builtin.clone())),
+        saving_rf: Arc::new(Mutex::new(builtin.rf.clone())),
+        builtin: Arc::new(Mutex::new(builtin)),
+        profile_configs: Arc::new(profile_configs),
+        profiles: Arc::new(cfg.profiles.clone()),
+        dry_run,
+        mode_changed: Arc::clone(&mode_changed),
+        virtual_keys,
+        dimmer: Arc::clone(&dimmer),
+        suspend_request: Arc::clone(&suspend_request),
+    };
 
     // Setup epoll
     let epoll = match Epoll::new(EpollCreateFlags::empty()) {
@@ -277,88 +1049,614 @@ fn main() {
         }
     };
 
-    let event = EpollEvent::new(EpollFlags::EPOLLIN, 0);
-    if let Err(e) = epoll.add(&file, event) {
-        error!("Failed to add input device to epoll: {}", e);
+    for (i, (d, _)) in devices
+        .iter()
+        .enumerate()
+        .filter_map(|(i, slot)| Some((i, slot.as_ref()?)))
+    {
+        let event = EpollEvent::new(EpollFlags::EPOLLIN, TOKEN_DEVICE_BASE + i as u64);
+        if let Err(e) = epoll.add(d, event) {
+            error!(
+                "Failed to add input device {} to epoll: {}",
+                d.path.display(),
+                e
+            );
+            return;
+        }
+    }
+
+    // Fires when a hold threshold is crossed or a multi-tap window closes
+    let timer = match DeadlineTimer::new() {
+        Ok(t) => t,
+        Err(e) => {
+            error!("Failed to create gesture timer: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = epoll.add(&timer, EpollEvent::new(EpollFlags::EPOLLIN, TOKEN_TIMER)) {
+        error!("Failed to add gesture timer to epoll: {}", e);
+        return;
+    }
+    if let Err(e) = epoll.add(
+        mode_changed.as_ref(),
+        EpollEvent::new(EpollFlags::EPOLLIN, TOKEN_MODE),
+    ) {
+        error!("Failed to add mode change eventfd to epoll: {}", e);
+        return;
+    }
+
+    // Power key hotplug: new /dev/input entries trigger an immediate attempt, the
+    // retry timer covers nodes that exist but can't be opened yet
+    let mut input_watcher = match InputDirWatcher::new() {
+        Ok(w) => w,
+        Err(e) => {
+            error!("Failed to watch for input devices: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = epoll.add(
+        &input_watcher,
+        EpollEvent::new(EpollFlags::EPOLLIN, TOKEN_HOTPLUG),
+    ) {
+        error!("Failed to add input watcher to epoll: {}", e);
+        return;
+    }
+    let retry_timer = match DeadlineTimer::new() {
+        Ok(t) => t,
+        Err(e) => {
+            error!("Failed to create reconnect timer: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = epoll.add(
+        &retry_timer,
+        EpollEvent::new(EpollFlags::EPOLLIN, TOKEN_RECONNECT),
+    ) {
+        error!("Failed to add reconnect timer to epoll: {}", e);
+        return;
+    }
+    if devices[0].is_none() {
+        schedule_reconnect(&retry_timer, &mut reconnect_backoff);
+    }
+
+    // Fires when the idle timeout for the current power source runs out
+    let idle_timer = match DeadlineTimer::new() {
+        Ok(t) => t,
+        Err(e) => {
+            error!("Failed to create idle timer: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = epoll.add(
+        &idle_timer,
+        EpollEvent::new(EpollFlags::EPOLLIN, TOKEN_IDLE),
+    ) {
+        error!("Failed to add idle timer to epoll: {}", e);
+        return;
+    }
+    // Periodic battery readings for the low/critical thresholds
+    let battery_timer = match DeadlineTimer::new() {
+        Ok(t) => t,
+        Err(e) => {
+            error!("Failed to create battery timer: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = epoll.add(
+        &battery_timer,
+        EpollEvent::new(EpollFlags::EPOLLIN, TOKEN_BATTERY),
+    ) {
+        error!("Failed to add battery timer to epoll: {}", e);
+        return;
+    }
+    if cfg.battery.enabled()
+        && supplies.battery.is_some()
+        && let Err(e) = battery_timer.arm(Some(Instant::now()))
+    {
+        warn!("Failed to arm battery timer: {}", e);
+    }
+
+    // Charger plug/unplug switches between the AC and battery saving settings
+    let source_overrides = !cfg.on_ac.is_empty() || !cfg.on_battery.is_empty();
+    let mut saving_on_ac = on_ac;
+    let charger_timer = match DeadlineTimer::new() {
+        Ok(t) => t,
+        Err(e) => {
+            error!("Failed to create charger timer: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = epoll.add(
+        &charger_timer,
+        EpollEvent::new(EpollFlags::EPOLLIN, TOKEN_CHARGER),
+    ) {
+        error!("Failed to add charger timer to epoll: {}", e);
+        return;
+    }
+    if source_overrides && let Err(e) = charger_timer.arm(Some(Instant::now() + CHARGER_POLL)) {
+        warn!("Failed to arm charger timer: {}", e);
+    }
+
+    // Actions from other programs, e.g. `echo profile:deep > /run/uconsole-sleep/control`
+    let mut control = match &cfg.control_fifo {
+        Some(path) => match ControlFifo::open(path) {
+            Ok(c) => {
+                info!("Accepting control commands on {}", path.display());
+                Some(c)
+            }
+            Err(e) => {
+                warn!("Control FIFO unavailable: {}", e);
+                None
+            }
+        },
+        None => None,
+    };
+    if let Some(c) = &control
+        && let Err(e) = epoll.add(c, EpollEvent::new(EpollFlags::EPOLLIN, TOKEN_CONTROL))
+    {
+        warn!("Failed to add control FIFO to epoll: {}", e);
+        control = None;
+    }
+
+    // Real suspend for the `sleep` action
+    let suspender = Suspender::new(cfg.suspend.clone());
+    debug!("cfg.suspend={:?}", cfg.suspend);
+    if bindings
+        .actions()
+        .chain(chords.actions())
+        .any(|a| *a == Action::Sleep)
+        && let Err(e) = suspender.check()
+    {
+        warn!("'sleep' is bound but suspend won't work: {}", e);
+    }
+    if let Err(e) = epoll.add(
+        suspend_request.as_ref(),
+        EpollEvent::new(EpollFlags::EPOLLIN, TOKEN_SUSPEND),
+    ) {
+        error!("Failed to add suspend request eventfd to epoll: {}", e);
         return;
     }
+    let mut resume_settle_until: Option<Instant> = None;
+
+    if let Some(name) = &cli.profile {
+        switch_profile(&ctx, Some(name.clone()));
+    }
+
+    // Ends the dim stage: saving mode follows unless input cancelled it first
+    let dim_timer = match DeadlineTimer::new() {
+        Ok(t) => t,
+        Err(e) => {
+            error!("Failed to create dim timer: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = epoll.add(&dim_timer, EpollEvent::new(EpollFlags::EPOLLIN, TOKEN_DIM)) {
+        error!("Failed to add dim timer to epoll: {}", e);
+        return;
+    }
+
+    // Thermal cap on every cpufreq policy, in any power mode
+    let mut thermal = cfg.thermal.enabled().then(|| {
+        let policies: Vec<PathBuf> = cpu_config
+            .policies
+            .iter()
+            .map(|p| p.policy_path.clone())
+            .collect();
+        ThermalMonitor::new(cfg.thermal.clone(), &policies, freq_cap.clone())
+    });
+    if let Some(release) = cfg.thermal.release_millic
+        && cfg.thermal.trip_millic.is_some_and(|trip| release >= trip)
+    {
+        warn!("THERMAL_RELEASE_C is not below THERMAL_TRIP_C, using the default hysteresis");
+    }
+    let thermal_timer = match DeadlineTimer::new() {
+        Ok(t) => t,
+        Err(e) => {
+            error!("Failed to create thermal timer: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = epoll.add(
+        &thermal_timer,
+        EpollEvent::new(EpollFlags::EPOLLIN, TOKEN_THERMAL),
+    ) {
+        error!("Failed to add thermal timer to epoll: {}", e);
+        return;
+    }
+    if thermal.is_some()
+        && let Err(e) = thermal_timer.arm(Some(Instant::now()))
+    {
+        warn!("Failed to arm thermal timer: {}", e);
+    }
+
+    // rfkill events: hardware switches, and radios turned on behind our back
+    let mut rfkill_monitor = match &cfg.rfkill_dev {
+        Some(path) => match RfkillMonitor::open(path) {
+            Ok(m) => Some(m),
+            Err(e) => {
+                warn!("rfkill events unavailable: {}", e);
+                None
+            }
+        },
+        None => None,
+    };
+    if let Some(m) = &rfkill_monitor
+        && let Err(e) = epoll.add(m, EpollEvent::new(EpollFlags::EPOLLIN, TOKEN_RFKILL))
+    {
+        warn!("Failed to add {} to epoll: {}", m.path.display(), e);
+        rfkill_monitor = None;
+    }
 
     loop {
-        let mut events = vec![EpollEvent::new(EpollFlags::empty(), 0); 4];
+        let mut events = vec![EpollEvent::new(EpollFlags::empty(), 0); 8];
         match epoll.wait(&mut events, EpollTimeout::NONE) {
             Ok(num) => {
                 for ev in &events[..num] {
-                    if ev.events().contains(EpollFlags::EPOLLIN) {
-                        match file.read_exact(&mut buf) {
-                            Ok(_) => {
-                                let sec = i64::from_ne_bytes(buf[0..8].try_into().unwrap());
-                                let usec = i64::from_ne_bytes(buf[8..16].try_into().unwrap());
-                                let etype = u16::from_ne_bytes(buf[16..18].try_into().unwrap());
-                                let code = u16::from_ne_bytes(buf[18..20].try_into().unwrap());
-                                let value = i32::from_ne_bytes(buf[20..24].try_into().unwrap());
-
-                                debug!(
-                                    "event: t={} ms={} type={} code={} value={}",
-                                    sec, usec, etype, code, value
-                                );
-
-                                let now = Instant::now();
-                                let result = classify_key_event(
-                                    last_key_down_timestamp,
-                                    etype,
-                                    code,
-                                    value,
-                                    hold_trigger,
-                                    now,
+                    if ev.data() == TOKEN_TIMER {
+                        timer.acknowledge();
+                        continue;
+                    }
+                    if ev.data() == TOKEN_IDLE {
+                        idle_timer.acknowledge();
+                        // The timeout depends on the supply, which may have changed
+                        on_ac = battery::on_ac_power().unwrap_or(on_ac);
+                        if idle.poll(Instant::now(), on_ac) && ctx.mode() == PowerMode::Normal {
+                            let timeout = idle.config.timeout(on_ac).unwrap_or_default();
+                            let source = if on_ac { "AC" } else { "battery" };
+                            if dimmer.enabled() {
+                                info!("No input for {:?} on {}, dimming display", timeout, source);
+                                dimmer.dim();
+                                if let Err(e) =
+                                    dim_timer.arm(Some(Instant::now() + dimmer.config.hold))
+                                {
+                                    warn!("Failed to arm dim timer: {}", e);
+                                }
+                            } else {
+                                info!(
+                                    "No input for {:?} on {}, entering saving mode",
+                                    timeout, source
                                 );
-                                last_key_down_timestamp = result.last_key_down;
-
-                                match result.decision {
-                                    KeyDecision::Continue => {}
-                                    KeyDecision::ShortPress => {
-                                        info!("Power key short press: toggling power mode");
-                                        // short press -> toggle power mode
-                                        let mode_clone = Arc::clone(&power_mode);
-                                        let cpu_config_clone = cpu_config.clone();
-                                        let dry_run_clone = dry_run;
-                                        let wifi_config_clone = wifi_config.clone();
-                                        let bt_config_clone = bt_config.clone();
-
-                                        spawn(move || {
-                                            let mut mode = mode_clone.lock().unwrap();
-                                            match *mode {
-                                                PowerMode::Normal => {
-                                                    enter_saving_mode(
-                                                        &cpu_config_clone,
-                                                        dry_run_clone,
-                                                        Some(&wifi_config_clone),
-                                                        Some(&bt_config_clone),
-                                                    );
-                                                    *mode = PowerMode::Saving;
-                                                }
-                                                PowerMode::Saving => {
-                                                    exit_saving_mode(
-                                                        &cpu_config_clone,
-                                                        dry_run_clone,
-                                                        Some(&wifi_config_clone),
-                                                        Some(&bt_config_clone),
-                                                    );
-                                                    *mode = PowerMode::Normal;
-                                                }
-                                            }
-                                        });
+                                switch_power_mode(&ctx, Some(PowerMode::Saving));
+                            }
+                        }
+                        continue;
+                    }
+                    if ev.data() == TOKEN_BATTERY {
+                        battery_timer.acknowledge();
+                        let now = Instant::now();
+                        on_ac = supplies.charger_online().unwrap_or(on_ac);
+                        match supplies.read_battery() {
+                            Ok(Some(status)) => {
+                                debug!("battery: {:?} on_ac={}", status, on_ac);
+                                match low_battery.update(&status, on_ac, now) {
+                                    Some(BatteryEvent::Low(c)) => {
+                                        info!("Battery at {}%, entering saving mode", c);
+                                        switch_power_mode(&ctx, Some(PowerMode::Saving));
+                                    }
+                                    Some(BatteryEvent::Critical(c)) => {
+                                        warn!(
+                                            "Battery critical at {}%, powering off in {:?} unless a charger is connected",
+                                            c, low_battery.config.critical_grace
+                                        );
+                                        switch_power_mode(&ctx, Some(PowerMode::Saving));
+                                    }
+                                    Some(BatteryEvent::Shutdown(c)) => {
+                                        error!("Battery still critical at {}%, powering off", c);
+                                        run_action(&Action::Poweroff, &ctx);
                                     }
-                                    KeyDecision::LongPress => {
-                                        info!("Long press detected (no action implemented)");
+                                    Some(BatteryEvent::Recovered) => {
+                                        info!("Battery no longer critical, poweroff cancelled")
                                     }
+                                    None => {}
                                 }
                             }
-                            Err(e) => {
-                                warn!("Error reading event: {}", e);
-                                sleep(Duration::from_millis(200));
+                            Ok(None) => {}
+                            Err(e) => warn!("Failed to read battery: {}", e),
+                        }
+                        if let Err(e) = battery_timer.arm(Some(low_battery.next_poll(now))) {
+                            warn!("Failed to arm battery timer: {}", e);
+                        }
+                        continue;
+                    }
+                    if ev.data() == TOKEN_CHARGER {
+                        charger_timer.acknowledge();
+                        on_ac = supplies
+                            .charger_online()
+                            .or_else(|| battery::on_ac_power().ok())
+                            .unwrap_or(on_ac);
+                        if let Err(e) = charger_timer.arm(Some(Instant::now() + CHARGER_POLL)) {
+                            warn!("Failed to arm charger timer: {}", e);
+                        }
+                        continue;
+                    }
+                    if ev.data() == TOKEN_THERMAL {
+                        thermal_timer.acknowledge();
+                        if let Some(t) = thermal.as_mut() {
+                            t.poll(dry_run);
+                            if let Err(e) =
+                                thermal_timer.arm(Some(Instant::now() + t.config.poll_interval))
+                            {
+                                warn!("Failed to arm thermal timer: {}", e);
+                            }
+                        }
+                        continue;
+                    }
+                    if ev.data() == TOKEN_RFKILL {
+                        let Some(m) = rfkill_monitor.as_mut() else {
+                            continue;
+                        };
+                        for (e, change) in m.read_changes() {
+                            let Some(kind) = e.rf_type() else {
+                                continue;
+                            };
+                            match change {
+                                RfkillChange::Hard(hard) => info!(
+                                    "{}: rfkill{} hardware switch {}",
+                                    kind.label(),
+                                    e.idx,
+                                    if hard { "on (blocked)" } else { "off" }
+                                ),
+                                RfkillChange::Soft(true) => {
+                                    debug!("{}: rfkill{} blocked", kind.label(), e.idx)
+                                }
+                                RfkillChange::Soft(false) => {
+                                    // only logged: blocking it again would fight
+                                    // whoever turned it on
+                                    let external = !rf::take_own_unblock(e.idx)
+                                        && ctx.mode() == PowerMode::Saving
+                                        && ctx
+                                            .rf(kind)
+                                            .is_some_and(|rf| rf.enabled && rf.covers(e.idx));
+                                    if external {
+                                        info!(
+                                            "{}: rfkill{} turned back on by another program (e.g. NetworkManager) in saving mode, leaving it on",
+                                            kind.label(),
+                                            e.idx
+                                        );
+                                    } else {
+                                        debug!("{}: rfkill{} unblocked", kind.label(), e.idx);
+                                    }
+                                }
                             }
                         }
+                        continue;
                     }
+                    if ev.data() == TOKEN_CONTROL {
+                        if let Some(c) = control.as_mut() {
+                            for cmd in c.read_commands() {
+                                match cmd {
+                                    Ok(action) => {
+                                        info!("Control command: running '{}'", action);
+                                        run_action(&action, &ctx);
+                                    }
+                                    Err(e) => warn!("Ignoring control command: {}", e),
+                                }
+                            }
+                        }
+                        continue;
+                    }
+                    if ev.data() == TOKEN_SUSPEND {
+                        let _ = suspend_request.read();
+                        let mut wake_devices: Vec<PathBuf> = devices
+                            .iter()
+                            .flatten()
+                            .filter(|(_, r)| *r == DeviceRole::PowerKey)
+                            .map(|(d, _)| d.path.clone())
+                            .collect();
+                        if cfg.suspend.wake_keyboard {
+                            wake_devices.extend(internal_kb_nodes(&cfg, Level::Warn));
+                        }
+                        suspend_and_resume(&suspender, &ctx, &devices, &wake_devices, wake_grabbed);
+                        // Whatever was held went unseen while asleep
+                        recognizer.cancel_press();
+                        key_state.clear();
+                        let now = Instant::now();
+                        idle.reset(now);
+                        resume_settle_until = Some(now + RESUME_SETTLE);
+                        continue;
+                    }
+                    if ev.data() == TOKEN_DIM {
+                        dim_timer.acknowledge();
+                        if dimmer.is_dimmed() && ctx.mode() == PowerMode::Normal {
+                            info!("Still no input after dimming, entering saving mode");
+                            switch_power_mode(&ctx, Some(PowerMode::Saving));
+                        }
+                        continue;
+                    }
+                    if ev.data() == TOKEN_HOTPLUG || ev.data() == TOKEN_RECONNECT {
+                        let triggered = if ev.data() == TOKEN_HOTPLUG {
+                            input_watcher.changed()
+                        } else {
+                            retry_timer.acknowledge();
+                            true
+                        };
+                        if triggered && devices[0].is_none() {
+                            reconnect_power_key(
+                                &power_key_selector,
+                                &epoll,
+                                &mut devices[0],
+                                &mut reconnect_backoff,
+                                &retry_timer,
+                            );
+                        }
+                        // Before the activity scan, which would take it as one of its own
+                        if ev.data() == TOKEN_HOTPLUG
+                            && triggered
+                            && (cfg.watch_internal_kb || cfg.wake.enabled())
+                        {
+                            watch_new_internal_kb(&cfg, &epoll, &mut devices, wake_grabbed);
+                        }
+                        // A newly plugged keyboard or mouse counts as activity too
+                        if ev.data() == TOKEN_HOTPLUG && triggered && cfg.idle.enabled() {
+                            watch_new_activity_devices(&epoll, &mut devices);
+                        }
+                        continue;
+                    }
+                    if ev.data() == TOKEN_MODE {
+                        let _ = mode_changed.read();
+                        let mode = power_mode.lock().unwrap().clone();
+                        wake.reset();
+                        if mode == PowerMode::Normal {
+                            idle.reset(Instant::now());
+                        }
+                        // A manual switch ends the dim stage as well
+                        let _ = dim_timer.arm(None);
+                        // Keep typing / trackball rolls with the screen off away from the
+                        // desktop; a profile that leaves it on or dims it needs them
+                        let grab = mode == PowerMode::Saving
+                            && cfg.wake.grab
+                            && ctx.saving.lock().unwrap().display == DisplayAction::Off;
+                        if grab != wake_grabbed {
+                            for (d, _) in devices
+                                .iter()
+                                .flatten()
+                                .filter(|(_, r)| *r == DeviceRole::InternalKb)
+                            {
+                                if let Err(e) = d.grab(grab) {
+                                    warn!("Failed to (un)grab {}: {}", d.path.display(), e);
+                                }
+                            }
+                            wake_grabbed = grab;
+                        }
+                        continue;
+                    }
+                    let index = (ev.data() - TOKEN_DEVICE_BASE) as usize;
+                    let Some((device, role)) = devices.get_mut(index).and_then(Option::as_mut)
+                    else {
+                        continue;
+                    };
+                    let role = *role;
+                    let hung_up = ev
+                        .events()
+                        .intersects(EpollFlags::EPOLLHUP | EpollFlags::EPOLLERR);
+                    if !ev.events().contains(EpollFlags::EPOLLIN) && !hung_up {
+                        continue;
+                    }
+                    let events = match device.read_events() {
+                        Ok(events) => events,
+                        Err(e) if hung_up || matches!(e, Error::NotFound(_)) => {
+                            let (gone, _) = devices[index].take().unwrap();
+                            let _ = epoll.delete(&gone);
+                            // Whatever was held on it will never see its release
+                            key_state.clear_device(index);
+                            if role == DeviceRole::PowerKey {
+                                recognizer.cancel_press();
+                                warn!(
+                                    "Power key device {} disappeared ({}), waiting for it to come back",
+                                    gone.path.display(),
+                                    e
+                                );
+                                reconnect_backoff.reset();
+                                schedule_reconnect(&retry_timer, &mut reconnect_backoff);
+                            } else {
+                                warn!("Input device {} disappeared ({})", gone.path.display(), e);
+                            }
+                            continue;
+                        }
+                        Err(e) => {
+                            warn!("Error reading event: {}", e);
+                            sleep(Duration::from_millis(200));
+                            continue;
+                        }
+                    };
+                    // Kernel timestamps, so press durations don't depend on how
+                    // quickly the loop got around to reading the events
+                    let batch: Vec<_> = events
+                        .into_iter()
+                        .map(|e| (e, device.event_time(&e)))
+                        .collect();
+                    for (event, at) in batch {
+                        debug!(
+                            "event: dev={} t={} ms={} type={} code={} value={}",
+                            index, event.sec, event.usec, event.etype, event.code, event.value
+                        );
+
+                        if idle.on_event(event.etype, event.code, event.value, at)
+                            && dimmer.is_dimmed()
+                            && ctx.mode() == PowerMode::Normal
+                        {
+                            info!("Input during dim stage, staying in normal mode");
+                            let _ = dim_timer.arm(None);
+                            dimmer.restore();
+                        }
+                        if role == DeviceRole::Activity {
+                            continue;
+                        }
+                        if role == DeviceRole::PowerKey
+                            && resume_settle_until.is_some_and(|t| at < t)
+                        {
+                            debug!("Ignoring power key event right after resume");
+                            continue;
+                        }
+
+                        if role == DeviceRole::InternalKb
+                            && ctx.mode() == PowerMode::Saving
+                            && wake.on_event(event.etype, event.code, event.value, at)
+                        {
+                            info!("Input on internal keyboard/trackball: leaving saving mode");
+                            switch_power_mode(&ctx, Some(PowerMode::Normal));
+                            continue;
+                        }
+
+                        key_state.update(index, event.etype, event.code, event.value);
+                        // Opened only for wake-up: its keys don't take part in chords
+                        if role == DeviceRole::InternalKb && !cfg.watch_internal_kb {
+                            continue;
+                        }
+                        if let Some((chord, action)) =
+                            chords.on_event(&key_state, event.etype, event.code, event.value)
+                        {
+                            info!("Chord {}: running '{}'", chord, action);
+                            // The power key press was part of the chord, not a gesture
+                            if chord.contains(recognizer.key) {
+                                recognizer.cancel_press();
+                            }
+                            run_action(&action, &ctx);
+                            continue;
+                        }
+
+                        if role != DeviceRole::PowerKey {
+                            continue;
+                        }
+                        // Everything but the trigger key goes on to the desktop if asked to
+                        if cfg.uinput_passthrough
+                            && (event.etype == EV_SYN
+                                || (event.etype == EV_KEY && event.code != recognizer.key))
+                            && let Some(v) = &ctx.virtual_keys
+                            && let Err(e) = v.emit(event.etype, event.code, event.value)
+                        {
+                            debug!("Pass-through failed: {}", e);
+                        }
+                        // Only wait for multi-taps the current mode actually binds
+                        let mode = ctx.mode();
+                        recognizer.set_max_taps(bindings.max_taps(&mode));
+                        if let Some(gesture) =
+                            recognizer.on_event(event.etype, event.code, event.value, at)
+                        {
+                            dispatch_gesture(gesture, &bindings, &hold_feedback, &ctx);
+                        }
+                    }
+                }
+                // Any of the handlers above may have noticed the charger come or go
+                if source_overrides && on_ac != saving_on_ac {
+                    saving_on_ac = on_ac;
+                    info!(
+                        "Charger {}, using the {} saving settings",
+                        if on_ac { "connected" } else { "disconnected" },
+                        if on_ac { "AC" } else { "battery" }
+                    );
+                    let next = if on_ac { &saving_ac } else { &saving_battery };
+                    switch_saving_config(&ctx, next.clone());
+                }
+                if let Some(gesture) = recognizer.poll(Instant::now()) {
+                    dispatch_gesture(gesture, &bindings, &hold_feedback, &ctx);
+                }
+                // Wake up again for the next hold threshold or tap window
+                if let Err(e) = timer.arm(recognizer.deadline()) {
+                    warn!("Failed to arm gesture timer: {}", e);
+                }
+                if let Err(e) = idle_timer.arm(idle.deadline(on_ac)) {
+                    warn!("Failed to arm idle timer: {}", e);
                 }
             }
             Err(e) => {
@@ -391,90 +1689,4 @@ mod tests {
         let resolved = resolve_log_level(None, 0, Some(Level::Warn));
         assert_eq!(resolved, Some(LevelFilter::Warn));
     }
-
-    // ---- classify_key_event: short/long press detection ----
-
-    const HOLD: Duration = Duration::from_millis(700);
-
-    #[test]
-    fn classify_non_power_key_is_ignored() {
-        let now = Instant::now();
-        // A keyboard event (e.g. 'A' key) must not be treated as a power key.
-        let r = classify_key_event(None, EV_KEY, 30, 1, HOLD, now);
-        assert_eq!(r.decision, KeyDecision::Continue);
-        assert_eq!(r.last_key_down, None);
-    }
-
-    #[test]
-    fn classify_non_ev_key_type_is_ignored() {
-        let now = Instant::now();
-        // Synchronization events (type 0) on the power device must be ignored.
-        let r = classify_key_event(None, 0, KEY_POWER, 0, HOLD, now);
-        assert_eq!(r.decision, KeyDecision::Continue);
-        assert_eq!(r.last_key_down, None);
-    }
-
-    #[test]
-    fn classify_key_down_records_timestamp() {
-        let now = Instant::now();
-        let r = classify_key_event(None, EV_KEY, KEY_POWER, 1, HOLD, now);
-        assert_eq!(r.decision, KeyDecision::Continue);
-        assert_eq!(r.last_key_down, Some(now));
-    }
-
-    #[test]
-    fn classify_short_press_when_elapsed_below_threshold() {
-        // key-down happened 100ms ago; threshold is 700ms -> short press.
-        let now = Instant::now();
-        let down = now - Duration::from_millis(100);
-        let r = classify_key_event(Some(down), EV_KEY, KEY_POWER, 0, HOLD, now);
-        assert_eq!(r.decision, KeyDecision::ShortPress);
-        assert_eq!(r.last_key_down, None);
-    }
-
-    #[test]
-    fn classify_long_press_when_elapsed_at_or_above_threshold() {
-        // key-down happened 800ms ago; threshold is 700ms -> long press.
-        let now = Instant::now();
-        let down = now - Duration::from_millis(800);
-        let r = classify_key_event(Some(down), EV_KEY, KEY_POWER, 0, HOLD, now);
-        assert_eq!(r.decision, KeyDecision::LongPress);
-        assert_eq!(r.last_key_down, None);
-    }
-
-    #[test]
-    fn classify_key_up_without_prior_down_is_ignored() {
-        // An orphan key-up (no preceding key-down) must NOT toggle power mode.
-        let now = Instant::now();
-        let r = classify_key_event(None, EV_KEY, KEY_POWER, 0, HOLD, now);
-        assert_eq!(r.decision, KeyDecision::Continue);
-        assert_eq!(r.last_key_down, None);
-    }
-
-    #[test]
-    fn classify_auto_repeat_value_does_not_toggle() {
-        // The kernel reports value 2 for auto-repeat; it must be a no-op.
-        let now = Instant::now();
-        let down = now - Duration::from_millis(100);
-        let r = classify_key_event(Some(down), EV_KEY, KEY_POWER, 2, HOLD, now);
-        assert_eq!(r.decision, KeyDecision::Continue);
-        // auto-repeat must not clear the recorded key-down time
-        assert_eq!(r.last_key_down, Some(down));
-    }
-
-    #[test]
-    fn classify_boundary_just_below_and_at_threshold() {
-        // elapsed == 699ms -> short, elapsed == 700ms -> long (decision is `<`, not `<=`)
-        let now = Instant::now();
-        let short_down = now - Duration::from_millis(699);
-        assert_eq!(
-            classify_key_event(Some(short_down), EV_KEY, KEY_POWER, 0, HOLD, now).decision,
-            KeyDecision::ShortPress
-        );
-        let boundary_down = now - HOLD;
-        assert_eq!(
-            classify_key_event(Some(boundary_down), EV_KEY, KEY_POWER, 0, HOLD, now).decision,
-            KeyDecision::LongPress
-        );
-    }
 }
@@ -1,57 +1,180 @@
 //! Power mode helper - combines display toggling with CPU frequency changes
 
+use crate::CpuFreqConfig;
+use crate::hardware::cpu::CpuPolicies;
+use crate::hardware::devfreq::DevfreqConfig;
+use crate::hardware::rf::{RfConfig, RfType};
 use crate::hardware::{backlight, drm_panel, framebuffer};
-use crate::{BTConfig, CpuFreqConfig, WifiConfig};
+use crate::profile::DisplayAction;
+use crate::restore::Restored;
 use log::{debug, info, warn};
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
-fn set_display_on(dry_run: bool) -> Result<(), String> {
-    let backlight_path = match backlight::find_backlight() {
-        Ok(Some(p)) => p,
-        Ok(None) => return Err("backlight not found".to_string()),
-        Err(e) => return Err(format!("failed to find backlight: {}", e)),
-    };
+/// Sysfs nodes that make up the display
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DisplayPaths {
+    pub backlight: Option<PathBuf>,
+    pub framebuffer: Option<PathBuf>,
+    pub drm: Option<PathBuf>,
+}
+
+impl DisplayPaths {
+    pub fn find() -> Self {
+        DisplayPaths {
+            backlight: backlight::find_backlight().ok().flatten(),
+            framebuffer: framebuffer::find_framebuffer().ok().flatten(),
+            drm: drm_panel::find_drm_panel().ok().flatten(),
+        }
+    }
+}
 
-    let framebuffer_path = framebuffer::find_framebuffer().ok().flatten();
-    let drm_path = drm_panel::find_drm_panel().ok().flatten();
+/// Display state recorded when the display is switched off, written back when it
+/// is switched on again. `None` fields couldn't be read and fall back to "on".
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DisplayState {
+    pub brightness: Option<u32>,
+    pub bl_power: Option<String>,
+    pub fb_blank: Option<String>,
+    /// `connected`, `disconnected` or `unknown` as read from the connector
+    pub drm_status: Option<String>,
+    /// The backlight was faded to black before switching off: it comes back on
+    /// at 0 and the fade in brings it up to `brightness`
+    pub faded_out: bool,
+}
 
-    info!("Turning display ON");
-    if !dry_run {
-        if let Some(fb) = framebuffer_path {
-            let _ = fs::write(fb.join("blank"), "0");
+fn read_trimmed(path: &Path) -> Option<String> {
+    let s = fs::read_to_string(path).ok()?;
+    let s = s.trim();
+    (!s.is_empty()).then(|| s.to_string())
+}
+
+impl DisplayState {
+    pub fn capture(paths: &DisplayPaths) -> Self {
+        let bl = paths.backlight.as_deref();
+        DisplayState {
+            brightness: bl.and_then(|p| backlight::get_brightness(p).ok()),
+            bl_power: bl.and_then(|p| read_trimmed(&p.join("bl_power"))),
+            // many kernels don't report the blank level; those were unblanked
+            fb_blank: paths
+                .framebuffer
+                .as_deref()
+                .and_then(|p| read_trimmed(&p.join("blank"))),
+            drm_status: paths
+                .drm
+                .as_deref()
+                .and_then(|p| read_trimmed(&p.join("status"))),
+            faded_out: false,
         }
-        let _ = fs::write(backlight_path.join("bl_power"), "0");
-        if let Some(drm) = drm_path {
-            let _ = fs::write(drm.join("status"), "detect");
+    }
+
+    /// Value to write to the connector's `status` to get back to this state. A
+    /// connector that was forced off stays off, anything else returns to detection.
+    fn drm_command(&self) -> &'static str {
+        match self.drm_status.as_deref() {
+            Some("disconnected") => "off",
+            _ => "detect",
         }
-    } else {
-        debug!("DRY-RUN: display ON skipped");
     }
-    Ok(())
 }
 
-fn set_display_off(dry_run: bool) -> Result<(), String> {
-    let backlight_path = match backlight::find_backlight() {
-        Ok(Some(p)) => p,
-        Ok(None) => return Err("backlight not found".to_string()),
-        Err(e) => return Err(format!("failed to find backlight: {}", e)),
+/// Record the display state, unless the caller did so already (e.g. before a
+/// fade-out), then switch it off
+fn display_off(
+    paths: &DisplayPaths,
+    captured: Option<DisplayState>,
+    dry_run: bool,
+) -> Result<DisplayState, String> {
+    let Some(backlight_path) = &paths.backlight else {
+        return Err("backlight not found".to_string());
     };
-
-    let framebuffer_path = framebuffer::find_framebuffer().ok().flatten();
-    let drm_path = drm_panel::find_drm_panel().ok().flatten();
+    let state = captured.unwrap_or_else(|| DisplayState::capture(paths));
+    debug!("Display state before saving mode: {:?}", state);
 
     info!("Turning display OFF");
     if !dry_run {
-        if let Some(drm) = drm_path {
+        if let Some(drm) = &paths.drm {
             let _ = fs::write(drm.join("status"), "off");
         }
-        if let Some(fb) = framebuffer_path {
+        if let Some(fb) = &paths.framebuffer {
             let _ = fs::write(fb.join("blank"), "1");
         }
         let _ = fs::write(backlight_path.join("bl_power"), "4");
     } else {
         debug!("DRY-RUN: display OFF skipped");
     }
+    Ok(state)
+}
+
+/// Switch the display back to `saved`, or fully on if nothing was recorded
+pub fn display_on(
+    paths: &DisplayPaths,
+    saved: Option<&DisplayState>,
+    dry_run: bool,
+) -> Result<Restored, String> {
+    let Some(backlight_path) = &paths.backlight else {
+        return Err("backlight not found".to_string());
+    };
+    let saved = saved.cloned().unwrap_or_default();
+
+    info!("Turning display ON");
+    if !dry_run {
+        let level = if saved.faded_out {
+            Some(0)
+        } else {
+            saved.brightness
+        };
+        if let Some(b) = level
+            && let Err(e) = backlight::set_brightness(backlight_path, b)
+        {
+            warn!("Failed to restore brightness {}: {}", b, e);
+        }
+        if let Some(fb) = &paths.framebuffer {
+            let _ = fs::write(fb.join("blank"), saved.fb_blank.as_deref().unwrap_or("0"));
+        }
+        let _ = fs::write(
+            backlight_path.join("bl_power"),
+            saved.bl_power.as_deref().unwrap_or("0"),
+        );
+        if let Some(drm) = &paths.drm {
+            let _ = fs::write(drm.join("status"), saved.drm_command());
+        }
+    } else {
+        debug!("DRY-RUN: display ON skipped");
+    }
+    let mut detail = Vec::new();
+    if let Some(b) = saved.brightness {
+        detail.push(format!("brightness {}", b));
+    }
+    detail.push(format!(
+        "bl_power {}",
+        saved.bl_power.as_deref().unwrap_or("0")
+    ));
+    if paths.framebuffer.is_some() {
+        detail.push(format!(
+            "blank {}",
+            saved.fb_blank.as_deref().unwrap_or("0")
+        ));
+    }
+    if paths.drm.is_some() {
+        detail.push(format!("status {}", saved.drm_command()));
+    }
+    Ok(Restored::new("display", detail.join(", ")))
+}
+
+/// State recorded by the last `set_display_off`, consumed by `set_display_on`
+static SAVED_DISPLAY: Mutex<Option<DisplayState>> = Mutex::new(None);
+
+fn set_display_on(dry_run: bool) -> Result<Restored, String> {
+    let saved = SAVED_DISPLAY.lock().unwrap().take();
+    display_on(&DisplayPaths::find(), saved.as_ref(), dry_run)
+}
+
+fn set_display_off(captured: Option<DisplayState>, dry_run: bool) -> Result<(), String> {
+    let state = display_off(&DisplayPaths::find(), captured, dry_run)?;
+    // switching off twice must not record the already-off state
+    SAVED_DISPLAY.lock().unwrap().get_or_insert(state);
     Ok(())
 }
 
@@ -70,123 +193,219 @@ fn toggle_display(dry_run: bool) -> Result<(), String> {
 
     if bl_state_trim == "4" {
         // Currently reports ON -> ensure it's ON
-        set_display_on(dry_run)
+        set_display_on(dry_run).map(|_| ())
     } else {
         // Currently reports OFF -> ensure it's OFF
-        set_display_off(dry_run)
+        set_display_off(None, dry_run)
+    }
+}
+
+/// What a saving mode does: the built-in one (whose settings can differ between AC
+/// and battery) or a named profile.
+#[derive(Clone, Debug)]
+pub struct SavingConfig {
+    /// Profile name, `saving` for the built-in one
+    pub name: String,
+    pub cpu: CpuPolicies,
+    pub devfreq: DevfreqConfig,
+    /// One entry per radio type; only enabled ones are blocked
+    pub rf: Vec<RfConfig>,
+    /// Only `Off` is handled here; dimming is up to the caller
+    pub display: DisplayAction,
+}
+
+impl SavingConfig {
+    /// Apply this saving mode. `display` is the display state recorded before
+    /// anything touched it, e.g. ahead of a fade-out; it is captured here if `None`.
+    pub fn enter(&self, display: Option<DisplayState>, dry_run: bool) {
+        info!("Entering power-saving mode ({})", self.name);
+        if self.display == DisplayAction::Off
+            && let Err(e) = set_display_off(display, dry_run)
+        {
+            warn!("set_display_off failed: {}", e);
+        }
+        self.cpu.apply_saving_mode(dry_run);
+        self.devfreq.apply_saving_mode(dry_run);
+        for r in &self.rf {
+            r.block(dry_run);
+        }
+    }
+
+    /// Put everything back the way [`SavingConfig::enter`] found it
+    pub fn exit(&self, dry_run: bool) -> Vec<Restored> {
+        info!("Exiting power-saving mode ({})", self.name);
+        let mut restored = self.cpu.apply_normal_mode(dry_run);
+        restored.extend(self.devfreq.apply_normal_mode(dry_run));
+        if self.display == DisplayAction::Off {
+            match set_display_on(dry_run) {
+                Ok(r) => restored.push(r),
+                Err(e) => warn!("set_display_on failed: {}", e),
+            }
+        }
+        for r in &self.rf {
+            restored.extend(r.restore(dry_run));
+        }
+        report(&restored);
+        restored
+    }
+
+    /// The RF config of one radio type
+    pub fn rf(&self, kind: RfType) -> Option<&RfConfig> {
+        self.rf.iter().find(|r| r.kind == kind)
+    }
+
+    /// Move an active saving mode over to `next`: undo what only `self` does, then
+    /// apply `next`. Radios blocked by both stay blocked. `display` is as for
+    /// [`SavingConfig::enter`].
+    pub fn switch_to(&self, next: &SavingConfig, display: Option<DisplayState>, dry_run: bool) {
+        info!("Switching power-saving mode {} -> {}", self.name, next.name);
+        let (was_off, now_off) = (
+            self.display == DisplayAction::Off,
+            next.display == DisplayAction::Off,
+        );
+        if !was_off
+            && now_off
+            && let Err(e) = set_display_off(display, dry_run)
+        {
+            warn!("set_display_off failed: {}", e);
+        }
+        // back to the entry snapshot first, so `next` records the same one
+        let mut restored = self.cpu.apply_normal_mode(dry_run);
+        next.cpu.apply_saving_mode(dry_run);
+        restored.extend(self.devfreq.apply_normal_mode(dry_run));
+        next.devfreq.apply_saving_mode(dry_run);
+        for r in &self.rf {
+            match next
+                .rf(r.kind)
+                .filter(|n| n.enabled && n.select == r.select)
+            {
+                Some(n) => r.hand_over(n),
+                None => restored.extend(r.restore(dry_run)),
+            }
+        }
+        for r in &next.rf {
+            r.block(dry_run);
+        }
+        if was_off && !now_off {
+            match set_display_on(dry_run) {
+                Ok(r) => restored.push(r),
+                Err(e) => warn!("set_display_on failed: {}", e),
+            }
+        }
+        report(&restored);
+    }
+}
+
+fn report(restored: &[Restored]) {
+    for r in restored {
+        info!("Restored {}", r);
     }
 }
 
 /// Power saving mode state
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum PowerMode {
     Normal,
     Saving,
 }
 
-pub fn enter_saving_mode(
-    cpu_config: &CpuFreqConfig,
-    dry_run: bool,
-    wifi: Option<&WifiConfig>,
-    bt: Option<&BTConfig>,
-) {
-    info!("Entering power-saving mode");
-    if let Err(e) = set_display_off(dry_run) {
-        warn!("set_display_off failed: {}", e);
-    }
-    cpu_config.apply_saving_mode(dry_run);
-    if let Some(w) = wifi {
-        w.block(dry_run);
-    }
-    if let Some(b) = bt {
-        b.block(dry_run);
-    }
+/// The built-in saving mode over a single policy, switching the display off; see
+/// [`SavingConfig::enter`]
+pub fn enter_saving_mode(cpu_config: &CpuFreqConfig, dry_run: bool, rf: &[RfConfig]) {
+    legacy_saving(cpu_config, rf).enter(None, dry_run);
 }
 
-/// Exit power-saving mode: restore CPU then turn display on
+/// Undo [`enter_saving_mode`] called with the same configs; see [`SavingConfig::exit`]
 pub fn exit_saving_mode(
     cpu_config: &CpuFreqConfig,
     dry_run: bool,
-    wifi: Option<&WifiConfig>,
-    bt: Option<&BTConfig>,
-) {
-    info!("Exiting power-saving mode");
-    cpu_config.apply_normal_mode(dry_run);
-    if let Err(e) = set_display_on(dry_run) {
-        warn!("set_display_on failed: {}", e);
-    }
-    if let Some(w) = wifi {
-        w.unblock(dry_run);
-    }
-    if let Some(b) = bt {
-        b.unblock(dry_run);
+    rf: &[RfConfig],
+) -> Vec<Restored> {
+    legacy_saving(cpu_config, rf).exit(dry_run)
+}
+
+/// Clones share what entering recorded, so exiting through a fresh config restores it
+fn legacy_saving(cpu_config: &CpuFreqConfig, rf: &[RfConfig]) -> SavingConfig {
+    SavingConfig {
+        name: "saving".to_string(),
+        cpu: CpuPolicies::single(cpu_config.clone()),
+        devfreq: DevfreqConfig::default(),
+        rf: rf.to_vec(),
+        display: DisplayAction::Off,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::env;
+    use crate::hardware::rf::RfSelector;
+    use crate::test_util::TempDir;
     use std::fs;
 
+    /// Built-in style saving config over one policy; the display is left alone so
+    /// the tests don't depend on real sysfs display paths
+    fn saving(cpu: CpuFreqConfig, rf: &[RfConfig]) -> SavingConfig {
+        SavingConfig {
+            name: "saving".to_string(),
+            display: DisplayAction::On,
+            cpu: CpuPolicies::single(cpu),
+            devfreq: DevfreqConfig::default(),
+            rf: rf.to_vec(),
+        }
+    }
+
     #[test]
     fn test_enter_exit_saving_mode_dryrun() {
-        let tmp = env::temp_dir().join(format!(
-            "uconsole_pm_test_{}",
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis()
-        ));
-        let _ = fs::create_dir_all(&tmp);
-        let cpu = CpuFreqConfig::with_policy_path(tmp.clone(), Some(String::from("100,200")));
+        let tmp = TempDir::new("pm_test");
+        let cfg = saving(
+            CpuFreqConfig::with_policy_path(tmp.to_path_buf(), Some(String::from("100,200"))),
+            &[],
+        );
         // Dry run should not create policy files
-        enter_saving_mode(&cpu, true, None, None);
+        cfg.enter(None, true);
         assert!(!tmp.join("scaling_min_freq").exists());
         assert!(!tmp.join("scaling_max_freq").exists());
 
         // Non-dry-run should write
-        enter_saving_mode(&cpu, false, None, None);
+        cfg.enter(None, false);
         assert!(tmp.join("scaling_min_freq").exists());
         assert!(tmp.join("scaling_max_freq").exists());
 
         // exit - verify it doesn't panic
-        exit_saving_mode(&cpu, false, None, None);
-    }
-
-    /// Unique temp dir helper scoped to a test by name, so tests don't collide.
-    fn tmp_dir(name: &str) -> std::path::PathBuf {
-        let ms = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis();
-        let dir = env::temp_dir().join(format!("uconsole_{name}_{ms}"));
-        let _ = fs::create_dir_all(&dir);
-        dir
+        cfg.exit(false);
     }
 
-    /// Drive `enter_saving_mode` / `exit_saving_mode` against cpu + wifi + bt backed by
+    /// Drive `SavingConfig::enter` / `SavingConfig::exit` against cpu + wifi + bt backed by
     /// temp directories and assert each subsystem's final on-disk state. This verifies
     /// the orchestration without depending on real sysfs display paths.
     #[test]
     fn test_enter_exit_full_state_cpu_wifi_bt() {
-        // CPU policy dir: seed the *default* values so exit_saving_mode can restore them.
-        let cpu_dir = tmp_dir("pm_cpu");
+        // CPU policy dir: seed the *default* values so exit can restore them.
+        let cpu_dir = TempDir::new("pm_cpu");
         fs::write(cpu_dir.join("scaling_min_freq"), "600000\n").unwrap();
         fs::write(cpu_dir.join("scaling_max_freq"), "1800000\n").unwrap();
-        let cpu = CpuFreqConfig::with_policy_path(cpu_dir.clone(), Some(String::from("100,600")));
+        let cpu =
+            CpuFreqConfig::with_policy_path(cpu_dir.to_path_buf(), Some(String::from("100,600")));
 
         // WiFi and BT rfkill dirs backed by a writable "state" file.
-        let wifi_dir = tmp_dir("pm_wifi");
-        let bt_dir = tmp_dir("pm_bt");
+        let wifi_dir = TempDir::new("pm_wifi");
+        let bt_dir = TempDir::new("pm_bt");
         // default (unblocked) state
         fs::write(wifi_dir.join("state"), "1").unwrap();
         fs::write(bt_dir.join("state"), "1").unwrap();
-        let wifi = WifiConfig::new(true, Some(wifi_dir.clone()));
-        let bt = BTConfig::new(true, Some(bt_dir.clone()));
+        let rf = [
+            RfConfig::new(RfType::Wlan, true, RfSelector::Path(wifi_dir.to_path_buf())),
+            RfConfig::new(
+                RfType::Bluetooth,
+                true,
+                RfSelector::Path(bt_dir.to_path_buf()),
+            ),
+        ];
+
+        let cfg = saving(cpu, &rf);
 
         // --- enter saving mode ---
-        enter_saving_mode(&cpu, false, Some(&wifi), Some(&bt));
+        cfg.enter(None, false);
 
         // CPU clamped to saving range
         assert_eq!(
@@ -202,7 +421,7 @@ mod tests {
         assert_eq!(fs::read_to_string(bt_dir.join("state")).unwrap(), "0");
 
         // --- exit saving mode ---
-        exit_saving_mode(&cpu, false, Some(&wifi), Some(&bt));
+        cfg.exit(false);
 
         // CPU restored to the defaults seeded above
         assert_eq!(
@@ -226,20 +445,24 @@ mod tests {
     /// transitions (the missing rfkill only produces a warning at runtime).
     #[test]
     fn test_enter_exit_with_disabled_rf() {
-        let cpu_dir = tmp_dir("pm_norf");
+        let cpu_dir = TempDir::new("pm_norf");
         fs::write(cpu_dir.join("scaling_min_freq"), "400000\n").unwrap();
         fs::write(cpu_dir.join("scaling_max_freq"), "1400000\n").unwrap();
-        let cpu = CpuFreqConfig::with_policy_path(cpu_dir.clone(), Some(String::from("100,400")));
-        // rfkill disabled: no path
-        let wifi = WifiConfig::new(false, None);
-        let bt = BTConfig::new(false, None);
+        let cpu =
+            CpuFreqConfig::with_policy_path(cpu_dir.to_path_buf(), Some(String::from("100,400")));
+        // rfkill disabled: nothing to look up
+        let rf = [
+            RfConfig::new(RfType::Wlan, false, RfSelector::All),
+            RfConfig::new(RfType::Bluetooth, false, RfSelector::All),
+        ];
 
-        enter_saving_mode(&cpu, false, Some(&wifi), Some(&bt));
+        let cfg = saving(cpu, &rf);
+        cfg.enter(None, false);
         assert_eq!(
             fs::read_to_string(cpu_dir.join("scaling_max_freq")).unwrap(),
             "400000"
         );
-        exit_saving_mode(&cpu, false, Some(&wifi), Some(&bt));
+        cfg.exit(false);
         assert_eq!(
             fs::read_to_string(cpu_dir.join("scaling_max_freq"))
                 .unwrap()
@@ -248,19 +471,236 @@ mod tests {
         );
     }
 
+    fn display_fixture(name: &str) -> (TempDir, DisplayPaths) {
+        let root = TempDir::new(name);
+        let paths = DisplayPaths {
+            backlight: Some(root.join("backlight")),
+            framebuffer: Some(root.join("fb0")),
+            drm: Some(root.join("card0-DSI-1")),
+        };
+        for dir in [&paths.backlight, &paths.framebuffer, &paths.drm] {
+            fs::create_dir_all(dir.as_ref().unwrap()).unwrap();
+        }
+        (root, paths)
+    }
+
+    fn read(dir: &Option<std::path::PathBuf>, file: &str) -> String {
+        fs::read_to_string(dir.as_ref().unwrap().join(file))
+            .unwrap()
+            .trim()
+            .to_string()
+    }
+
+    /// A user-dimmed panel comes back at exactly the brightness and power level
+    /// it had before, not at some default.
+    #[test]
+    fn test_display_state_restored_exactly() {
+        let (_root, paths) = display_fixture("pm_display");
+        let bl = paths.backlight.as_ref().unwrap();
+        fs::write(bl.join("brightness"), "10\n").unwrap();
+        fs::write(bl.join("bl_power"), "1\n").unwrap();
+        fs::write(paths.framebuffer.as_ref().unwrap().join("blank"), "").unwrap();
+        fs::write(paths.drm.as_ref().unwrap().join("status"), "connected\n").unwrap();
+
+        let saved = display_off(&paths, None, false).unwrap();
+        assert_eq!(saved.brightness, Some(10));
+        assert_eq!(saved.bl_power.as_deref(), Some("1"));
+        assert_eq!(saved.fb_blank, None);
+        assert_eq!(read(&paths.backlight, "bl_power"), "4");
+        assert_eq!(read(&paths.framebuffer, "blank"), "1");
+        assert_eq!(read(&paths.drm, "status"), "off");
+
+        // something else touched the brightness while the display was off
+        fs::write(bl.join("brightness"), "255").unwrap();
+        display_on(&paths, Some(&saved), false).unwrap();
+        assert_eq!(read(&paths.backlight, "brightness"), "10");
+        assert_eq!(read(&paths.backlight, "bl_power"), "1");
+        assert_eq!(read(&paths.framebuffer, "blank"), "0");
+        assert_eq!(read(&paths.drm, "status"), "detect");
+
+        // a connector that was forced off stays off; without a record it's all on
+        let off = DisplayState {
+            drm_status: Some("disconnected".to_string()),
+            ..Default::default()
+        };
+        display_on(&paths, Some(&off), false).unwrap();
+        assert_eq!(read(&paths.drm, "status"), "off");
+        display_on(&paths, None, false).unwrap();
+        assert_eq!(read(&paths.backlight, "bl_power"), "0");
+        assert_eq!(read(&paths.backlight, "brightness"), "10");
+
+        // recorded before a fade-out took the brightness down to 0
+        let before_fade = DisplayState::capture(&paths);
+        fs::write(bl.join("brightness"), "0").unwrap();
+        let saved = display_off(&paths, Some(before_fade.clone()), false).unwrap();
+        assert_eq!(saved, before_fade);
+        display_on(&paths, Some(&saved), false).unwrap();
+        assert_eq!(read(&paths.backlight, "brightness"), "10");
+
+        // a fade in follows: the panel comes back dark instead of flashing
+        let faded = DisplayState {
+            faded_out: true,
+            ..saved
+        };
+        display_on(&paths, Some(&faded), false).unwrap();
+        assert_eq!(read(&paths.backlight, "brightness"), "0");
+        assert_eq!(read(&paths.backlight, "bl_power"), "0");
+    }
+
+    /// Plugging in the charger while saving lifts the clamp and turns WiFi back on
+    /// when the AC settings ask for that; BT stays blocked under both.
+    #[test]
+    fn test_switch_saving_config() {
+        let cpu_dir = TempDir::new("pm_switch_cpu");
+        fs::write(cpu_dir.join("scaling_min_freq"), "600000\n").unwrap();
+        fs::write(cpu_dir.join("scaling_max_freq"), "1800000\n").unwrap();
+        let wifi_dir = TempDir::new("pm_switch_wifi");
+        let bt_dir = TempDir::new("pm_switch_bt");
+        fs::write(wifi_dir.join("state"), "1").unwrap();
+        fs::write(bt_dir.join("state"), "1").unwrap();
+
+        let battery = SavingConfig {
+            name: "battery".to_string(),
+            display: DisplayAction::Off,
+            cpu: CpuPolicies::single(CpuFreqConfig::with_policy_path(
+                cpu_dir.to_path_buf(),
+                Some("100,600".to_string()),
+            )),
+            devfreq: DevfreqConfig::default(),
+            rf: vec![
+                RfConfig::new(RfType::Wlan, true, RfSelector::Path(wifi_dir.to_path_buf())),
+                RfConfig::new(
+                    RfType::Bluetooth,
+                    true,
+                    RfSelector::Path(bt_dir.to_path_buf()),
+                ),
+            ],
+        };
+        let ac = SavingConfig {
+            name: "ac".to_string(),
+            display: DisplayAction::Off,
+            cpu: CpuPolicies::single(CpuFreqConfig::with_policy_path(cpu_dir.to_path_buf(), None)),
+            devfreq: DevfreqConfig::default(),
+            rf: vec![
+                RfConfig::new(
+                    RfType::Wlan,
+                    false,
+                    RfSelector::Path(wifi_dir.to_path_buf()),
+                ),
+                battery.rf(RfType::Bluetooth).unwrap().clone(),
+            ],
+        };
+        battery.enter(None, false);
+        assert_eq!(fs::read_to_string(wifi_dir.join("state")).unwrap(), "0");
+
+        battery.switch_to(&ac, None, false);
+        let max = fs::read_to_string(cpu_dir.join("scaling_max_freq")).unwrap();
+        assert_eq!(max.trim(), "1800000");
+        assert_eq!(fs::read_to_string(wifi_dir.join("state")).unwrap(), "1");
+        assert_eq!(fs::read_to_string(bt_dir.join("state")).unwrap(), "0");
+
+        ac.switch_to(&battery, None, false);
+        let max = fs::read_to_string(cpu_dir.join("scaling_max_freq")).unwrap();
+        assert_eq!(max.trim(), "600000");
+        assert_eq!(fs::read_to_string(wifi_dir.join("state")).unwrap(), "0");
+    }
+
+    /// Exit puts back what was there on entry, not what was there at startup: a
+    /// radio already off stays off, CPU limits changed since startup come back.
+    /// The snapshot survives a profile switch that keeps the radio blocked.
+    #[test]
+    fn test_exit_restores_entry_snapshot() {
+        let cpu_dir = TempDir::new("pm_snap_cpu");
+        fs::write(cpu_dir.join("scaling_min_freq"), "600000\n").unwrap();
+        fs::write(cpu_dir.join("scaling_max_freq"), "1800000\n").unwrap();
+        let wifi_dir = TempDir::new("pm_snap_wifi");
+        let bt_dir = TempDir::new("pm_snap_bt");
+        fs::write(wifi_dir.join("state"), "1").unwrap();
+        fs::write(bt_dir.join("state"), "1").unwrap();
+        let deep = SavingConfig {
+            name: "deep".to_string(),
+            display: DisplayAction::On,
+            cpu: CpuPolicies::single(CpuFreqConfig::with_policy_path(
+                cpu_dir.to_path_buf(),
+                Some("100,600".to_string()),
+            )),
+            devfreq: DevfreqConfig::default(),
+            rf: vec![
+                RfConfig::new(RfType::Wlan, true, RfSelector::Path(wifi_dir.to_path_buf())),
+                RfConfig::new(
+                    RfType::Bluetooth,
+                    true,
+                    RfSelector::Path(bt_dir.to_path_buf()),
+                ),
+            ],
+        };
+        let light = SavingConfig {
+            name: "light".to_string(),
+            display: DisplayAction::On,
+            cpu: CpuPolicies::single(CpuFreqConfig::with_policy_path(cpu_dir.to_path_buf(), None)),
+            devfreq: DevfreqConfig::default(),
+            rf: vec![RfConfig::new(
+                RfType::Wlan,
+                true,
+                RfSelector::Path(wifi_dir.to_path_buf()),
+            )],
+        };
+
+        // after startup: the user lowered the max and switched Wi-Fi off
+        fs::write(cpu_dir.join("scaling_max_freq"), "1500000\n").unwrap();
+        fs::write(wifi_dir.join("state"), "0").unwrap();
+        deep.enter(None, false);
+        assert_eq!(fs::read_to_string(bt_dir.join("state")).unwrap(), "0");
+
+        // BT is put back on the switch, Wi-Fi stays blocked under `light`
+        deep.switch_to(&light, None, false);
+        assert_eq!(fs::read_to_string(bt_dir.join("state")).unwrap(), "1");
+        let restored = light.exit(false);
+        assert_eq!(
+            fs::read_to_string(cpu_dir.join("scaling_max_freq"))
+                .unwrap()
+                .trim(),
+            "1500000"
+        );
+        assert_eq!(fs::read_to_string(wifi_dir.join("state")).unwrap(), "0");
+        let wifi_entry = wifi_dir.file_name().unwrap().to_string_lossy();
+        assert_eq!(
+            restored,
+            vec![Restored::new(format!("WiFi {}", wifi_entry), "blocked")]
+        );
+
+        // entering again takes a fresh snapshot
+        fs::write(wifi_dir.join("state"), "1").unwrap();
+        deep.enter(None, false);
+        let restored = deep.exit(false);
+        assert_eq!(fs::read_to_string(wifi_dir.join("state")).unwrap(), "1");
+        assert_eq!(
+            restored[0],
+            Restored::new(cpu_dir.display().to_string(), "600000-1500000 kHz")
+        );
+        assert_eq!(restored.len(), 3);
+    }
+
     /// Dry-run must leave every subsystem untouched: no CPU writes, no rfkill writes.
     #[test]
     fn test_dry_run_writes_nothing() {
-        let cpu_dir = tmp_dir("pm_dry");
-        let cpu = CpuFreqConfig::with_policy_path(cpu_dir.clone(), Some(String::from("100,400")));
-        let wifi_dir = tmp_dir("pm_dry_wifi");
-        let bt_dir = tmp_dir("pm_dry_bt");
+        let cpu_dir = TempDir::new("pm_dry");
+        let cpu =
+            CpuFreqConfig::with_policy_path(cpu_dir.to_path_buf(), Some(String::from("100,400")));
+        let wifi_dir = TempDir::new("pm_dry_wifi");
+        let bt_dir = TempDir::new("pm_dry_bt");
         fs::write(wifi_dir.join("state"), "1").unwrap();
         fs::write(bt_dir.join("state"), "1").unwrap();
-        let wifi = WifiConfig::new(true, Some(wifi_dir.clone()));
-        let bt = BTConfig::new(true, Some(bt_dir.clone()));
+        let rf = [
+            RfConfig::new(RfType::Wlan, true, RfSelector::Path(wifi_dir.to_path_buf())),
+            RfConfig::new(
+                RfType::Bluetooth,
+                true,
+                RfSelector::Path(bt_dir.to_path_buf()),
+            ),
+        ];
 
-        enter_saving_mode(&cpu, true, Some(&wifi), Some(&bt));
+        saving(cpu, &rf).enter(None, true);
         assert!(!cpu_dir.join("scaling_min_freq").exists());
         assert_eq!(fs::read_to_string(wifi_dir.join("state")).unwrap(), "1");
         assert_eq!(fs::read_to_string(bt_dir.join("state")).unwrap(), "1");
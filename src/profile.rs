@@ -0,0 +1,360 @@
+//! Named power profiles: alternative saving modes with their own CPU range,
+//! governor, RF and display handling, e.g. `screen-off-audio` (display off, radios
+//! kept for streaming), `light` or `deep`.
+//!
+//! Profiles are defined with `PROFILE_<NAME>_<SETTING>` keys. `normal` (no saving)
+//! and `saving` (the built-in saving mode from `SAVING_CPU_FREQ`, `TOGGLE_WIFI`, ...)
+//! always exist.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::Error;
+use crate::hardware::cpu::parse_tunables;
+use crate::hardware::cpu_freq::FreqSpec;
+use crate::hardware::cpu_hotplug::OfflineCores;
+use crate::hardware::rf::RfType;
+
+pub const NORMAL_PROFILE: &str = "normal";
+pub const BUILTIN_PROFILE: &str = "saving";
+
+/// What a profile does with a radio
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RfAction {
+    /// Leave it as it is
+    #[default]
+    Keep,
+    Block,
+}
+
+impl FromStr for RfAction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "keep" | "on" => Ok(RfAction::Keep),
+            "block" | "off" => Ok(RfAction::Block),
+            other => Err(Error::InvalidValue(format!(
+                "unknown RF action '{}' (expected keep or block)",
+                other
+            ))),
+        }
+    }
+}
+
+/// What a profile does with the display
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DisplayAction {
+    #[default]
+    Off,
+    On,
+    /// Keep it on at the dim level (`DIM_PERCENT`)
+    Dim,
+}
+
+impl FromStr for DisplayAction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(DisplayAction::Off),
+            "on" | "keep" => Ok(DisplayAction::On),
+            "dim" => Ok(DisplayAction::Dim),
+            other => Err(Error::InvalidValue(format!(
+                "unknown display action '{}' (expected off, on or dim)",
+                other
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for DisplayAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DisplayAction::Off => write!(f, "off"),
+            DisplayAction::On => write!(f, "on"),
+            DisplayAction::Dim => write!(f, "dim"),
+        }
+    }
+}
+
+/// One profile's settings
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProfileSpec {
+    /// `min,max` in MHz like `SAVING_CPU_FREQ`; `None` leaves the CPU alone
+    pub cpu_freq: Option<String>,
+    pub governor: Option<String>,
+    /// `name=value` tunables of `governor`
+    pub governor_tunables: Vec<(String, String)>,
+    /// Ranges of single cpufreq policies, taking precedence over `cpu_freq`; `None`
+    /// leaves that policy alone
+    pub cpu_freq_policies: BTreeMap<u32, Option<String>>,
+    /// Cores to take offline
+    pub cpu_offline: Option<OfflineCores>,
+    /// devfreq `max_freq`; `None` leaves the devices alone
+    pub devfreq_max: Option<FreqSpec>,
+    pub devfreq_governor: Option<String>,
+    /// Radios by type; types not listed are kept
+    pub rf: BTreeMap<RfType, RfAction>,
+    pub display: DisplayAction,
+}
+
+impl ProfileSpec {
+    pub fn rf(&self, kind: RfType) -> RfAction {
+        self.rf.get(&kind).copied().unwrap_or_default()
+    }
+}
+
+/// All configured profiles plus the order the power key cycles through
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profiles {
+    pub specs: BTreeMap<String, ProfileSpec>,
+    /// From `PROFILE_CYCLE`; empty means [`Profiles::cycle_order`]'s default
+    pub cycle: Vec<String>,
+    /// Profile that `toggle`, `enter_saving` and the idle timeout use (`PROFILE_DEFAULT`)
+    pub default: Option<String>,
+}
+
+fn profile_name(s: &str) -> String {
+    s.trim().to_ascii_lowercase()
+}
+
+impl Profiles {
+    /// Apply a `PROFILE_*` key, given without the `PROFILE_` prefix
+    pub fn parse_key(&mut self, key: &str, val: &str) -> Result<(), Error> {
+        match key {
+            "CYCLE" => {
+                self.cycle = val
+                    .split(',')
+                    .map(profile_name)
+                    .filter(|n| !n.is_empty())
+                    .collect();
+                return Ok(());
+            }
+            "DEFAULT" => {
+                self.default = Some(profile_name(val));
+                return Ok(());
+            }
+            _ => {}
+        }
+        // `<NAME>_CPU_FREQ_POLICY<N>`
+        if let Some((name, n)) = key.rsplit_once("_CPU_FREQ_POLICY") {
+            let n = n.parse::<u32>().map_err(|_| {
+                Error::InvalidValue(format!("expected a policy number in '{}'", key))
+            })?;
+            let val = val.trim();
+            self.spec_mut(name)?.cpu_freq_policies.insert(
+                n,
+                (!val.is_empty() && !val.eq_ignore_ascii_case("none")).then(|| val.to_string()),
+            );
+            return Ok(());
+        }
+        // longer suffixes first: `_DEVFREQ_GOVERNOR` also ends in `_GOVERNOR`
+        let (name, setting) = [
+            "_DEVFREQ_MAX",
+            "_DEVFREQ_GOVERNOR",
+            "_CPU_OFFLINE",
+            "_CPU_ONLINE",
+            "_CPU_FREQ",
+            "_GOVERNOR",
+            "_GOVERNOR_TUNABLES",
+            "_WIFI",
+            "_WLAN",
+            "_BT",
+            "_BLUETOOTH",
+            "_WWAN",
+            "_GPS",
+            "_NFC",
+            "_DISPLAY",
+        ]
+        .iter()
+        .find_map(|suffix| Some((key.strip_suffix(suffix)?, &suffix[1..])))
+        .ok_or_else(|| Error::InvalidValue(format!("unknown profile setting in '{}'", key)))?;
+        let val = val.trim();
+        let spec = self.spec_mut(name)?;
+        match setting {
+            "CPU_FREQ" => {
+                spec.cpu_freq =
+                    (!val.is_empty() && !val.eq_ignore_ascii_case("none")).then(|| val.to_string())
+            }
+            "GOVERNOR" => spec.governor = (!val.is_empty()).then(|| val.to_string()),
+            "GOVERNOR_TUNABLES" => spec.governor_tunables = parse_tunables(val)?,
+            // both set `cpu_offline`; config lines are applied in file order, so the
+            // later one wins
+            "CPU_OFFLINE" => spec.cpu_offline = OfflineCores::parse_offline(val)?,
+            "CPU_ONLINE" => spec.cpu_offline = Some(OfflineCores::parse_online(val)?),
+            "DEVFREQ_MAX" => {
+                spec.devfreq_max = match val.to_ascii_lowercase().as_str() {
+                    "" | "none" => None,
+                    v => Some(v.parse()?),
+                }
+            }
+            "DEVFREQ_GOVERNOR" => {
+                spec.devfreq_governor = (!val.is_empty()).then(|| val.to_string())
+            }
+            "DISPLAY" => spec.display = val.parse()?,
+            radio => {
+                spec.rf.insert(radio.parse()?, val.parse()?);
+            }
+        }
+        Ok(())
+    }
+
+    /// The spec of profile `name`, created on first use
+    fn spec_mut(&mut self, name: &str) -> Result<&mut ProfileSpec, Error> {
+        let name = profile_name(name);
+        if name.is_empty() || name == NORMAL_PROFILE || name == BUILTIN_PROFILE {
+            return Err(Error::InvalidValue(format!(
+                "'{}' can't be used as a profile name",
+                name
+            )));
+        }
+        Ok(self.specs.entry(name).or_default())
+    }
+
+    /// Whether `name` is `normal`, `saving` or a configured profile
+    pub fn contains(&self, name: &str) -> bool {
+        name == NORMAL_PROFILE || name == BUILTIN_PROFILE || self.specs.contains_key(name)
+    }
+
+    /// Order the power key cycles through: `PROFILE_CYCLE`, or `normal` followed by
+    /// the configured profiles (just `saving` if there are none)
+    pub fn cycle_order(&self) -> Vec<String> {
+        if !self.cycle.is_empty() {
+            return self.cycle.clone();
+        }
+        let mut order = vec![NORMAL_PROFILE.to_string()];
+        if self.specs.is_empty() {
+            order.push(BUILTIN_PROFILE.to_string());
+        } else {
+            order.extend(self.specs.keys().cloned());
+        }
+        order
+    }
+
+    /// Profile after `current` in the cycle. A profile outside the cycle goes back
+    /// to the start.
+    pub fn next_in_cycle(&self, current: &str) -> String {
+        let order = self.cycle_order();
+        match order.iter().position(|n| n == current) {
+            Some(i) => order[(i + 1) % order.len()].clone(),
+            None => order[0].clone(),
+        }
+    }
+
+    /// Names in `PROFILE_CYCLE` / `PROFILE_DEFAULT` that don't exist
+    pub fn unknown_names(&self) -> Vec<String> {
+        self.cycle
+            .iter()
+            .chain(self.default.iter())
+            .filter(|n| !self.contains(n))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_profiles() {
+        let mut p = Profiles::default();
+        p.parse_key("SCREEN-OFF-AUDIO_CPU_FREQ", "300,800").unwrap();
+        p.parse_key("SCREEN-OFF-AUDIO_WIFI", "keep").unwrap();
+        p.parse_key("DEEP_CPU_FREQ", "100,600").unwrap();
+        p.parse_key("DEEP_GOVERNOR", "powersave").unwrap();
+        p.parse_key("LIGHT_GOVERNOR_TUNABLES", "up_threshold=90")
+            .unwrap();
+        p.parse_key("DEEP_WIFI", "block").unwrap();
+        p.parse_key("DEEP_BT", "off").unwrap();
+        p.parse_key("LIGHT_DISPLAY", "dim").unwrap();
+        p.parse_key("LIGHT_CPU_FREQ", "none").unwrap();
+
+        assert_eq!(
+            p.specs["deep"],
+            ProfileSpec {
+                cpu_freq: Some("100,600".to_string()),
+                governor: Some("powersave".to_string()),
+                governor_tunables: Vec::new(),
+                cpu_freq_policies: BTreeMap::new(),
+                cpu_offline: None,
+                devfreq_max: None,
+                devfreq_governor: None,
+                rf: BTreeMap::from([
+                    (RfType::Wlan, RfAction::Block),
+                    (RfType::Bluetooth, RfAction::Block),
+                ]),
+                display: DisplayAction::Off,
+            }
+        );
+        assert_eq!(p.specs["light"].display, DisplayAction::Dim);
+        assert_eq!(p.specs["light"].cpu_freq, None);
+        assert_eq!(
+            p.specs["light"].governor_tunables,
+            vec![("up_threshold".to_string(), "90".to_string())]
+        );
+        assert_eq!(p.specs["screen-off-audio"].rf(RfType::Wlan), RfAction::Keep);
+        p.parse_key("DEEP_WWAN", "block").unwrap();
+        assert_eq!(p.specs["deep"].rf(RfType::Wwan), RfAction::Block);
+        assert_eq!(p.specs["deep"].rf(RfType::Gps), RfAction::Keep);
+
+        assert!(p.parse_key("DEEP_COLOR", "blue").is_err());
+        assert!(p.parse_key("DEEP_DISPLAY", "blink").is_err());
+        assert!(p.parse_key("NORMAL_WIFI", "block").is_err());
+    }
+
+    #[test]
+    fn test_parse_profile_cores_and_devfreq() {
+        let mut p = Profiles::default();
+        p.parse_key("DEEP_CPU_OFFLINE", "2-3").unwrap();
+        p.parse_key("DEEP_CPU_FREQ_POLICY4", "300,1200").unwrap();
+        p.parse_key("DEEP_CPU_FREQ_POLICY6", "none").unwrap();
+        p.parse_key("DEEP_DEVFREQ_MAX", "250MHz").unwrap();
+        p.parse_key("DEEP_DEVFREQ_GOVERNOR", "powersave").unwrap();
+        p.parse_key("LIGHT_CPU_ONLINE", "2").unwrap();
+
+        let deep = &p.specs["deep"];
+        assert_eq!(deep.cpu_offline, Some(OfflineCores::List(vec![2, 3])));
+        assert_eq!(
+            deep.cpu_freq_policies,
+            BTreeMap::from([(4, Some("300,1200".to_string())), (6, None)])
+        );
+        assert_eq!(deep.devfreq_max, Some("250MHz".parse().unwrap()));
+        assert_eq!(deep.devfreq_governor.as_deref(), Some("powersave"));
+        assert_eq!(deep.governor, None);
+        assert_eq!(
+            p.specs["light"].cpu_offline,
+            Some(OfflineCores::KeepOnline(2))
+        );
+        assert!(!p.specs.contains_key("deep_devfreq"));
+
+        assert!(p.parse_key("LIGHT_CPU_ONLINE", "0").is_err());
+        assert!(p.parse_key("LIGHT_CPU_FREQ_POLICYX", "1,2").is_err());
+        assert!(p.parse_key("SAVING_CPU_OFFLINE", "1").is_err());
+    }
+
+    #[test]
+    fn test_cycle_order() {
+        let mut p = Profiles::default();
+        assert_eq!(p.cycle_order(), vec!["normal", "saving"]);
+        assert_eq!(p.next_in_cycle("normal"), "saving");
+        assert_eq!(p.next_in_cycle("saving"), "normal");
+
+        p.parse_key("LIGHT_DISPLAY", "dim").unwrap();
+        p.parse_key("DEEP_WIFI", "block").unwrap();
+        assert_eq!(p.cycle_order(), vec!["normal", "deep", "light"]);
+
+        p.parse_key("CYCLE", "normal, Light, deep").unwrap();
+        assert_eq!(p.next_in_cycle("normal"), "light");
+        assert_eq!(p.next_in_cycle("light"), "deep");
+        assert_eq!(p.next_in_cycle("deep"), "normal");
+        // the built-in saving mode isn't in this cycle: start over
+        assert_eq!(p.next_in_cycle("saving"), "normal");
+
+        p.parse_key("DEFAULT", "ultra").unwrap();
+        p.parse_key("CYCLE", "normal,deep,turbo").unwrap();
+        assert_eq!(p.unknown_names(), vec!["turbo", "ultra"]);
+    }
+}
@@ -0,0 +1,29 @@
+//! Reports of what leaving saving mode put back. Every component snapshots the
+//! state it is about to change when saving mode is entered and restores exactly
+//! that on exit, so changes made in between (Wi-Fi switched off by hand, new CPU
+//! limits) aren't clobbered with startup values.
+use std::fmt;
+
+/// One thing restored on leaving saving mode
+#[derive(Clone, Debug, PartialEq)]
+pub struct Restored {
+    /// The component, e.g. `policy0` or `WiFi rfkill1`
+    pub component: String,
+    /// What it is back at, e.g. `scaling 600000-1800000 kHz`
+    pub detail: String,
+}
+
+impl Restored {
+    pub fn new(component: impl Into<String>, detail: impl Into<String>) -> Self {
+        Restored {
+            component: component.into(),
+            detail: detail.into(),
+        }
+    }
+}
+
+impl fmt::Display for Restored {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.component, self.detail)
+    }
+}
@@ -0,0 +1,325 @@
+//! Real suspend (`mem` or `freeze` via `/sys/power/state`), as opposed to the
+//! display-off saving mode. The power key, and optionally the internal keyboard,
+//! are made wakeup sources for the duration of the sleep.
+//!
+//! All sysfs access goes through a configurable root so it can be tested against a
+//! fake tree.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use log::{debug, info, warn};
+
+use crate::error::Error;
+
+pub const SYS_ROOT: &str = "/sys";
+
+/// What gets written to `/sys/power/state`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SleepState {
+    /// Suspend to RAM; the variant comes from `/sys/power/mem_sleep`
+    #[default]
+    Mem,
+    /// Suspend-to-idle: freeze userspace, nothing powered down
+    Freeze,
+}
+
+impl FromStr for SleepState {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "mem" => Ok(SleepState::Mem),
+            "freeze" => Ok(SleepState::Freeze),
+            other => Err(Error::InvalidValue(format!(
+                "unknown sleep state '{}' (expected mem or freeze)",
+                other
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for SleepState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SleepState::Mem => write!(f, "mem"),
+            SleepState::Freeze => write!(f, "freeze"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SuspendConfig {
+    pub state: SleepState,
+    /// `mem_sleep` variant to select for `mem` (`s2idle`, `shallow`, `deep`);
+    /// `None` keeps the kernel's choice
+    pub mem_sleep: Option<String>,
+    /// Also let the internal keyboard wake the system
+    pub wake_keyboard: bool,
+}
+
+/// A `power/wakeup` file and the value it had before we touched it
+#[derive(Clone, Debug, PartialEq)]
+pub struct WakeupSource {
+    pub path: PathBuf,
+    pub previous: String,
+}
+
+pub struct Suspender {
+    pub config: SuspendConfig,
+    sys: PathBuf,
+}
+
+impl Suspender {
+    pub fn new(config: SuspendConfig) -> Self {
+        Self::with_sys_root(config, PathBuf::from(SYS_ROOT))
+    }
+
+    pub fn with_sys_root(config: SuspendConfig, sys: PathBuf) -> Self {
+        Suspender { config, sys }
+    }
+
+    /// Check that the kernel supports the configured state (and `mem_sleep` variant)
+    pub fn check(&self) -> Result<(), Error> {
+        let power = self.sys.join("power");
+        let states = fs::read_to_string(power.join("state"))?;
+        let state = self.config.state.to_string();
+        if !states.split_whitespace().any(|s| s == state) {
+            return Err(Error::InvalidValue(format!(
+                "sleep state '{}' not supported (available: {})",
+                state,
+                states.trim()
+            )));
+        }
+        if self.config.state != SleepState::Mem {
+            return Ok(());
+        }
+        let variants = match fs::read_to_string(power.join("mem_sleep")) {
+            Ok(v) => v,
+            // older kernels without the file only know one kind of mem
+            Err(_) if self.config.mem_sleep.is_none() => return Ok(()),
+            Err(e) => return Err(Error::from(e)),
+        };
+        debug!("mem_sleep variants: {}", variants.trim());
+        if let Some(wanted) = &self.config.mem_sleep
+            && !variants
+                .split_whitespace()
+                .any(|v| v.trim_matches(|c| c == '[' || c == ']') == wanted)
+        {
+            return Err(Error::InvalidValue(format!(
+                "mem_sleep '{}' not supported (available: {})",
+                wanted,
+                variants.trim()
+            )));
+        }
+        Ok(())
+    }
+
+    /// The `power/wakeup` file responsible for an evdev node: the first one found on
+    /// the input device or one of its parents (e.g. the AXP PEK platform device)
+    pub fn wakeup_path(&self, devnode: &Path) -> Option<PathBuf> {
+        let name = devnode.file_name()?;
+        let mut dir = fs::canonicalize(self.sys.join("class/input").join(name)).ok()?;
+        let devices = fs::canonicalize(self.sys.join("devices")).ok()?;
+        while dir.starts_with(&devices) && dir != devices {
+            let wakeup = dir.join("power/wakeup");
+            if wakeup.is_file() {
+                return Some(wakeup);
+            }
+            dir.pop();
+        }
+        None
+    }
+
+    /// Enable wakeup for each device that has a `power/wakeup` file, recording the
+    /// old values for [`Suspender::restore_wakeups`]
+    pub fn enable_wakeups(&self, devnodes: &[PathBuf], dry_run: bool) -> Vec<WakeupSource> {
+        let mut sources: Vec<WakeupSource> = Vec::new();
+        for node in devnodes {
+            let Some(path) = self.wakeup_path(node) else {
+                warn!("{} can't wake the system (no power/wakeup)", node.display());
+                continue;
+            };
+            // a keyboard and trackball may share one USB device
+            if sources.iter().any(|s| s.path == path) {
+                continue;
+            }
+            let previous = match fs::read_to_string(&path) {
+                Ok(v) => v.trim().to_string(),
+                Err(e) => {
+                    warn!("Failed to read {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            debug!("Wakeup via {} ({})", node.display(), path.display());
+            if !dry_run
+                && previous != "enabled"
+                && let Err(e) = fs::write(&path, "enabled")
+            {
+                warn!("Failed to enable wakeup on {}: {}", path.display(), e);
+                continue;
+            }
+            sources.push(WakeupSource { path, previous });
+        }
+        sources
+    }
+
+    pub fn restore_wakeups(&self, sources: &[WakeupSource], dry_run: bool) {
+        for s in sources.iter().filter(|s| s.previous != "enabled") {
+            if dry_run {
+                debug!(
+                    "DRY-RUN: would write {} to {}",
+                    s.previous,
+                    s.path.display()
+                );
+            } else if let Err(e) = fs::write(&s.path, &s.previous) {
+                warn!("Failed to restore {}: {}", s.path.display(), e);
+            }
+        }
+    }
+
+    /// Suspend with `wake_devices` as wakeup sources. Returns once the system has
+    /// resumed (the write to `/sys/power/state` blocks until then).
+    pub fn suspend(&self, wake_devices: &[PathBuf], dry_run: bool) -> Result<(), Error> {
+        self.check()?;
+        let power = self.sys.join("power");
+        if let Some(variant) = &self.config.mem_sleep
+            && self.config.state == SleepState::Mem
+            && !dry_run
+        {
+            fs::write(power.join("mem_sleep"), variant)?;
+        }
+        let sources = self.enable_wakeups(wake_devices, dry_run);
+        let result = if dry_run {
+            debug!(
+                "DRY-RUN: would write {} to /sys/power/state",
+                self.config.state
+            );
+            Ok(())
+        } else {
+            info!("Suspending ({})", self.config.state);
+            fs::write(power.join("state"), self.config.state.to_string()).map_err(Error::from)
+        };
+        self.restore_wakeups(&sources, dry_run);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::os::unix::fs::symlink;
+
+    fn fake_sys() -> TempDir {
+        let root = TempDir::new("suspend");
+        fs::create_dir_all(root.join("power")).unwrap();
+        fs::write(root.join("power/state"), "freeze mem\n").unwrap();
+        fs::write(root.join("power/mem_sleep"), "s2idle [deep]\n").unwrap();
+
+        // power key: wakeup lives on the platform device above the input device
+        let pek = root.join("devices/platform/axp221-pek");
+        fs::create_dir_all(pek.join("power")).unwrap();
+        fs::write(pek.join("power/wakeup"), "disabled\n").unwrap();
+        fs::create_dir_all(pek.join("input/input0/event0")).unwrap();
+        // a device without any wakeup support
+        fs::create_dir_all(root.join("devices/virtual/input/input9/event9")).unwrap();
+
+        fs::create_dir_all(root.join("class/input")).unwrap();
+        symlink(
+            "../../devices/platform/axp221-pek/input/input0/event0",
+            root.join("class/input/event0"),
+        )
+        .unwrap();
+        symlink(
+            "../../devices/virtual/input/input9/event9",
+            root.join("class/input/event9"),
+        )
+        .unwrap();
+        root
+    }
+
+    #[test]
+    fn test_check_states() {
+        let sys = fake_sys();
+        let mut config = SuspendConfig::default();
+        assert!(
+            Suspender::with_sys_root(config.clone(), sys.to_path_buf())
+                .check()
+                .is_ok()
+        );
+        config.mem_sleep = Some("s2idle".to_string());
+        assert!(
+            Suspender::with_sys_root(config.clone(), sys.to_path_buf())
+                .check()
+                .is_ok()
+        );
+        config.mem_sleep = Some("shallow".to_string());
+        assert!(
+            Suspender::with_sys_root(config.clone(), sys.to_path_buf())
+                .check()
+                .is_err()
+        );
+
+        fs::write(sys.join("power/state"), "freeze\n").unwrap();
+        config.mem_sleep = None;
+        assert!(
+            Suspender::with_sys_root(config.clone(), sys.to_path_buf())
+                .check()
+                .is_err()
+        );
+        config.state = SleepState::Freeze;
+        assert!(
+            Suspender::with_sys_root(config, sys.to_path_buf())
+                .check()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_wakeup_sources() {
+        let sys = fake_sys();
+        let s = Suspender::with_sys_root(SuspendConfig::default(), sys.to_path_buf());
+        let wakeup = sys.join("devices/platform/axp221-pek/power/wakeup");
+        assert_eq!(
+            s.wakeup_path(Path::new("/dev/input/event0")).unwrap(),
+            fs::canonicalize(&wakeup).unwrap()
+        );
+        assert_eq!(s.wakeup_path(Path::new("/dev/input/event9")), None);
+
+        let nodes = [
+            PathBuf::from("/dev/input/event0"),
+            PathBuf::from("/dev/input/event9"),
+        ];
+        let sources = s.enable_wakeups(&nodes, false);
+        assert_eq!(sources.len(), 1);
+        assert_eq!(sources[0].previous, "disabled");
+        assert_eq!(fs::read_to_string(&wakeup).unwrap(), "enabled");
+        s.restore_wakeups(&sources, false);
+        assert_eq!(fs::read_to_string(&wakeup).unwrap(), "disabled");
+    }
+
+    #[test]
+    fn test_suspend_writes_state() {
+        let sys = fake_sys();
+        let config = SuspendConfig {
+            mem_sleep: Some("deep".to_string()),
+            ..Default::default()
+        };
+        let s = Suspender::with_sys_root(config, sys.to_path_buf());
+        s.suspend(&[PathBuf::from("/dev/input/event0")], false)
+            .unwrap();
+        assert_eq!(fs::read_to_string(sys.join("power/state")).unwrap(), "mem");
+        assert_eq!(
+            fs::read_to_string(sys.join("power/mem_sleep")).unwrap(),
+            "deep"
+        );
+        // wakeup is back to what it was once resumed
+        assert_eq!(
+            fs::read_to_string(sys.join("devices/platform/axp221-pek/power/wakeup")).unwrap(),
+            "disabled"
+        );
+    }
+}
@@ -0,0 +1,312 @@
+//! Thermal capping: watch `/sys/class/thermal/thermal_zone*/temp` and lower every
+//! cpufreq policy's `scaling_max_freq` while the hottest zone is above a trip
+//! point, in any power mode. The cap is lifted once the temperature has dropped to
+//! a lower release point, so it doesn't flap around the trip.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::{info, warn};
+
+use crate::error::Error;
+use crate::hardware::cpu::FreqCap;
+use crate::hardware::cpu_freq::{FreqLimits, FreqSpec, Snap};
+
+pub const THERMAL_PATH: &str = "/sys/class/thermal";
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Release point below the trip point unless configured
+pub const DEFAULT_HYSTERESIS_MILLIC: i32 = 10_000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThermalConfig {
+    /// Temperature that sets the cap, in millidegrees Celsius; `None` disables it
+    pub trip_millic: Option<i32>,
+    /// Temperature that lifts it again; defaults to 10 °C below the trip
+    pub release_millic: Option<i32>,
+    /// Highest frequency while capped
+    pub cap: FreqSpec,
+    /// Zone types (e.g. `cpu-thermal`) or directory names to read; all if empty
+    pub zones: Vec<String>,
+    pub poll_interval: Duration,
+}
+
+impl Default for ThermalConfig {
+    fn default() -> Self {
+        ThermalConfig {
+            trip_millic: None,
+            release_millic: None,
+            cap: FreqSpec::Percent(60),
+            zones: Vec::new(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+}
+
+impl ThermalConfig {
+    pub fn enabled(&self) -> bool {
+        self.trip_millic.is_some()
+    }
+
+    /// The release point; one not below the trip point is replaced by the default
+    pub fn release(&self) -> Option<i32> {
+        let trip = self.trip_millic?;
+        Some(
+            self.release_millic
+                .filter(|r| *r < trip)
+                .unwrap_or(trip - DEFAULT_HYSTERESIS_MILLIC),
+        )
+    }
+}
+
+/// Parse a temperature in degrees Celsius (`75`, `72.5`) into millidegrees
+pub fn parse_celsius(s: &str) -> Result<i32, Error> {
+    let c: f32 = s
+        .trim()
+        .trim_end_matches(['C', 'c'])
+        .trim_end_matches('°')
+        .trim()
+        .parse()
+        .map_err(|_| Error::InvalidValue(format!("bad temperature '{}'", s.trim())))?;
+    if !(0.0..=150.0).contains(&c) {
+        return Err(Error::InvalidValue(format!(
+            "temperature {} °C out of range",
+            c
+        )));
+    }
+    Ok((c * 1000.0).round() as i32)
+}
+
+/// Thermal zone directories below `dir`, all of them or those whose `type` or
+/// directory name is in `names`
+pub fn find_zones(dir: &Path, names: &[String]) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut zones: Vec<PathBuf> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with("thermal_zone"))
+        })
+        .filter(|p| {
+            if names.is_empty() {
+                return true;
+            }
+            let kind = fs::read_to_string(p.join("type")).unwrap_or_default();
+            let dir_name = p.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            names.iter().any(|n| n == kind.trim() || n == dir_name)
+        })
+        .collect();
+    zones.sort();
+    zones
+}
+
+/// A change of the thermal cap
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ThermalEvent {
+    /// Reached the trip point at this temperature (millidegrees)
+    Capped(i32),
+    /// Cooled down to the release point
+    Released(i32),
+}
+
+/// Applies [`ThermalConfig`] to temperature readings and the cpufreq policies
+pub struct ThermalMonitor {
+    pub config: ThermalConfig,
+    pub zones: Vec<PathBuf>,
+    /// Policy directories and their cap in kHz
+    pub targets: Vec<(PathBuf, u64)>,
+    cap: FreqCap,
+    capped: bool,
+}
+
+impl ThermalMonitor {
+    pub fn new(config: ThermalConfig, policies: &[PathBuf], cap: FreqCap) -> Self {
+        Self::with_thermal_path(config, Path::new(THERMAL_PATH), policies, cap)
+    }
+
+    /// Read the zones below `dir`. The cap is resolved against each policy's
+    /// limits; a policy it can't be resolved for is left alone.
+    pub fn with_thermal_path(
+        config: ThermalConfig,
+        dir: &Path,
+        policies: &[PathBuf],
+        cap: FreqCap,
+    ) -> Self {
+        let zones = find_zones(dir, &config.zones);
+        if zones.is_empty() {
+            warn!("No thermal zones found in {}", dir.display());
+        }
+        let targets = policies
+            .iter()
+            .filter_map(|p| {
+                let what = format!("{}: thermal cap", p.display());
+                match FreqLimits::read(p).resolve(config.cap, &what, Snap::Down) {
+                    Ok(khz) => Some((p.clone(), khz)),
+                    Err(e) => {
+                        warn!("{}: no thermal cap: {}", p.display(), e);
+                        None
+                    }
+                }
+            })
+            .collect();
+        ThermalMonitor {
+            config,
+            zones,
+            targets,
+            cap,
+            capped: false,
+        }
+    }
+
+    pub fn capped(&self) -> bool {
+        self.capped
+    }
+
+    /// The hottest zone's temperature in millidegrees, if any could be read
+    pub fn read_temp(&self) -> Option<i32> {
+        self.zones
+            .iter()
+            .filter_map(|z| fs::read_to_string(z.join("temp")).ok()?.trim().parse().ok())
+            .max()
+    }
+
+    /// Feed a reading; reports when the cap should be set or lifted
+    pub fn update(&mut self, temp: i32) -> Option<ThermalEvent> {
+        let (trip, release) = (self.config.trip_millic?, self.config.release()?);
+        if !self.capped && temp >= trip {
+            self.capped = true;
+            Some(ThermalEvent::Capped(temp))
+        } else if self.capped && temp <= release {
+            self.capped = false;
+            Some(ThermalEvent::Released(temp))
+        } else {
+            None
+        }
+    }
+
+    /// Read the zones and set or lift the cap if needed
+    pub fn poll(&mut self, dry_run: bool) -> Option<ThermalEvent> {
+        let temp = self.read_temp()?;
+        let event = self.update(temp)?;
+        match event {
+            ThermalEvent::Capped(t) => info!(
+                "Thermal: {:.1} °C, capping CPU frequency",
+                t as f32 / 1000.0
+            ),
+            ThermalEvent::Released(t) => info!(
+                "Thermal: {:.1} °C, lifting CPU frequency cap",
+                t as f32 / 1000.0
+            ),
+        }
+        for (policy, khz) in &self.targets {
+            self.cap.set(policy, self.capped.then_some(*khz), dry_run);
+        }
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::cpu::CpuFreqConfig;
+    use crate::test_util::TempDir;
+
+    fn fake_sys() -> TempDir {
+        let root = TempDir::new("thermal");
+        for (zone, kind, temp) in [(0, "cpu-thermal", "50000"), (1, "battery", "30000")] {
+            let dir = root.join(format!("thermal/thermal_zone{}", zone));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("type"), format!("{}\n", kind)).unwrap();
+            fs::write(dir.join("temp"), format!("{}\n", temp)).unwrap();
+        }
+        let policy = root.join("policy0");
+        fs::create_dir_all(&policy).unwrap();
+        fs::write(policy.join("cpuinfo_min_freq"), "600000\n").unwrap();
+        fs::write(policy.join("cpuinfo_max_freq"), "1800000\n").unwrap();
+        fs::write(policy.join("scaling_min_freq"), "600000\n").unwrap();
+        fs::write(policy.join("scaling_max_freq"), "1800000\n").unwrap();
+        root
+    }
+
+    fn config() -> ThermalConfig {
+        ThermalConfig {
+            trip_millic: Some(70_000),
+            release_millic: Some(60_000),
+            cap: FreqSpec::Khz(1_000_000),
+            zones: vec!["cpu-thermal".to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_celsius() {
+        assert_eq!(parse_celsius("75").unwrap(), 75_000);
+        assert_eq!(parse_celsius(" 72.5 °C").unwrap(), 72_500);
+        assert!(parse_celsius("hot").is_err());
+        assert!(parse_celsius("500").is_err());
+    }
+
+    #[test]
+    fn test_hysteresis() {
+        let sys = fake_sys();
+        let mut m = ThermalMonitor::with_thermal_path(
+            config(),
+            &sys.join("thermal"),
+            &[],
+            FreqCap::default(),
+        );
+        assert_eq!(m.zones.len(), 1);
+        assert_eq!(m.read_temp(), Some(50_000));
+        assert_eq!(m.update(69_000), None);
+        assert_eq!(m.update(70_000), Some(ThermalEvent::Capped(70_000)));
+        assert_eq!(m.update(75_000), None);
+        // between release and trip: stays capped
+        assert_eq!(m.update(65_000), None);
+        assert!(m.capped());
+        assert_eq!(m.update(60_000), Some(ThermalEvent::Released(60_000)));
+        assert_eq!(m.update(65_000), None);
+    }
+
+    #[test]
+    fn test_cap_survives_leaving_saving_mode() {
+        let sys = fake_sys();
+        let policy = sys.join("policy0");
+        let zone_temp = sys.join("thermal/thermal_zone0/temp");
+        let max = || {
+            fs::read_to_string(policy.join("scaling_max_freq"))
+                .unwrap()
+                .trim()
+                .to_string()
+        };
+        let cap = FreqCap::default();
+        let cpu = CpuFreqConfig::with_policy_path(policy.clone(), Some("600,1500".to_string()))
+            .with_freq_cap(cap.clone());
+        let mut m = ThermalMonitor::with_thermal_path(
+            config(),
+            &sys.join("thermal"),
+            std::slice::from_ref(&policy),
+            cap,
+        );
+
+        // capped in normal mode
+        fs::write(&zone_temp, "80000\n").unwrap();
+        assert_eq!(m.poll(false), Some(ThermalEvent::Capped(80_000)));
+        assert_eq!(max(), "1000000");
+        // saving mode can't raise it, leaving saving mode doesn't undo it
+        cpu.apply_saving_mode(false);
+        assert_eq!(max(), "1000000");
+        cpu.apply_normal_mode(false);
+        assert_eq!(max(), "1000000");
+        // released: back to what the current mode wants
+        cpu.apply_saving_mode(false);
+        fs::write(&zone_temp, "55000\n").unwrap();
+        assert_eq!(m.poll(false), Some(ThermalEvent::Released(55_000)));
+        assert_eq!(max(), "1500000");
+        cpu.apply_normal_mode(false);
+        assert_eq!(max(), "1800000");
+    }
+}
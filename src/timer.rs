@@ -0,0 +1,89 @@
+//! One-shot deadline timer backed by a timerfd, so deadlines can be waited on in
+//! the same epoll set as the input devices.
+
+use std::os::fd::{AsFd, BorrowedFd};
+use std::time::{Duration, Instant};
+
+use nix::errno::Errno;
+use nix::sys::time::TimeSpec;
+use nix::sys::timerfd::{ClockId, Expiration, TimerFd, TimerFlags, TimerSetTimeFlags};
+
+use crate::error::Error;
+
+pub struct DeadlineTimer {
+    fd: TimerFd,
+}
+
+impl DeadlineTimer {
+    pub fn new() -> Result<Self, Error> {
+        let fd = TimerFd::new(
+            ClockId::CLOCK_MONOTONIC,
+            TimerFlags::TFD_NONBLOCK | TimerFlags::TFD_CLOEXEC,
+        )
+        .map_err(|e| Error::Io(format!("timerfd_create failed: {}", e)))?;
+        Ok(DeadlineTimer { fd })
+    }
+
+    /// Arm the timer to fire at `deadline`, or disarm it with `None`. A deadline in
+    /// the past fires right away.
+    pub fn arm(&self, deadline: Option<Instant>) -> Result<(), Error> {
+        let res = match deadline {
+            Some(d) => {
+                // a zero expiration would disarm the timer, so fire after at least 1ns
+                let delay = d
+                    .saturating_duration_since(Instant::now())
+                    .max(Duration::from_nanos(1));
+                self.fd.set(
+                    Expiration::OneShot(TimeSpec::from_duration(delay)),
+                    TimerSetTimeFlags::empty(),
+                )
+            }
+            None => self.fd.unset(),
+        };
+        res.map_err(|e| Error::Io(format!("timerfd_settime failed: {}", e)))
+    }
+
+    /// Consume the expiration so the fd stops polling readable
+    pub fn acknowledge(&self) {
+        match self.fd.wait() {
+            Ok(()) | Err(Errno::EAGAIN) => {}
+            Err(e) => log::warn!("timerfd read failed: {}", e),
+        }
+    }
+}
+
+impl AsFd for DeadlineTimer {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+
+    fn readable(t: &DeadlineTimer, timeout_ms: u16) -> bool {
+        let mut fds = [PollFd::new(t.as_fd(), PollFlags::POLLIN)];
+        poll(&mut fds, PollTimeout::from(timeout_ms)).unwrap() == 1
+    }
+
+    #[test]
+    fn test_timer_fires_and_disarms() {
+        let t = DeadlineTimer::new().unwrap();
+        assert!(!readable(&t, 0));
+
+        t.arm(Some(Instant::now() + Duration::from_millis(5)))
+            .unwrap();
+        assert!(readable(&t, 1000));
+        t.acknowledge();
+        assert!(!readable(&t, 0));
+
+        // past deadline fires immediately; disarming before it's read clears it
+        t.arm(Some(Instant::now() - Duration::from_secs(1)))
+            .unwrap();
+        assert!(readable(&t, 1000));
+        t.arm(None).unwrap();
+        assert!(!readable(&t, 20));
+    }
+}
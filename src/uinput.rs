@@ -0,0 +1,234 @@
+//! uinput virtual keyboard used to re-emit keys: remapped gestures (e.g. a hold
+//! becoming `KEY_POWER` for the desktop's logout dialog) and, optionally, the
+//! events of the grabbed power key device that the daemon doesn't consume.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use log::debug;
+
+use crate::error::Error;
+use crate::evdev::{Layout, SYN_REPORT};
+use crate::input::{EV_KEY, EV_SYN, InputEvent};
+
+pub const UINPUT_PATH: &str = "/dev/uinput";
+pub const VIRTUAL_DEVICE_NAME: &str = "uconsole-sleep virtual keys";
+const SYSFS_VIRTUAL_INPUT: &str = "/sys/devices/virtual/input";
+
+// uinput ioctls from linux/uinput.h
+const UI_DEV_CREATE: u64 = 0x5501;
+const UI_DEV_DESTROY: u64 = 0x5502;
+const UI_DEV_SETUP: u64 = 0x405c5503;
+const UI_SET_EVBIT: u64 = 0x40045564;
+const UI_SET_KEYBIT: u64 = 0x40045565;
+const BUS_VIRTUAL: u16 = 0x06;
+const UINPUT_MAX_NAME_SIZE: usize = 80;
+
+/// `_IOC(_IOC_READ, 'U', 44, len)`
+const fn ui_get_sysname(len: usize) -> u64 {
+    (2 << 30) | ((len as u64) << 16) | ((b'U' as u64) << 8) | 44
+}
+
+/// `struct uinput_setup`: input_id, name, ff_effects_max
+fn setup_bytes(name: &str) -> [u8; 92] {
+    let mut buf = [0u8; 92];
+    buf[0..2].copy_from_slice(&BUS_VIRTUAL.to_ne_bytes());
+    // vendor/product/version stay 0
+    let name = name.as_bytes();
+    let len = name.len().min(UINPUT_MAX_NAME_SIZE - 1);
+    buf[8..8 + len].copy_from_slice(&name[..len]);
+    buf
+}
+
+/// A created uinput device; destroyed again when dropped
+#[derive(Debug)]
+pub struct VirtualDevice {
+    file: File,
+    /// Key codes the device was created with; others are dropped by the kernel
+    pub keys: Vec<u16>,
+    layout: Layout,
+}
+
+impl VirtualDevice {
+    /// Create a virtual keyboard on `/dev/uinput` able to send `keys`
+    pub fn create(keys: &[u16]) -> Result<Self, Error> {
+        Self::create_at(Path::new(UINPUT_PATH), VIRTUAL_DEVICE_NAME, keys)
+    }
+
+    pub fn create_at(uinput: &Path, name: &str, keys: &[u16]) -> Result<Self, Error> {
+        let file = OpenOptions::new()
+            .write(true)
+            .open(uinput)
+            .map_err(|e| Error::Io(format!("failed to open {}: {}", uinput.display(), e)))?;
+        let mut keys = keys.to_vec();
+        keys.sort_unstable();
+        keys.dedup();
+
+        let dev = VirtualDevice {
+            file,
+            keys,
+            layout: Layout::NATIVE,
+        };
+        dev.ioctl_int(UI_SET_EVBIT, EV_SYN as libc::c_int)?;
+        dev.ioctl_int(UI_SET_EVBIT, EV_KEY as libc::c_int)?;
+        for k in &dev.keys {
+            dev.ioctl_int(UI_SET_KEYBIT, *k as libc::c_int)?;
+        }
+        let setup = setup_bytes(name);
+        dev.ioctl_ptr(UI_DEV_SETUP, setup.as_ptr() as *mut libc::c_void)?;
+        dev.ioctl_int(UI_DEV_CREATE, 0)?;
+        debug!("Created uinput device '{}' with keys {:?}", name, dev.keys);
+        Ok(dev)
+    }
+
+    fn ioctl_int(&self, request: u64, arg: libc::c_int) -> Result<(), Error> {
+        let ret = unsafe { libc::ioctl(self.file.as_raw_fd(), request as _, arg) };
+        if ret < 0 {
+            return Err(Error::Io(format!(
+                "uinput ioctl {:#x} failed: {}",
+                request,
+                std::io::Error::last_os_error()
+            )));
+        }
+        Ok(())
+    }
+
+    fn ioctl_ptr(&self, request: u64, arg: *mut libc::c_void) -> Result<(), Error> {
+        let ret = unsafe { libc::ioctl(self.file.as_raw_fd(), request as _, arg) };
+        if ret < 0 {
+            return Err(Error::Io(format!(
+                "uinput ioctl {:#x} failed: {}",
+                request,
+                std::io::Error::last_os_error()
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn supports_key(&self, code: u16) -> bool {
+        self.keys.binary_search(&code).is_ok()
+    }
+
+    /// Write one event; the kernel stamps the time
+    pub fn emit(&self, etype: u16, code: u16, value: i32) -> Result<(), Error> {
+        let ev = InputEvent {
+            sec: 0,
+            usec: 0,
+            etype,
+            code,
+            value,
+        };
+        (&self.file).write_all(&self.layout.encode(&ev))?;
+        Ok(())
+    }
+
+    pub fn sync(&self) -> Result<(), Error> {
+        self.emit(EV_SYN, SYN_REPORT, 0)
+    }
+
+    /// A full press and release of `code`, each in its own report
+    pub fn tap_key(&self, code: u16) -> Result<(), Error> {
+        self.emit(EV_KEY, code, 1)?;
+        self.sync()?;
+        self.emit(EV_KEY, code, 0)?;
+        self.sync()
+    }
+
+    /// Kernel name of the created device, e.g. `input7`
+    pub fn sysname(&self) -> Result<String, Error> {
+        let mut buf = [0u8; 64];
+        self.ioctl_ptr(
+            ui_get_sysname(buf.len()),
+            buf.as_mut_ptr() as *mut libc::c_void,
+        )?;
+        let end = buf.iter().position(|c| *c == 0).unwrap_or(buf.len());
+        Ok(String::from_utf8_lossy(&buf[..end]).into_owned())
+    }
+
+    /// The `/dev/input/eventN` node the kernel created for this device, if any yet
+    pub fn event_node(&self) -> Result<Option<PathBuf>, Error> {
+        let dir = Path::new(SYSFS_VIRTUAL_INPUT).join(self.sysname()?);
+        let node = fs::read_dir(&dir)?
+            .flatten()
+            .filter_map(|e| e.file_name().into_string().ok())
+            .find(|n| n.starts_with("event"))
+            .map(|n| Path::new("/dev/input").join(n));
+        Ok(node)
+    }
+}
+
+impl Drop for VirtualDevice {
+    fn drop(&mut self) {
+        let _ = self.ioctl_int(UI_DEV_DESTROY, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::InputDevice;
+    use std::thread::sleep;
+    use std::time::{Duration, Instant};
+
+    /// KEY_F24: nothing on a desktop reacts to it, unlike KEY_POWER
+    const UNUSED_KEY: u16 = 194;
+
+    #[test]
+    fn test_ioctl_encoding_matches_kernel_headers() {
+        assert_eq!(ui_get_sysname(64), 0x8040552c);
+        let setup = setup_bytes("x");
+        assert_eq!(&setup[0..2], &BUS_VIRTUAL.to_ne_bytes());
+        assert_eq!(setup[8], b'x');
+        assert_eq!(setup[9], 0);
+    }
+
+    #[test]
+    fn test_emitted_keys_read_back() {
+        let dev = match VirtualDevice::create(&[UNUSED_KEY]) {
+            Ok(d) => d,
+            Err(e) => {
+                eprintln!("skipping uinput read-back test: {}", e);
+                return;
+            }
+        };
+        assert!(dev.supports_key(UNUSED_KEY));
+        assert!(!dev.supports_key(30));
+
+        // the node shows up asynchronously
+        let mut node = None;
+        for _ in 0..50 {
+            node = dev.event_node().ok().flatten().filter(|p| p.exists());
+            if node.is_some() {
+                break;
+            }
+            sleep(Duration::from_millis(20));
+        }
+        let Some(node) = node else {
+            eprintln!("skipping uinput read-back test: no event node");
+            return;
+        };
+        let mut reader = match InputDevice::open(&node) {
+            Ok(r) => r,
+            Err(e) => {
+                eprintln!("skipping uinput read-back test: {}", e);
+                return;
+            }
+        };
+        assert_eq!(reader.info().name, VIRTUAL_DEVICE_NAME);
+
+        dev.tap_key(UNUSED_KEY).unwrap();
+        let mut keys = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while keys.len() < 2 && Instant::now() < deadline {
+            for ev in reader.read_events().unwrap() {
+                if ev.etype == EV_KEY {
+                    keys.push((ev.code, ev.value));
+                }
+            }
+            sleep(Duration::from_millis(5));
+        }
+        assert_eq!(keys, vec![(UNUSED_KEY, 1), (UNUSED_KEY, 0)]);
+    }
+}
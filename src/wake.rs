@@ -0,0 +1,205 @@
+//! Wake-up detection from the internal keyboard and trackball while in saving mode
+
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::error::Error;
+pub use crate::input::EV_REL;
+use crate::input::{EV_KEY, keycode_from_name};
+
+pub const REL_X: u16 = 0;
+pub const REL_Y: u16 = 1;
+
+/// Trackball motion only counts if it adds up within this window, so slow drift
+/// (e.g. the device shifting in a bag) never reaches the threshold
+pub const MOTION_WINDOW: Duration = Duration::from_secs(1);
+
+/// Sums trackball motion over [`MOTION_WINDOW`] and reports when it exceeds a
+/// threshold, so sensor jitter doesn't count as the user touching the device
+#[derive(Clone, Debug, Default)]
+pub struct MotionAccumulator {
+    pub threshold: u32,
+    motion: u32,
+    start: Option<Instant>,
+}
+
+impl MotionAccumulator {
+    pub fn new(threshold: u32) -> Self {
+        MotionAccumulator {
+            threshold,
+            ..Default::default()
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.motion = 0;
+        self.start = None;
+    }
+
+    /// Feed one REL_X/REL_Y delta; returns true (and starts over) once the motion
+    /// within the window exceeds the threshold
+    pub fn add(&mut self, value: i32, now: Instant) -> bool {
+        match self.start {
+            Some(start) if now.duration_since(start) <= MOTION_WINDOW => {}
+            _ => {
+                self.motion = 0;
+                self.start = Some(now);
+            }
+        }
+        self.motion = self.motion.saturating_add(value.unsigned_abs());
+        if self.motion > self.threshold {
+            self.reset();
+            return true;
+        }
+        false
+    }
+}
+
+/// Which key presses wake the device
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum WakeKeys {
+    #[default]
+    None,
+    Any,
+    Keys(Vec<u16>),
+}
+
+impl FromStr for WakeKeys {
+    type Err = Error;
+
+    /// Parse `none`, `any` or a comma-separated list of key names
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "none" => return Ok(WakeKeys::None),
+            "any" => return Ok(WakeKeys::Any),
+            _ => {}
+        }
+        let keys = s
+            .split(',')
+            .map(|k| {
+                keycode_from_name(k)
+                    .ok_or_else(|| Error::InvalidValue(format!("unknown key '{}'", k.trim())))
+            })
+            .collect::<Result<Vec<u16>, Error>>()?;
+        Ok(WakeKeys::Keys(keys))
+    }
+}
+
+/// Wake-on-input settings
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WakeConfig {
+    pub keys: WakeKeys,
+    /// Sum of |REL_X| + |REL_Y| within [`MOTION_WINDOW`] needed to wake; `None` disables
+    pub motion_threshold: Option<u32>,
+    /// Grab the devices while in saving mode so input doesn't reach the desktop
+    pub grab: bool,
+}
+
+impl WakeConfig {
+    pub fn enabled(&self) -> bool {
+        self.keys != WakeKeys::None || self.motion_threshold.is_some()
+    }
+}
+
+/// Decides whether input events from the internal keyboard/trackball should end
+/// saving mode. Time is injected so the motion window is deterministic in tests.
+#[derive(Clone, Debug)]
+pub struct WakeDetector {
+    pub config: WakeConfig,
+    motion: MotionAccumulator,
+}
+
+impl WakeDetector {
+    pub fn new(config: WakeConfig) -> Self {
+        let motion = MotionAccumulator::new(config.motion_threshold.unwrap_or(0));
+        WakeDetector { config, motion }
+    }
+
+    /// Forget accumulated motion, e.g. when entering saving mode
+    pub fn reset(&mut self) {
+        self.motion.reset();
+    }
+
+    /// Feed one event; returns true if it should wake the device
+    pub fn on_event(&mut self, etype: u16, code: u16, value: i32, now: Instant) -> bool {
+        match etype {
+            EV_KEY if value == 1 => match &self.config.keys {
+                WakeKeys::None => false,
+                WakeKeys::Any => true,
+                WakeKeys::Keys(keys) => keys.contains(&code),
+            },
+            EV_REL if code == REL_X || code == REL_Y => {
+                self.config.motion_threshold.is_some() && self.motion.add(value, now)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn test_parse_wake_keys() {
+        assert_eq!("none".parse::<WakeKeys>().unwrap(), WakeKeys::None);
+        assert_eq!("ANY".parse::<WakeKeys>().unwrap(), WakeKeys::Any);
+        assert_eq!(
+            "KEY_ENTER, space".parse::<WakeKeys>().unwrap(),
+            WakeKeys::Keys(vec![28, 57])
+        );
+        assert!("KEY_ENTER,KEY_WAT".parse::<WakeKeys>().is_err());
+    }
+
+    #[test]
+    fn test_any_key_wakes_on_press_only() {
+        let mut w = WakeDetector::new(WakeConfig {
+            keys: WakeKeys::Any,
+            ..Default::default()
+        });
+        let now = Instant::now();
+        assert!(!w.on_event(EV_KEY, 30, 0, now));
+        assert!(!w.on_event(EV_KEY, 30, 2, now));
+        assert!(w.on_event(EV_KEY, 30, 1, now));
+        // motion is ignored without a threshold
+        assert!(!w.on_event(EV_REL, REL_X, 500, now));
+    }
+
+    #[test]
+    fn test_configured_key_only() {
+        let mut w = WakeDetector::new(WakeConfig {
+            keys: WakeKeys::Keys(vec![28]),
+            ..Default::default()
+        });
+        let now = Instant::now();
+        assert!(!w.on_event(EV_KEY, 30, 1, now));
+        assert!(w.on_event(EV_KEY, 28, 1, now));
+    }
+
+    #[test]
+    fn test_motion_threshold_within_window() {
+        let cfg = WakeConfig {
+            motion_threshold: Some(10),
+            ..Default::default()
+        };
+        assert!(cfg.enabled());
+        let mut w = WakeDetector::new(cfg);
+        let t0 = Instant::now();
+        // jitter spread over more than the window never adds up
+        assert!(!w.on_event(EV_REL, REL_X, 4, t0));
+        assert!(!w.on_event(EV_REL, REL_Y, -4, t0 + ms(500)));
+        assert!(!w.on_event(EV_REL, REL_X, 4, t0 + ms(1500)));
+        // a deliberate roll within the window does
+        assert!(!w.on_event(EV_REL, REL_X, -5, t0 + ms(1600)));
+        assert!(w.on_event(EV_REL, REL_Y, 3, t0 + ms(1700)));
+        // the accumulator starts over afterwards; wheel events don't count
+        assert!(!w.on_event(EV_REL, REL_X, 3, t0 + ms(1800)));
+        assert!(!w.on_event(EV_REL, 8, 50, t0 + ms(1800)));
+        // keys don't wake when only motion is configured
+        assert!(!w.on_event(EV_KEY, 28, 1, t0 + ms(1800)));
+    }
+}